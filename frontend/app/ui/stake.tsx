@@ -77,7 +77,7 @@ export default function StakeDemo({
 
   const [isLoading, setIsLoading] = useState(false);
 
-  const { address } = useAccount();
+  const { address, chainId } = useAccount();
   const walletConnected = Boolean(address);
 
   const fetchBalances = useCallback(async () => {
@@ -284,7 +284,8 @@ export default function StakeDemo({
 
         const depositResult: any = await actor.deposit_icrc1(
           amountNat,
-          address ?? ""
+          address ?? "",
+          chainId ? [BigInt(chainId)] : []
         );
 
         if (!depositResult || typeof depositResult.Ok !== "string") {
@@ -376,7 +377,8 @@ export default function StakeDemo({
           expected_eth_from,
           expected_amount_18dec,
           withdraw_amount_8dec,
-          expected_contract
+          expected_contract,
+          chainId ? [BigInt(chainId)] : []
         );
 
         if (!unlockResult || "Err" in unlockResult) {
@@ -0,0 +1,315 @@
+use crate::chains::get_chain;
+use crate::roles::{ensure_permission, Permission};
+use crate::{
+    allow_list, decode_h160, eth_call, normalize_address, submit_transaction, CallResult,
+    SubmittedTransaction,
+};
+use candid::CandidType;
+use ethers_core::abi::{AbiParser, Function, ParamType, Token};
+use ethers_core::types::{I256, U256};
+use ethers_core::utils::hex;
+use evm_rpc_canister_types::BlockTag;
+use ic_cdk::update;
+use serde::{Deserialize, Serialize};
+
+/// A Solidity value in candid form. Integers are decimal strings and byte
+/// strings are `0x`-prefixed hex, so 256-bit values survive the trip.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AbiValue {
+    Address(String),
+    Uint(String),
+    Int(String),
+    Bool(bool),
+    Bytes(String),
+    FixedBytes(String),
+    String(String),
+    Array(Vec<AbiValue>),
+    Tuple(Vec<AbiValue>),
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ContractCall {
+    pub contract: String,
+    /// Either a signature such as `mint(address,uint256)` or a JSON ABI
+    /// function fragment.
+    pub function: String,
+    pub args: Vec<AbiValue>,
+    /// Wei sent along with the call; defaults to zero.
+    pub value: Option<String>,
+    /// Fixed gas limit; estimated when unset.
+    pub gas: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ContractRead {
+    pub contract: String,
+    /// Signature including return types, e.g.
+    /// `balanceOf(address) returns (uint256)`, or a JSON ABI fragment.
+    pub function: String,
+    pub args: Vec<AbiValue>,
+    /// Block to read at; defaults to the latest block.
+    pub block: Option<BlockTag>,
+}
+
+pub fn parse_function(function: &str) -> CallResult<Function> {
+    let function = function.trim();
+    if function.starts_with('{') {
+        serde_json::from_str(function).map_err(|e| format!("Invalid ABI fragment: {}", e))
+    } else {
+        AbiParser::default()
+            .parse_function(function)
+            .map_err(|e| format!("Invalid function signature '{}': {}", function, e))
+    }
+}
+
+fn decode_hex(value: &str, context: &str) -> CallResult<Vec<u8>> {
+    hex::decode(value.trim_start_matches("0x")).map_err(|e| format!("Invalid {}: {}", context, e))
+}
+
+fn to_token(value: &AbiValue, kind: &ParamType) -> CallResult<Token> {
+    let token = match (value, kind) {
+        (AbiValue::Address(address), ParamType::Address) => {
+            Token::Address(decode_h160(address, "address argument")?)
+        }
+        (AbiValue::Uint(number), ParamType::Uint(_)) => Token::Uint(
+            U256::from_dec_str(number).map_err(|e| format!("Invalid uint {}: {:?}", number, e))?,
+        ),
+        (AbiValue::Int(number), ParamType::Int(_)) => Token::Int(
+            I256::from_dec_str(number)
+                .map_err(|e| format!("Invalid int {}: {:?}", number, e))?
+                .into_raw(),
+        ),
+        (AbiValue::Bool(flag), ParamType::Bool) => Token::Bool(*flag),
+        (AbiValue::Bytes(bytes), ParamType::Bytes) => {
+            Token::Bytes(decode_hex(bytes, "bytes argument")?)
+        }
+        (AbiValue::FixedBytes(bytes), ParamType::FixedBytes(size)) => {
+            let bytes = decode_hex(bytes, "fixed bytes argument")?;
+            if bytes.len() != *size {
+                return Err(format!("Expected bytes{}, got {} bytes", size, bytes.len()));
+            }
+            Token::FixedBytes(bytes)
+        }
+        (AbiValue::String(text), ParamType::String) => Token::String(text.clone()),
+        (AbiValue::Array(items), ParamType::Array(item_kind)) => Token::Array(
+            items
+                .iter()
+                .map(|item| to_token(item, item_kind))
+                .collect::<CallResult<_>>()?,
+        ),
+        (AbiValue::Array(items), ParamType::FixedArray(item_kind, size)) => {
+            if items.len() != *size {
+                return Err(format!(
+                    "Expected {} array items, got {}",
+                    size,
+                    items.len()
+                ));
+            }
+            Token::FixedArray(
+                items
+                    .iter()
+                    .map(|item| to_token(item, item_kind))
+                    .collect::<CallResult<_>>()?,
+            )
+        }
+        (AbiValue::Tuple(items), ParamType::Tuple(kinds)) => Token::Tuple(to_tokens(items, kinds)?),
+        (value, kind) => return Err(format!("Cannot encode {:?} as {}", value, kind)),
+    };
+    Ok(token)
+}
+
+fn to_tokens(values: &[AbiValue], kinds: &[ParamType]) -> CallResult<Vec<Token>> {
+    if values.len() != kinds.len() {
+        return Err(format!(
+            "Expected {} arguments, got {}",
+            kinds.len(),
+            values.len()
+        ));
+    }
+    values
+        .iter()
+        .zip(kinds)
+        .map(|(value, kind)| to_token(value, kind))
+        .collect()
+}
+
+/// Type-checks `args` against `function` and returns the calldata.
+pub fn encode_call(function: &Function, args: &[AbiValue]) -> CallResult<Vec<u8>> {
+    let kinds: Vec<ParamType> = function
+        .inputs
+        .iter()
+        .map(|param| param.kind.clone())
+        .collect();
+    let tokens = to_tokens(args, &kinds)?;
+    function
+        .encode_input(&tokens)
+        .map_err(|e| format!("Failed to encode data: {}", e))
+}
+
+pub fn from_token(token: Token) -> AbiValue {
+    match token {
+        Token::Address(address) => AbiValue::Address(format!("{:?}", address)),
+        Token::Uint(number) => AbiValue::Uint(number.to_string()),
+        Token::Int(number) => AbiValue::Int(I256::from_raw(number).to_string()),
+        Token::Bool(flag) => AbiValue::Bool(flag),
+        Token::Bytes(bytes) => AbiValue::Bytes(format!("0x{}", hex::encode(bytes))),
+        Token::FixedBytes(bytes) => AbiValue::FixedBytes(format!("0x{}", hex::encode(bytes))),
+        Token::String(text) => AbiValue::String(text),
+        Token::Array(items) | Token::FixedArray(items) => {
+            AbiValue::Array(items.into_iter().map(from_token).collect())
+        }
+        Token::Tuple(items) => AbiValue::Tuple(items.into_iter().map(from_token).collect()),
+    }
+}
+
+/// Runs a read-only call and decodes its return values against the
+/// function's declared outputs. Callers check permissions.
+pub async fn read_contract_values(chain_id: u64, read: ContractRead) -> CallResult<Vec<AbiValue>> {
+    let chain = get_chain(chain_id)?;
+    let contract = normalize_address(&read.contract)?;
+    let function = parse_function(&read.function)?;
+    let data = encode_call(&function, &read.args)?;
+
+    let output = eth_call(&chain, &contract, &data, read.block).await?;
+    let tokens = function
+        .decode_output(&output)
+        .map_err(|e| format!("Failed to decode {} output: {}", function.name, e))?;
+    Ok(tokens.into_iter().map(from_token).collect())
+}
+
+/// Encodes `call` and sends it through the shared fee, nonce and signing
+/// pipeline. Callers check permissions.
+pub async fn submit_contract_call(
+    chain_id: u64,
+    call: ContractCall,
+) -> CallResult<SubmittedTransaction> {
+    let chain = get_chain(chain_id)?;
+    allow_list::require_allowed_contract(chain_id, &call.contract)?;
+
+    let contract = decode_h160(&call.contract, "contract")?;
+    let function = parse_function(&call.function)?;
+    let data = encode_call(&function, &call.args)?;
+    let value = match call.value {
+        Some(value) => U256::from_dec_str(&value).map_err(|e| format!("Invalid value: {:?}", e))?,
+        None => U256::zero(),
+    };
+
+    submit_transaction(&chain, contract, value, data, call.gas.map(U256::from)).await
+}
+
+#[update]
+pub async fn call_contract(chain_id: u64, call: ContractCall) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::CallContract)?;
+    submit_contract_call(chain_id, call).await
+}
+
+/// Read-only `eth_call`; nothing is signed, so no allow-listing is needed.
+#[update]
+pub async fn read_contract(chain_id: u64, read: ContractRead) -> CallResult<Vec<AbiValue>> {
+    ensure_permission(Permission::ReadContract)?;
+    read_contract_values(chain_id, read).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::utils::keccak256;
+
+    fn word(value: u64) -> Vec<u8> {
+        let mut word = vec![0u8; 24];
+        word.extend_from_slice(&value.to_be_bytes());
+        word
+    }
+
+    #[test]
+    fn mint_encodes_recipient_and_amount() {
+        let function = parse_function("mint(address,uint256)").unwrap();
+        let recipient = format!("0x{}", "11".repeat(20));
+        let data = encode_call(
+            &function,
+            &[
+                AbiValue::Address(recipient),
+                AbiValue::Uint("1000".to_string()),
+            ],
+        )
+        .unwrap();
+
+        let mut expected = hex::decode("40c10f19").unwrap();
+        expected.extend_from_slice(&[0u8; 12]);
+        expected.extend_from_slice(&[0x11; 20]);
+        expected.extend(word(1000));
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn burn_to_icp_encodes_principal_subaccount_and_amount() {
+        let function = parse_function("burnToICP(bytes,bytes32,uint256)").unwrap();
+        let subaccount = format!("0x{}", "00".repeat(31) + "07");
+        let data = encode_call(
+            &function,
+            &[
+                AbiValue::Bytes("0x0a0b".to_string()),
+                AbiValue::FixedBytes(subaccount),
+                AbiValue::Uint("5".to_string()),
+            ],
+        )
+        .unwrap();
+
+        let mut expected = keccak256("burnToICP(bytes,bytes32,uint256)")[..4].to_vec();
+        expected.extend(word(0x60));
+        expected.extend(word(7));
+        expected.extend(word(5));
+        expected.extend(word(2));
+        let mut principal = vec![0x0a, 0x0b];
+        principal.resize(32, 0);
+        expected.extend(principal);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn json_fragments_encode_like_signatures() {
+        let fragment = r#"{"type":"function","name":"mint","inputs":[{"name":"to","type":"address"},{"name":"amount","type":"uint256"}],"outputs":[],"stateMutability":"nonpayable"}"#;
+        let args = [
+            AbiValue::Address(format!("0x{}", "22".repeat(20))),
+            AbiValue::Uint("1".to_string()),
+        ];
+
+        assert_eq!(
+            encode_call(&parse_function(fragment).unwrap(), &args),
+            encode_call(&parse_function("mint(address,uint256)").unwrap(), &args)
+        );
+    }
+
+    #[test]
+    fn arguments_are_type_checked() {
+        let function = parse_function("burnToICP(bytes,bytes32,uint256)").unwrap();
+        let short_subaccount = [
+            AbiValue::Bytes("0x01".to_string()),
+            AbiValue::FixedBytes("0x01".to_string()),
+            AbiValue::Uint("1".to_string()),
+        ];
+        assert!(encode_call(&function, &short_subaccount).is_err());
+        assert!(encode_call(&function, &[AbiValue::Uint("1".to_string())]).is_err());
+
+        let mint = parse_function("mint(address,uint256)").unwrap();
+        let swapped = [
+            AbiValue::Uint("1".to_string()),
+            AbiValue::Address(format!("0x{}", "11".repeat(20))),
+        ];
+        assert!(encode_call(&mint, &swapped).is_err());
+    }
+
+    #[test]
+    fn signed_integers_round_trip_through_tokens() {
+        let function = parse_function("f(int256)").unwrap();
+        let data = encode_call(&function, &[AbiValue::Int("-2".to_string())]).unwrap();
+
+        let mut minus_two = vec![0xff; 31];
+        minus_two.push(0xfe);
+        assert_eq!(data[4..], minus_two);
+        assert_eq!(
+            from_token(Token::Int(I256::from(-2).into_raw())),
+            AbiValue::Int("-2".to_string())
+        );
+    }
+}
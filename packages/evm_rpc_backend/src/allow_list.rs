@@ -0,0 +1,166 @@
+use crate::chains::get_chain;
+use crate::{normalize_address, CanisterState, STATE};
+use candid::{CandidType, Principal};
+use ic_cdk::{query, update};
+use serde::{Deserialize, Serialize};
+
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum AllowListAction {
+    Added,
+    Removed,
+}
+
+/// A single entry of the append-only allow-list audit log.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct AllowListEvent {
+    pub id: u64,
+    pub chain_id: u64,
+    pub contract: String,
+    pub action: AllowListAction,
+    pub caller: Principal,
+    pub timestamp_ns: u64,
+}
+
+/// Fails unless `contract` is allow-listed on `chain_id`. Returns the
+/// normalized (lowercase, `0x`-prefixed) address.
+pub fn require_allowed_contract(chain_id: u64, contract: &str) -> Result<String, String> {
+    let contract = normalize_address(contract)?;
+    let allowed = STATE.with(|state| {
+        state
+            .borrow()
+            .allowed_contracts
+            .get(&chain_id)
+            .is_some_and(|contracts| contracts.contains(&contract))
+    });
+    if allowed {
+        Ok(contract)
+    } else {
+        Err(format!(
+            "Contract {} is not allow-listed on chain {}",
+            contract, chain_id
+        ))
+    }
+}
+
+fn record_event(
+    state: &mut CanisterState,
+    chain_id: u64,
+    contract: String,
+    action: AllowListAction,
+    caller: Principal,
+    timestamp_ns: u64,
+) {
+    let id = state.allow_list_events.len() as u64;
+    state.allow_list_events.push(AllowListEvent {
+        id,
+        chain_id,
+        contract,
+        action,
+        caller,
+        timestamp_ns,
+    });
+}
+
+#[update]
+pub fn add_allowed_contract(chain_id: u64, contract: String) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update the contract allow-list".to_string());
+    }
+    get_chain(chain_id)?;
+    let contract = normalize_address(&contract)?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if !state
+            .allowed_contracts
+            .entry(chain_id)
+            .or_default()
+            .insert(contract.clone())
+        {
+            return Err(format!(
+                "Contract {} is already allow-listed on chain {}",
+                contract, chain_id
+            ));
+        }
+        record_event(
+            &mut state,
+            chain_id,
+            contract,
+            AllowListAction::Added,
+            caller,
+            ic_cdk::api::time(),
+        );
+        Ok(())
+    })
+}
+
+#[update]
+pub fn remove_allowed_contract(chain_id: u64, contract: String) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update the contract allow-list".to_string());
+    }
+    let contract = normalize_address(&contract)?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let removed = state
+            .allowed_contracts
+            .get_mut(&chain_id)
+            .is_some_and(|contracts| contracts.remove(&contract));
+        if !removed {
+            return Err(format!(
+                "Contract {} is not allow-listed on chain {}",
+                contract, chain_id
+            ));
+        }
+        if state
+            .allowed_contracts
+            .get(&chain_id)
+            .is_some_and(|contracts| contracts.is_empty())
+        {
+            state.allowed_contracts.remove(&chain_id);
+        }
+        record_event(
+            &mut state,
+            chain_id,
+            contract,
+            AllowListAction::Removed,
+            caller,
+            ic_cdk::api::time(),
+        );
+        Ok(())
+    })
+}
+
+#[query]
+pub fn list_allowed_contracts(chain_id: u64) -> Vec<String> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .allowed_contracts
+            .get(&chain_id)
+            .map(|contracts| contracts.iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+// Newest entries first.
+#[query]
+pub fn get_allow_list_events(offset: u64, limit: u64) -> Vec<AllowListEvent> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .allow_list_events
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
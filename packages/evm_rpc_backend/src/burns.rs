@@ -0,0 +1,559 @@
+use crate::chains::{get_chain, ChainConfig};
+use crate::roles::{ensure_permission, Permission};
+use crate::{
+    block_hash_at, consensus, ensure_final_receipt, fetch_receipt, get_latest_block_number,
+    nat_to_u64, normalize_address, CallResult, CanisterState, STATE,
+};
+use candid::{CandidType, Nat, Principal};
+use ethers_core::abi::{decode, ParamType, Token};
+use ethers_core::utils::{hex, keccak256};
+use evm_rpc_canister_types::{
+    BlockTag, GetLogsArgs, GetLogsResult, LogEntry, MultiGetLogsResult, EVM_RPC,
+};
+use ic_cdk::{query, update};
+use ic_cdk_timers::set_timer_interval;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+const BURN_SCAN_INTERVAL: Duration = Duration::from_secs(120);
+const DEFAULT_MAX_BLOCK_RANGE: u64 = 500;
+// Oldest detections are dropped past this; consumers page by id and keep
+// their own cursor.
+const MAX_DETECTED_BURNS: usize = 10_000;
+const MAX_PAGE_SIZE: u64 = 100;
+const BURN_TO_ICP_EVENT: &str = "BurnToICP(address,bytes,bytes32,uint256)";
+const TRANSFER_EVENT: &str = "Transfer(address,address,uint256)";
+
+/// Where the scanner resumes on a chain. Scanning only runs on chains that
+/// have a cursor.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct BurnScanCursor {
+    pub next_block: u64,
+    /// Most blocks requested in one `eth_getLogs` call.
+    pub max_block_range: u64,
+    pub last_scanned_at_ns: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// A `BurnToICP` log from an allow-listed contract, buried at least the
+/// chain's confirmation depth when it was recorded.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DetectedBurn {
+    pub id: u64,
+    pub chain_id: u64,
+    pub contract: String,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub block_number: u64,
+    pub block_hash: Option<String>,
+    pub from: String,
+    /// `None` when the event carried bytes that are not a valid principal.
+    pub recipient: Option<Principal>,
+    /// `None` for the default, all-zero subaccount.
+    pub recipient_subaccount: Option<Vec<u8>>,
+    pub amount_wei: String,
+    pub detected_at_ns: u64,
+}
+
+fn burn_to_icp_topic() -> String {
+    format!("0x{}", hex::encode(keccak256(BURN_TO_ICP_EVENT)))
+}
+
+pub fn is_burn_to_icp_log(log: &LogEntry) -> bool {
+    log.topics
+        .first()
+        .is_some_and(|topic| topic.eq_ignore_ascii_case(&burn_to_icp_topic()))
+}
+
+/// An ERC-20 `Transfer`. Plain burns are matched on these alone, so an
+/// `Approval` to the zero address, with the same topic layout, is not one.
+pub fn is_transfer_log(log: &LogEntry) -> bool {
+    let topic = format!("0x{}", hex::encode(keccak256(TRANSFER_EVENT)));
+    log.topics
+        .first()
+        .is_some_and(|first| first.eq_ignore_ascii_case(&topic))
+}
+
+fn topic_address(topic: &str) -> CallResult<String> {
+    let topic = topic.trim_start_matches("0x");
+    if topic.len() != 64 {
+        return Err(format!("Invalid address topic: {}", topic));
+    }
+    normalize_address(&format!("0x{}", &topic[24..]))
+}
+
+/// Decodes a `BurnToICP` log. `id` and `detected_at_ns` are assigned when the
+/// burn is recorded.
+pub(crate) fn parse_burn_log(chain_id: u64, log: &LogEntry) -> CallResult<Option<DetectedBurn>> {
+    if log.removed || !is_burn_to_icp_log(log) {
+        return Ok(None);
+    }
+    let from = topic_address(
+        log.topics
+            .get(1)
+            .ok_or("BurnToICP log lacks a from topic")?,
+    )?;
+    let data = hex::decode(log.data.trim_start_matches("0x"))
+        .map_err(|e| format!("Invalid BurnToICP data: {}", e))?;
+    let tokens = decode(
+        &[
+            ParamType::Bytes,
+            ParamType::FixedBytes(32),
+            ParamType::Uint(256),
+        ],
+        &data,
+    )
+    .map_err(|e| format!("Failed to decode BurnToICP data: {}", e))?;
+    let (recipient, subaccount, amount) = match tokens.as_slice() {
+        [Token::Bytes(recipient), Token::FixedBytes(subaccount), Token::Uint(amount)] => (
+            Principal::try_from_slice(recipient).ok(),
+            subaccount.clone(),
+            *amount,
+        ),
+        _ => return Err("Unexpected BurnToICP data layout".to_string()),
+    };
+    let tx_hash = log
+        .transactionHash
+        .clone()
+        .ok_or("BurnToICP log lacks a transaction hash")?;
+    let log_index = log
+        .logIndex
+        .as_ref()
+        .ok_or("BurnToICP log lacks a log index")?;
+    let block_number = log
+        .blockNumber
+        .as_ref()
+        .ok_or("BurnToICP log lacks a block number")?;
+
+    Ok(Some(DetectedBurn {
+        id: 0,
+        chain_id,
+        contract: normalize_address(&log.address)?,
+        tx_hash: tx_hash.to_lowercase(),
+        log_index: nat_to_u64(log_index, "log index")?,
+        block_number: nat_to_u64(block_number, "log block number")?,
+        block_hash: log.blockHash.as_ref().map(|hash| hash.to_lowercase()),
+        from,
+        recipient,
+        recipient_subaccount: subaccount
+            .iter()
+            .any(|byte| *byte != 0)
+            .then_some(subaccount),
+        amount_wei: amount.to_string(),
+        detected_at_ns: 0,
+    }))
+}
+
+/// Stores `burns` that are not known yet and returns the stored entry for
+/// each of them.
+fn record_burns_in(
+    state: &mut CanisterState,
+    burns: Vec<DetectedBurn>,
+    now_ns: u64,
+) -> Vec<DetectedBurn> {
+    let mut recorded = Vec::with_capacity(burns.len());
+    for mut burn in burns {
+        let existing = state.detected_burns.iter().find(|known| {
+            known.chain_id == burn.chain_id
+                && known.tx_hash == burn.tx_hash
+                && known.log_index == burn.log_index
+        });
+        if let Some(existing) = existing {
+            recorded.push(existing.clone());
+            continue;
+        }
+        burn.id = state.next_burn_id;
+        burn.detected_at_ns = now_ns;
+        state.next_burn_id += 1;
+        state.detected_burns.push(burn.clone());
+        recorded.push(burn);
+    }
+    if state.detected_burns.len() > MAX_DETECTED_BURNS {
+        let excess = state.detected_burns.len() - MAX_DETECTED_BURNS;
+        state.detected_burns.drain(..excess);
+    }
+    recorded
+}
+
+fn record_burns(burns: Vec<DetectedBurn>) -> Vec<DetectedBurn> {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| record_burns_in(&mut state.borrow_mut(), burns, now))
+}
+
+fn allowed_contracts(chain_id: u64) -> Vec<String> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .allowed_contracts
+            .get(&chain_id)
+            .map(|contracts| contracts.iter().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Highest block with the chain's burn confirmations.
+fn confirmed_head(latest_block: u64, chain: &ChainConfig) -> Option<u64> {
+    (latest_block + 1).checked_sub(chain.burn_confirmations())
+}
+
+/// Fetches `BurnToICP` logs of `contracts` in `[from, to]`. Like receipts,
+/// `Inconsistent` answers are only accepted with a quorum.
+async fn fetch_burn_logs(
+    chain: &ChainConfig,
+    contracts: Vec<String>,
+    from: u64,
+    to: u64,
+) -> CallResult<Vec<LogEntry>> {
+    let args = GetLogsArgs {
+        fromBlock: Some(BlockTag::Number(Nat::from(from))),
+        toBlock: Some(BlockTag::Number(Nat::from(to))),
+        addresses: contracts,
+        topics: Some(vec![vec![burn_to_icp_topic()]]),
+    };
+    let (result,) = EVM_RPC
+        .eth_get_logs(chain.services(), chain.rpc_config(), args, 10_000_000_000)
+        .await
+        .map_err(|e| format!("Call failed: {:?}", e))?;
+
+    let result = match result {
+        MultiGetLogsResult::Consistent(result) => result,
+        MultiGetLogsResult::Inconsistent(responses) => {
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            consensus::agreed(&responses, quorum).ok_or_else(|| {
+                format!(
+                    "Inconsistent result from RPC: no {} of {} providers agree on logs for blocks {}-{}",
+                    quorum,
+                    responses.len(),
+                    from,
+                    to
+                )
+            })?
+        }
+    };
+
+    match result {
+        GetLogsResult::Ok(logs) => Ok(logs),
+        GetLogsResult::Err(err) => Err(format!("eth_getLogs error: {:?}", err)),
+    }
+}
+
+fn update_cursor(chain_id: u64, f: impl FnOnce(&mut BurnScanCursor)) {
+    STATE.with(|state| {
+        if let Some(cursor) = state.borrow_mut().burn_cursors.get_mut(&chain_id) {
+            f(cursor);
+        }
+    });
+}
+
+/// Scans the next confirmed block range on `chain_id` and returns how many
+/// new burns were recorded.
+async fn scan_chain(chain_id: u64) -> CallResult<u64> {
+    let chain = get_chain(chain_id)?;
+    let cursor = STATE
+        .with(|state| state.borrow().burn_cursors.get(&chain_id).cloned())
+        .ok_or_else(|| format!("Burn scanning is not enabled on chain {}", chain_id))?;
+    let contracts = allowed_contracts(chain_id);
+    if contracts.is_empty() {
+        return Ok(0);
+    }
+
+    let latest_block = get_latest_block_number(&chain).await?;
+    let Some(head) = confirmed_head(latest_block, &chain).filter(|head| *head >= cursor.next_block)
+    else {
+        return Ok(0);
+    };
+    let from = cursor.next_block;
+    let to = head.min(from.saturating_add(cursor.max_block_range.max(1) - 1));
+
+    let logs = fetch_burn_logs(&chain, contracts, from, to).await?;
+    let mut burns = Vec::new();
+    for log in &logs {
+        match parse_burn_log(chain_id, log) {
+            Ok(Some(burn)) => burns.push(burn),
+            Ok(None) => {}
+            Err(err) => ic_cdk::println!("skipping malformed burn log on {}: {}", chain_id, err),
+        }
+    }
+    // Logs below the confirmed head are final in practice; checking their
+    // blocks keeps a lagging provider from reporting a replaced one.
+    let mut checked_blocks = BTreeSet::new();
+    for burn in &burns {
+        if !checked_blocks.insert(burn.block_number) {
+            continue;
+        }
+        let canonical = block_hash_at(&chain, burn.block_number).await?;
+        if burn
+            .block_hash
+            .as_ref()
+            .is_some_and(|hash| *hash != canonical)
+        {
+            return Err(format!(
+                "Logs for block {} came from a replaced block",
+                burn.block_number
+            ));
+        }
+    }
+    let known_before = STATE.with(|state| state.borrow().next_burn_id);
+    let recorded = record_burns(burns);
+    let new_burns = recorded
+        .iter()
+        .filter(|burn| burn.id >= known_before)
+        .count() as u64;
+
+    let now = ic_cdk::api::time();
+    update_cursor(chain_id, |cursor| {
+        // A controller may have moved the cursor while the logs were fetched.
+        if cursor.next_block == from {
+            cursor.next_block = to + 1;
+        }
+        cursor.last_scanned_at_ns = Some(now);
+        cursor.last_error = None;
+    });
+    Ok(new_burns)
+}
+
+pub fn start_burn_scanner() {
+    set_timer_interval(BURN_SCAN_INTERVAL, || {
+        ic_cdk::spawn(async {
+            let chain_ids: Vec<u64> =
+                STATE.with(|state| state.borrow().burn_cursors.keys().copied().collect());
+            for chain_id in chain_ids {
+                if let Err(err) = scan_chain(chain_id).await {
+                    ic_cdk::println!("burn scan on chain {} failed: {}", chain_id, err);
+                    update_cursor(chain_id, |cursor| cursor.last_error = Some(err));
+                }
+            }
+        })
+    });
+}
+
+/// Starts (or moves) burn scanning on `chain_id` at `next_block`, usually the
+/// contract's deployment block.
+#[update]
+pub fn set_burn_scan_cursor(
+    chain_id: u64,
+    next_block: u64,
+    max_block_range: Option<u64>,
+) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may move the burn scan cursor".to_string());
+    }
+    get_chain(chain_id)?;
+    let max_block_range = max_block_range.unwrap_or(DEFAULT_MAX_BLOCK_RANGE);
+    if max_block_range == 0 {
+        return Err("max_block_range must be at least 1".to_string());
+    }
+
+    STATE.with(|state| {
+        state.borrow_mut().burn_cursors.insert(
+            chain_id,
+            BurnScanCursor {
+                next_block,
+                max_block_range,
+                last_scanned_at_ns: None,
+                last_error: None,
+            },
+        );
+    });
+    Ok(())
+}
+
+#[query]
+pub fn get_burn_scan_cursors() -> Vec<(u64, BurnScanCursor)> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .burn_cursors
+            .iter()
+            .map(|(chain_id, cursor)| (*chain_id, cursor.clone()))
+            .collect()
+    })
+}
+
+/// Runs one scan step on `chain_id` without waiting for the timer.
+#[update]
+pub async fn scan_burns(chain_id: u64) -> CallResult<u64> {
+    ensure_permission(Permission::Verify)?;
+    let result = scan_chain(chain_id).await;
+    if let Err(err) = &result {
+        update_cursor(chain_id, |cursor| cursor.last_error = Some(err.clone()));
+    }
+    result
+}
+
+/// Records the `BurnToICP` logs of a single transaction, so a burn can be
+/// picked up before the scanner reaches its block.
+#[update]
+pub async fn scan_burn_transaction(
+    chain_id: u64,
+    tx_hash: String,
+) -> CallResult<Vec<DetectedBurn>> {
+    ensure_permission(Permission::Verify)?;
+    let chain = get_chain(chain_id)?;
+    let contracts = allowed_contracts(chain_id);
+
+    let receipt = fetch_receipt(&tx_hash, &chain)
+        .await?
+        .ok_or_else(|| "Transaction receipt not yet available.".to_string())?;
+    ensure_final_receipt(&chain, &tx_hash, &receipt).await?;
+
+    let mut burns = Vec::new();
+    for log in &receipt.logs {
+        let from_allowed_contract =
+            normalize_address(&log.address).is_ok_and(|address| contracts.contains(&address));
+        if !from_allowed_contract {
+            continue;
+        }
+        if let Some(burn) = parse_burn_log(chain_id, log)? {
+            burns.push(burn);
+        }
+    }
+    if burns.is_empty() {
+        return Err(format!(
+            "Transaction {} contains no BurnToICP event",
+            tx_hash
+        ));
+    }
+    Ok(record_burns(burns))
+}
+
+/// Burns with `id >= start_id`, oldest first.
+#[query]
+pub fn list_detected_burns(start_id: u64, limit: u64) -> Vec<DetectedBurn> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        let state = state.borrow();
+        let start = state
+            .detected_burns
+            .partition_point(|burn| burn.id < start_id);
+        state.detected_burns[start..]
+            .iter()
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers_core::abi::encode;
+    use ethers_core::types::U256;
+
+    fn burn_data(recipient: &[u8], subaccount: [u8; 32], amount: u64) -> String {
+        let data = encode(&[
+            Token::Bytes(recipient.to_vec()),
+            Token::FixedBytes(subaccount.to_vec()),
+            Token::Uint(U256::from(amount)),
+        ]);
+        format!("0x{}", hex::encode(data))
+    }
+
+    fn burn_log(data: String) -> LogEntry {
+        LogEntry {
+            transactionHash: Some("0xABC".to_string()),
+            blockNumber: Some(Nat::from(10u64)),
+            data,
+            blockHash: Some("0xDEF".to_string()),
+            transactionIndex: Some(Nat::from(0u64)),
+            topics: vec![
+                burn_to_icp_topic(),
+                format!("0x{}{}", "00".repeat(12), "aa".repeat(20)),
+            ],
+            address: format!("0x{}", "BB".repeat(20)),
+            logIndex: Some(Nat::from(3u64)),
+            removed: false,
+        }
+    }
+
+    fn principal() -> Principal {
+        Principal::from_slice(&[1, 2, 3, 4])
+    }
+
+    #[test]
+    fn burn_logs_decode_recipient_and_amount() {
+        let burn = parse_burn_log(
+            1,
+            &burn_log(burn_data(principal().as_slice(), [0; 32], 500)),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(burn.tx_hash, "0xabc");
+        assert_eq!(burn.log_index, 3);
+        assert_eq!(burn.block_number, 10);
+        assert_eq!(burn.from, format!("0x{}", "aa".repeat(20)));
+        assert_eq!(burn.contract, format!("0x{}", "bb".repeat(20)));
+        assert_eq!(burn.recipient, Some(principal()));
+        assert_eq!(burn.recipient_subaccount, None);
+        assert_eq!(burn.amount_wei, "500");
+    }
+
+    #[test]
+    fn burn_logs_keep_a_non_default_subaccount() {
+        let mut subaccount = [0u8; 32];
+        subaccount[31] = 1;
+        let burn = parse_burn_log(
+            1,
+            &burn_log(burn_data(principal().as_slice(), subaccount, 1)),
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(burn.recipient_subaccount, Some(subaccount.to_vec()));
+    }
+
+    #[test]
+    fn other_events_and_removed_logs_are_skipped() {
+        let mut transfer = burn_log(burn_data(principal().as_slice(), [0; 32], 1));
+        transfer.topics[0] = format!(
+            "0x{}",
+            hex::encode(keccak256("Transfer(address,address,uint256)"))
+        );
+        assert!(parse_burn_log(1, &transfer).unwrap().is_none());
+
+        let mut removed = burn_log(burn_data(principal().as_slice(), [0; 32], 1));
+        removed.removed = true;
+        assert!(parse_burn_log(1, &removed).unwrap().is_none());
+    }
+
+    #[test]
+    fn only_transfer_events_are_transfers() {
+        let mut log = burn_log(burn_data(principal().as_slice(), [0; 32], 1));
+        assert!(!is_transfer_log(&log));
+        log.topics[0] = format!("0x{}", hex::encode(keccak256(TRANSFER_EVENT))).to_uppercase();
+        assert!(is_transfer_log(&log));
+        log.topics[0] = format!(
+            "0x{}",
+            hex::encode(keccak256("Approval(address,address,uint256)"))
+        );
+        assert!(!is_transfer_log(&log));
+    }
+
+    #[test]
+    fn short_data_is_an_error() {
+        let data = burn_data(principal().as_slice(), [0; 32], 1);
+        let short = burn_log(data[..data.len() - 64].to_string());
+
+        assert!(parse_burn_log(1, &short).is_err());
+    }
+
+    #[test]
+    fn invalid_principal_bytes_leave_no_recipient() {
+        let burn = parse_burn_log(1, &burn_log(burn_data(&[0xff; 30], [0; 32], 1)))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(burn.recipient, None);
+    }
+
+    #[test]
+    fn missing_from_topic_is_an_error() {
+        let mut log = burn_log(burn_data(principal().as_slice(), [0; 32], 1));
+        log.topics.truncate(1);
+
+        assert!(parse_burn_log(1, &log).is_err());
+    }
+}
@@ -0,0 +1,509 @@
+use crate::gas::{self, FeeCapMode};
+use crate::STATE;
+use candid::CandidType;
+use evm_rpc_canister_types::{
+    ConsensusStrategy, EthMainnetService, EthSepoliaService, L2MainnetService, RpcApi, RpcConfig,
+    RpcService, RpcServices,
+};
+use ic_cdk::{query, update};
+use serde::Deserialize;
+
+// Contract and depth every chain used before they became per-chain settings.
+pub const LEGACY_BRIDGE_CONTRACT_ADDRESS: &str = "0x272aEe5159a257359e84EAB3a6e3bd7F90b712EC";
+const LEGACY_CONFIRMATION_DEPTH: u64 = 12;
+const DEFAULT_MIN_PRIORITY_FEE_WEI: u64 = 1_000_000_000; // 1 gwei safety floor
+const DEFAULT_BASE_FEE_MULTIPLIER_PERCENT: u64 = 200;
+const DEFAULT_GAS_LIMIT_MULTIPLIER_PERCENT: u64 = 120;
+const DEFAULT_MAX_GAS_LIMIT: u64 = 1_000_000;
+
+/// Provider set handed to the EVM RPC canister. Built-in services default to
+/// the canister's own provider selection when no list is given.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub enum RpcProviders {
+    Custom(Vec<RpcApi>),
+    EthMainnet(Option<Vec<EthMainnetService>>),
+    EthSepolia(Option<Vec<EthSepoliaService>>),
+    ArbitrumOne(Option<Vec<L2MainnetService>>),
+    BaseMainnet(Option<Vec<L2MainnetService>>),
+    OptimismMainnet(Option<Vec<L2MainnetService>>),
+}
+
+impl RpcProviders {
+    fn builtin_chain_id(&self) -> Option<u64> {
+        match self {
+            RpcProviders::Custom(_) => None,
+            RpcProviders::EthMainnet(_) => Some(1),
+            RpcProviders::EthSepolia(_) => Some(11_155_111),
+            RpcProviders::ArbitrumOne(_) => Some(42_161),
+            RpcProviders::BaseMainnet(_) => Some(8_453),
+            RpcProviders::OptimismMainnet(_) => Some(10),
+        }
+    }
+
+    /// Number of explicitly listed providers, if known locally.
+    fn explicit_count(&self) -> Option<usize> {
+        match self {
+            RpcProviders::Custom(services) => Some(services.len()),
+            RpcProviders::EthMainnet(services) => services.as_ref().map(Vec::len),
+            RpcProviders::EthSepolia(services) => services.as_ref().map(Vec::len),
+            RpcProviders::ArbitrumOne(services)
+            | RpcProviders::BaseMainnet(services)
+            | RpcProviders::OptimismMainnet(services) => services.as_ref().map(Vec::len),
+        }
+    }
+
+    fn to_services(&self, chain_id: u64) -> RpcServices {
+        match self.clone() {
+            RpcProviders::Custom(services) => RpcServices::Custom {
+                chainId: chain_id,
+                services,
+            },
+            RpcProviders::EthMainnet(services) => RpcServices::EthMainnet(services),
+            RpcProviders::EthSepolia(services) => RpcServices::EthSepolia(services),
+            RpcProviders::ArbitrumOne(services) => RpcServices::ArbitrumOne(services),
+            RpcProviders::BaseMainnet(services) => RpcServices::BaseMainnet(services),
+            RpcProviders::OptimismMainnet(services) => RpcServices::OptimismMainnet(services),
+        }
+    }
+
+    /// Individual providers for the EVM RPC canister's single-service
+    /// `request` method. Built-in networks without an explicit list use the
+    /// one provider every network supports.
+    fn to_service_list(&self) -> Vec<RpcService> {
+        match self.clone() {
+            RpcProviders::Custom(services) => {
+                services.into_iter().map(RpcService::Custom).collect()
+            }
+            RpcProviders::EthMainnet(services) => services
+                .unwrap_or(vec![EthMainnetService::PublicNode])
+                .into_iter()
+                .map(RpcService::EthMainnet)
+                .collect(),
+            RpcProviders::EthSepolia(services) => services
+                .unwrap_or(vec![EthSepoliaService::PublicNode])
+                .into_iter()
+                .map(RpcService::EthSepolia)
+                .collect(),
+            RpcProviders::ArbitrumOne(services) => services
+                .unwrap_or(vec![L2MainnetService::PublicNode])
+                .into_iter()
+                .map(RpcService::ArbitrumOne)
+                .collect(),
+            RpcProviders::BaseMainnet(services) => services
+                .unwrap_or(vec![L2MainnetService::PublicNode])
+                .into_iter()
+                .map(RpcService::BaseMainnet)
+                .collect(),
+            RpcProviders::OptimismMainnet(services) => services
+                .unwrap_or(vec![L2MainnetService::PublicNode])
+                .into_iter()
+                .map(RpcService::OptimismMainnet)
+                .collect(),
+        }
+    }
+
+    /// Custom provider headers usually carry API keys; everything else is
+    /// safe to show.
+    fn without_headers(&self) -> Self {
+        match self {
+            RpcProviders::Custom(services) => RpcProviders::Custom(
+                services
+                    .iter()
+                    .map(|service| RpcApi {
+                        url: service.url.clone(),
+                        headers: None,
+                    })
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
+/// Fee history sampling and caps used to price canister-signed transactions,
+/// and the headroom applied to `eth_estimateGas` results.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct GasPolicy {
+    pub fee_history_blocks: u64,
+    /// Priority-fee percentile sampled per block; the median across the
+    /// window is used.
+    pub reward_percentile: u8,
+    pub min_priority_fee_wei: u64,
+    /// Percentage of the estimate used as the gas limit; defaults to 120.
+    pub gas_limit_multiplier_percent: Option<u64>,
+    /// Hard ceiling on the gas limit; defaults to 1,000,000.
+    pub max_gas_limit: Option<u64>,
+    /// Percentage of the current base fee budgeted in `max_fee_per_gas`, as
+    /// headroom for base-fee growth; defaults to 200.
+    pub base_fee_multiplier_percent: Option<u64>,
+    /// Absolute ceiling on `max_fee_per_gas`; unlimited when unset.
+    pub max_fee_per_gas_cap_wei: Option<u64>,
+    /// Behaviour while the network is above the cap; defaults to `Reject`.
+    pub fee_cap_mode: Option<FeeCapMode>,
+}
+
+impl Default for GasPolicy {
+    fn default() -> Self {
+        Self {
+            fee_history_blocks: 9,
+            reward_percentile: 20,
+            min_priority_fee_wei: DEFAULT_MIN_PRIORITY_FEE_WEI,
+            gas_limit_multiplier_percent: None,
+            max_gas_limit: None,
+            base_fee_multiplier_percent: None,
+            max_fee_per_gas_cap_wei: None,
+            fee_cap_mode: None,
+        }
+    }
+}
+
+impl GasPolicy {
+    pub fn gas_limit_multiplier_percent(&self) -> u64 {
+        self.gas_limit_multiplier_percent
+            .unwrap_or(DEFAULT_GAS_LIMIT_MULTIPLIER_PERCENT)
+    }
+
+    pub fn max_gas_limit(&self) -> u64 {
+        self.max_gas_limit.unwrap_or(DEFAULT_MAX_GAS_LIMIT)
+    }
+
+    pub fn base_fee_multiplier_percent(&self) -> u64 {
+        self.base_fee_multiplier_percent
+            .unwrap_or(DEFAULT_BASE_FEE_MULTIPLIER_PERCENT)
+    }
+
+    pub fn fee_cap_mode(&self) -> FeeCapMode {
+        self.fee_cap_mode.unwrap_or(FeeCapMode::Reject)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.fee_history_blocks == 0 {
+            return Err("fee_history_blocks must be greater than zero".to_string());
+        }
+        if self.reward_percentile > 100 {
+            return Err("reward_percentile must be at most 100".to_string());
+        }
+        if self.gas_limit_multiplier_percent() < 100 {
+            return Err("gas_limit_multiplier_percent must be at least 100".to_string());
+        }
+        if self.max_gas_limit() < 21_000 {
+            return Err("max_gas_limit must be at least 21000".to_string());
+        }
+        if self.base_fee_multiplier_percent() < 100 {
+            return Err("base_fee_multiplier_percent must be at least 100".to_string());
+        }
+        if self
+            .max_fee_per_gas_cap_wei
+            .is_some_and(|cap| cap < self.min_priority_fee_wei)
+        {
+            return Err("max_fee_per_gas_cap_wei is below min_priority_fee_wei".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ChainConfig {
+    pub chain_id: u64,
+    pub providers: RpcProviders,
+    /// Applied by the EVM RPC canister first; `Inconsistent` answers are then
+    /// resolved locally per method against the same quorum.
+    pub consensus: ConsensusStrategy,
+    pub gas_policy: GasPolicy,
+    /// Blocks a receipt must be buried under before callers should act on it.
+    pub confirmation_depth: u64,
+    /// Confirmations a burn needs before it may be unlocked; defaults to
+    /// `confirmation_depth`.
+    pub min_burn_confirmations: Option<u64>,
+    /// ECDSA derivation path of the signing identity on this chain. Empty
+    /// keeps the canister's original address.
+    pub derivation_path: Vec<Vec<u8>>,
+}
+
+impl ChainConfig {
+    /// Copy safe to return to any caller.
+    fn redacted(&self) -> Self {
+        ChainConfig {
+            providers: self.providers.without_headers(),
+            ..self.clone()
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if let Some(expected) = self.providers.builtin_chain_id() {
+            if expected != self.chain_id {
+                return Err(format!(
+                    "chain_id {} does not match provider network {}",
+                    self.chain_id, expected
+                ));
+            }
+        }
+
+        let count = self.providers.explicit_count();
+        if count == Some(0) {
+            return Err("At least one RPC provider is required".to_string());
+        }
+        if let RpcProviders::Custom(services) = &self.providers {
+            if services.iter().any(|service| service.url.trim().is_empty()) {
+                return Err("Custom RPC provider URL must not be empty".to_string());
+            }
+        }
+
+        if let ConsensusStrategy::Threshold { min, total } = &self.consensus {
+            if *min == 0 {
+                return Err("Consensus threshold must be at least 1".to_string());
+            }
+            if let Some(total) = total {
+                if min > total {
+                    return Err("Consensus threshold exceeds total providers".to_string());
+                }
+                if let Some(count) = count {
+                    if count != *total as usize {
+                        return Err(format!(
+                            "Consensus total {} does not match {} configured providers",
+                            total, count
+                        ));
+                    }
+                }
+            } else if let Some(count) = count {
+                if *min as usize > count {
+                    return Err("Consensus threshold exceeds configured providers".to_string());
+                }
+            }
+        }
+
+        if self.min_burn_confirmations == Some(0) {
+            return Err("min_burn_confirmations must be at least 1".to_string());
+        }
+
+        self.gas_policy.validate()
+    }
+
+    pub fn burn_confirmations(&self) -> u64 {
+        self.min_burn_confirmations
+            .unwrap_or(self.confirmation_depth)
+            .max(1)
+    }
+
+    pub fn services(&self) -> RpcServices {
+        self.providers.to_services(self.chain_id)
+    }
+
+    pub fn service_list(&self) -> Vec<RpcService> {
+        self.providers.to_service_list()
+    }
+
+    pub fn rpc_config(&self) -> Option<RpcConfig> {
+        Some(RpcConfig {
+            responseConsensus: Some(self.consensus.clone()),
+            responseSizeEstimate: None,
+        })
+    }
+}
+
+/// Single-URL config written before multi-provider support; only decoded from
+/// stable memory so an upgrade keeps the configured endpoint.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyRpcConfig {
+    pub chain_id: u64,
+    pub rpc_url: String,
+}
+
+/// Single-chain provider config written before the chain registry existed.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct LegacyProviderConfig {
+    pub chain_id: u64,
+    pub providers: RpcProviders,
+    pub consensus: ConsensusStrategy,
+}
+
+impl From<LegacyRpcConfig> for LegacyProviderConfig {
+    fn from(config: LegacyRpcConfig) -> Self {
+        LegacyProviderConfig {
+            chain_id: config.chain_id,
+            providers: RpcProviders::Custom(vec![RpcApi {
+                url: config.rpc_url,
+                headers: None,
+            }]),
+            consensus: ConsensusStrategy::Equality,
+        }
+    }
+}
+
+impl From<LegacyProviderConfig> for ChainConfig {
+    fn from(config: LegacyProviderConfig) -> Self {
+        ChainConfig {
+            chain_id: config.chain_id,
+            providers: config.providers,
+            consensus: config.consensus,
+            gas_policy: GasPolicy::default(),
+            confirmation_depth: LEGACY_CONFIRMATION_DEPTH,
+            min_burn_confirmations: None,
+            derivation_path: vec![],
+        }
+    }
+}
+
+pub fn get_chain(chain_id: u64) -> Result<ChainConfig, String> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .chains
+            .get(&chain_id)
+            .cloned()
+            .ok_or_else(|| format!("Chain {} is not configured", chain_id))
+    })
+}
+
+#[update]
+pub fn set_chain_config(config: ChainConfig) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update chain configuration".to_string());
+    }
+    config.validate()?;
+
+    STATE.with(|state| {
+        state.borrow_mut().chains.insert(config.chain_id, config);
+    });
+
+    Ok(())
+}
+
+#[update]
+pub fn remove_chain_config(chain_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update chain configuration".to_string());
+    }
+
+    let has_deferred = gas::has_deferred(chain_id);
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let has_pending = state
+            .nonce_states
+            .get(&chain_id)
+            .is_some_and(|nonce_state| !nonce_state.pending.is_empty());
+        if has_pending || has_deferred {
+            return Err(format!("Chain {} still has pending transactions", chain_id));
+        }
+        state
+            .chains
+            .remove(&chain_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("Chain {} is not configured", chain_id))
+    })
+}
+
+#[update]
+pub fn set_gas_policy(chain_id: u64, policy: GasPolicy) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update the gas policy".to_string());
+    }
+    policy.validate()?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let chain = state
+            .chains
+            .get_mut(&chain_id)
+            .ok_or_else(|| format!("Chain {} is not configured", chain_id))?;
+        chain.gas_policy = policy;
+        Ok(())
+    })
+}
+
+/// Sets the confirmations burns need on `chain_id`; `None` falls back to the
+/// chain's `confirmation_depth`.
+#[update]
+pub fn set_min_burn_confirmations(chain_id: u64, confirmations: Option<u64>) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update chain configuration".to_string());
+    }
+    if confirmations == Some(0) {
+        return Err("min_burn_confirmations must be at least 1".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let chain = state
+            .chains
+            .get_mut(&chain_id)
+            .ok_or_else(|| format!("Chain {} is not configured", chain_id))?;
+        chain.min_burn_confirmations = confirmations;
+        Ok(())
+    })
+}
+
+#[query]
+pub fn get_gas_policy(chain_id: u64) -> Option<GasPolicy> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .chains
+            .get(&chain_id)
+            .map(|chain| chain.gas_policy.clone())
+    })
+}
+
+// Controllers see the full config, provider headers included; everyone else
+// gets it with the headers stripped.
+fn visible_to_caller(config: &ChainConfig) -> ChainConfig {
+    if ic_cdk::api::is_controller(&ic_cdk::api::caller()) {
+        config.clone()
+    } else {
+        config.redacted()
+    }
+}
+
+#[query]
+pub fn get_chain_config(chain_id: u64) -> Option<ChainConfig> {
+    STATE.with(|state| state.borrow().chains.get(&chain_id).map(visible_to_caller))
+}
+
+#[query]
+pub fn list_chain_configs() -> Vec<ChainConfig> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .chains
+            .values()
+            .map(visible_to_caller)
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use evm_rpc_canister_types::HttpHeader;
+
+    #[test]
+    fn redacted_config_drops_custom_provider_headers() {
+        let config = ChainConfig::from(LegacyProviderConfig {
+            chain_id: 31_337,
+            providers: RpcProviders::Custom(vec![RpcApi {
+                url: "https://rpc.example".to_string(),
+                headers: Some(vec![HttpHeader {
+                    name: "Authorization".to_string(),
+                    value: "Bearer secret".to_string(),
+                }]),
+            }]),
+            consensus: ConsensusStrategy::Equality,
+        });
+
+        let redacted = config.redacted();
+        match redacted.providers {
+            RpcProviders::Custom(services) => {
+                assert_eq!(services.len(), 1);
+                assert_eq!(services[0].url, "https://rpc.example");
+                assert!(services[0].headers.is_none());
+            }
+            other => panic!("unexpected providers {:?}", other),
+        }
+        assert_eq!(redacted.chain_id, 31_337);
+        assert_eq!(redacted.confirmation_depth, config.confirmation_depth);
+    }
+}
@@ -0,0 +1,94 @@
+use candid::CandidType;
+use evm_rpc_canister_types::{ConsensusStrategy, RpcService};
+
+/// Matching answers required out of `responses` when the EVM RPC canister
+/// reports `Inconsistent`: the configured threshold, or a strict majority
+/// under `Equality`.
+pub fn quorum(strategy: &ConsensusStrategy, responses: usize) -> usize {
+    match strategy {
+        ConsensusStrategy::Equality => responses / 2 + 1,
+        ConsensusStrategy::Threshold { min, .. } => (*min as usize).max(1),
+    }
+}
+
+/// Returns the answer shared by at least `quorum` providers, provided no
+/// other answer ties it. Answers are compared by their candid encoding.
+pub fn agreed<T: CandidType + Clone>(responses: &[(RpcService, T)], quorum: usize) -> Option<T> {
+    let mut groups: Vec<(Vec<u8>, &T, usize)> = Vec::new();
+    for (_, response) in responses {
+        let Ok(bytes) = candid::encode_one(response) else {
+            continue;
+        };
+        match groups.iter_mut().find(|(key, _, _)| *key == bytes) {
+            Some(group) => group.2 += 1,
+            None => groups.push((bytes, response, 1)),
+        }
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.2));
+    match groups.as_slice() {
+        [(_, best, votes), rest @ ..]
+            if *votes >= quorum && rest.first().is_none_or(|next| next.2 < *votes) =>
+        {
+            Some((*best).clone())
+        }
+        _ => None,
+    }
+}
+
+/// Lower median of `values` once at least `quorum` providers answered. Used
+/// for numeric answers that legitimately drift between providers (latest
+/// block, fee history), where exact agreement is rare.
+pub fn median<T: Ord + Clone>(mut values: Vec<T>, quorum: usize) -> Option<T> {
+    if values.is_empty() || values.len() < quorum {
+        return None;
+    }
+    values.sort();
+    Some(values[(values.len() - 1) / 2].clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn answers(values: &[&str]) -> Vec<(RpcService, String)> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(provider, value)| (RpcService::Provider(provider as u64), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn quorum_is_a_strict_majority_under_equality() {
+        assert_eq!(quorum(&ConsensusStrategy::Equality, 3), 2);
+        assert_eq!(quorum(&ConsensusStrategy::Equality, 4), 3);
+    }
+
+    #[test]
+    fn quorum_follows_the_threshold_and_never_drops_to_zero() {
+        let threshold = |min| ConsensusStrategy::Threshold { min, total: None };
+        assert_eq!(quorum(&threshold(2), 5), 2);
+        assert_eq!(quorum(&threshold(0), 5), 1);
+    }
+
+    #[test]
+    fn agreed_returns_the_answer_shared_by_a_quorum() {
+        assert_eq!(agreed(&answers(&["a", "b", "a"]), 2), Some("a".to_string()));
+        assert_eq!(agreed(&answers(&["a", "b", "c"]), 2), None);
+    }
+
+    #[test]
+    fn agreed_rejects_a_tie_for_first_place() {
+        assert_eq!(agreed(&answers(&["a", "b", "a", "b"]), 2), None);
+        assert_eq!(agreed::<String>(&[], 1), None);
+    }
+
+    #[test]
+    fn median_takes_the_lower_middle_once_quorum_answered() {
+        assert_eq!(median(vec![30, 10, 20], 2), Some(20));
+        assert_eq!(median(vec![40, 10, 30, 20], 3), Some(20));
+        assert_eq!(median(vec![10], 2), None);
+        assert_eq!(median(Vec::<u64>::new(), 0), None);
+    }
+}
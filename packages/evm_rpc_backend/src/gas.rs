@@ -0,0 +1,352 @@
+use crate::chains::{get_chain, ChainConfig, GasPolicy};
+use crate::nonce;
+use crate::{
+    decode_h160, ensure_identity, estimate_transaction_fees, sign_with_next_nonce, CachedIdentity,
+    CallResult, FeeEstimates, STATE,
+};
+use candid::CandidType;
+use ethers_core::types::U256;
+use ethers_core::utils::hex;
+use ic_cdk::{query, update};
+use ic_cdk_timers::set_timer_interval;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const DEFERRAL_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+const DEFERRED_HANDLE_PREFIX: &str = "deferred-";
+
+/// What to do with a transaction while the network asks for more than the
+/// chain's `max_fee_per_gas_cap_wei`.
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum FeeCapMode {
+    /// Fail the call.
+    Reject,
+    /// Queue the transaction and sign it once fees fall back under the cap.
+    Defer,
+}
+
+/// Fees currently charged by the network, before any policy is applied.
+#[derive(Clone, Copy)]
+pub struct NetworkFees {
+    pub base_fee_per_gas: U256,
+    pub priority_fee_per_gas: U256,
+}
+
+pub struct FeeQuote {
+    /// Fees to sign with, already clamped to the cap.
+    pub fees: FeeEstimates,
+    /// `base fee + priority fee`: the least a transaction needs to be included.
+    pub required_max_fee_per_gas: U256,
+    pub above_cap: bool,
+}
+
+/// `max_fee_per_gas` leaves room for the base fee to grow by the policy's
+/// multiplier; both fields are clamped to the cap. The quote is only above
+/// the cap when even the current base fee plus tip exceeds it.
+pub fn quote(network: NetworkFees, policy: &GasPolicy) -> FeeQuote {
+    let priority_fee = network.priority_fee_per_gas;
+    let max_fee = network
+        .base_fee_per_gas
+        .saturating_mul(U256::from(policy.base_fee_multiplier_percent()))
+        .checked_div(U256::from(100))
+        .unwrap_or(U256::MAX)
+        .saturating_add(priority_fee);
+    let required_max_fee_per_gas = network.base_fee_per_gas.saturating_add(priority_fee);
+
+    match policy.max_fee_per_gas_cap_wei.map(U256::from) {
+        Some(cap) => FeeQuote {
+            fees: FeeEstimates {
+                max_fee_per_gas: max_fee.min(cap),
+                max_priority_fee_per_gas: priority_fee.min(cap),
+            },
+            required_max_fee_per_gas,
+            above_cap: required_max_fee_per_gas > cap,
+        },
+        None => FeeQuote {
+            fees: FeeEstimates {
+                max_fee_per_gas: max_fee,
+                max_priority_fee_per_gas: priority_fee,
+            },
+            required_max_fee_per_gas,
+            above_cap: false,
+        },
+    }
+}
+
+/// A transaction held back because the network was above the fee cap.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct DeferredTransaction {
+    pub id: u64,
+    /// Returned to the caller in place of a transaction hash; it resolves to
+    /// the real hash once the transaction is broadcast.
+    pub handle: String,
+    pub to: String,
+    pub value: String,
+    pub data: String,
+    pub gas: u64,
+    pub estimated_gas: Option<u64>,
+    pub deferred_at_ns: u64,
+    /// Network fee that triggered the deferral.
+    pub required_max_fee_per_gas: String,
+    pub last_error: Option<String>,
+}
+
+pub fn is_deferred_handle(tx_hash: &str) -> bool {
+    tx_hash.starts_with(DEFERRED_HANDLE_PREFIX)
+}
+
+pub fn is_deferred(chain_id: u64, handle: &str) -> bool {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .deferred_transactions
+            .get(&chain_id)
+            .is_some_and(|queue| queue.values().any(|tx| tx.handle == handle))
+    })
+}
+
+pub fn has_deferred(chain_id: u64) -> bool {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .deferred_transactions
+            .get(&chain_id)
+            .is_some_and(|queue| !queue.is_empty())
+    })
+}
+
+/// Queues a transaction and returns its handle.
+pub fn defer(
+    chain_id: u64,
+    to: String,
+    value: U256,
+    data: &[u8],
+    gas: u64,
+    estimated_gas: Option<u64>,
+    quote: &FeeQuote,
+) -> String {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let id = state.next_deferred_id;
+        state.next_deferred_id += 1;
+        let handle = format!("{}{}", DEFERRED_HANDLE_PREFIX, id);
+        state
+            .deferred_transactions
+            .entry(chain_id)
+            .or_default()
+            .insert(
+                id,
+                DeferredTransaction {
+                    id,
+                    handle: handle.clone(),
+                    to,
+                    value: value.to_string(),
+                    data: format!("0x{}", hex::encode(data)),
+                    gas,
+                    estimated_gas,
+                    deferred_at_ns: now,
+                    required_max_fee_per_gas: quote.required_max_fee_per_gas.to_string(),
+                    last_error: None,
+                },
+            );
+        handle
+    })
+}
+
+fn deferred_transactions(chain_id: u64) -> Vec<DeferredTransaction> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .deferred_transactions
+            .get(&chain_id)
+            .map(|queue| queue.values().cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+fn update_deferred(chain_id: u64, id: u64, f: impl FnOnce(&mut Option<DeferredTransaction>)) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if let Some(queue) = state.deferred_transactions.get_mut(&chain_id) {
+            let mut entry = queue.remove(&id);
+            f(&mut entry);
+            if let Some(entry) = entry {
+                queue.insert(id, entry);
+            }
+            if queue.is_empty() {
+                state.deferred_transactions.remove(&chain_id);
+            }
+        }
+    });
+}
+
+/// Broadcasts queued transactions in arrival order with `fees` and reports
+/// whether the queue is now empty. Callers must hold the signing lock and have
+/// checked that `fees` are under the cap.
+pub async fn flush_deferred(
+    identity: &CachedIdentity,
+    chain: &ChainConfig,
+    fees: FeeEstimates,
+) -> bool {
+    for deferred in deferred_transactions(chain.chain_id) {
+        let result = async {
+            let to = decode_h160(&deferred.to, "deferred 'to' address")?;
+            let value = U256::from_dec_str(&deferred.value)
+                .map_err(|e| format!("Invalid deferred value: {:?}", e))?;
+            let data = hex::decode(deferred.data.trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid deferred data: {}", e))?;
+            sign_with_next_nonce(
+                identity,
+                chain,
+                to,
+                value,
+                data,
+                U256::from(deferred.gas),
+                fees,
+            )
+            .await
+        }
+        .await;
+
+        match result {
+            Ok(tx_hash) => {
+                ic_cdk::println!("broadcast deferred {} as {}", deferred.handle, tx_hash);
+                nonce::record_promotion(chain.chain_id, &deferred.handle, &tx_hash);
+                update_deferred(chain.chain_id, deferred.id, |entry| *entry = None);
+            }
+            Err(err) => {
+                ic_cdk::println!("failed to broadcast deferred {}: {}", deferred.handle, err);
+                update_deferred(chain.chain_id, deferred.id, |entry| {
+                    if let Some(entry) = entry {
+                        entry.last_error = Some(err);
+                    }
+                });
+            }
+        }
+    }
+    !has_deferred(chain.chain_id)
+}
+
+pub fn start_deferral_timer() {
+    set_timer_interval(DEFERRAL_CHECK_INTERVAL, || {
+        ic_cdk::spawn(async {
+            let chain_ids: Vec<u64> = STATE.with(|state| {
+                state
+                    .borrow()
+                    .deferred_transactions
+                    .keys()
+                    .copied()
+                    .collect()
+            });
+            for chain_id in chain_ids {
+                if let Err(err) = check_deferred_on(chain_id).await {
+                    ic_cdk::println!("deferred check on chain {} failed: {}", chain_id, err);
+                }
+            }
+        })
+    });
+}
+
+async fn check_deferred_on(chain_id: u64) -> CallResult<()> {
+    let chain = get_chain(chain_id)?;
+    let identity = ensure_identity(None, chain.derivation_path.clone()).await?;
+    let _guard = nonce::acquire_signing_lock(chain_id).await;
+
+    let quote = estimate_transaction_fees(&chain).await?;
+    if !quote.above_cap {
+        flush_deferred(&identity, &chain, quote.fees).await;
+    }
+    Ok(())
+}
+
+#[query]
+pub fn list_deferred_transactions(chain_id: u64) -> Vec<DeferredTransaction> {
+    deferred_transactions(chain_id)
+}
+
+/// Abandons a deferred transaction. Callers polling its handle will see it as
+/// `NotFound`.
+#[update]
+pub fn drop_deferred_transaction(chain_id: u64, id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may drop deferred transactions".to_string());
+    }
+
+    let mut dropped = false;
+    update_deferred(chain_id, id, |entry| dropped = entry.take().is_some());
+    if dropped {
+        Ok(())
+    } else {
+        Err(format!(
+            "No deferred transaction {} on chain {}",
+            id, chain_id
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(base_fee_per_gas: u64, priority_fee_per_gas: u64) -> NetworkFees {
+        NetworkFees {
+            base_fee_per_gas: U256::from(base_fee_per_gas),
+            priority_fee_per_gas: U256::from(priority_fee_per_gas),
+        }
+    }
+
+    fn capped(cap: u64) -> GasPolicy {
+        GasPolicy {
+            max_fee_per_gas_cap_wei: Some(cap),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn quote_leaves_headroom_for_base_fee_growth() {
+        let quote = quote(network(100, 2), &GasPolicy::default());
+
+        assert_eq!(quote.fees.max_fee_per_gas, U256::from(202));
+        assert_eq!(quote.fees.max_priority_fee_per_gas, U256::from(2));
+        assert_eq!(quote.required_max_fee_per_gas, U256::from(102));
+        assert!(!quote.above_cap);
+    }
+
+    #[test]
+    fn quote_follows_the_base_fee_multiplier() {
+        let policy = GasPolicy {
+            base_fee_multiplier_percent: Some(150),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            quote(network(100, 2), &policy).fees.max_fee_per_gas,
+            U256::from(152)
+        );
+    }
+
+    #[test]
+    fn quote_clamps_headroom_to_the_cap_without_flagging_it() {
+        let quote = quote(network(100, 2), &capped(150));
+
+        assert_eq!(quote.fees.max_fee_per_gas, U256::from(150));
+        assert!(!quote.above_cap);
+    }
+
+    #[test]
+    fn quote_is_above_the_cap_once_base_fee_and_tip_exceed_it() {
+        let quote = quote(network(100, 2), &capped(101));
+
+        assert_eq!(quote.fees.max_fee_per_gas, U256::from(101));
+        assert_eq!(quote.required_max_fee_per_gas, U256::from(102));
+        assert!(quote.above_cap);
+    }
+
+    #[test]
+    fn deferred_handles_are_told_apart_from_transaction_hashes() {
+        assert!(is_deferred_handle("deferred-3"));
+        assert!(!is_deferred_handle("0xdeferred"));
+    }
+}
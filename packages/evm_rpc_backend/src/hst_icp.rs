@@ -0,0 +1,164 @@
+use crate::abi::{read_contract_values, AbiValue, ContractRead};
+use crate::chains::get_chain;
+use crate::roles::{ensure_permission, Permission};
+use crate::{ensure_identity, CallResult};
+use candid::CandidType;
+use ethers_core::utils::{hex, keccak256};
+use ic_cdk::update;
+use serde::{Deserialize, Serialize};
+
+/// On-chain state of a HelixStakedICP deployment relevant to minting.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct HstIcpStatus {
+    /// Total supply in wei (18 decimals).
+    pub total_supply: String,
+    /// This canister's signing address on the chain.
+    pub minter: String,
+    /// Whether `minter` holds `MINTER_ROLE`.
+    pub minter_has_role: bool,
+}
+
+fn minter_role() -> String {
+    format!("0x{}", hex::encode(keccak256("MINTER_ROLE")))
+}
+
+async fn read_single(
+    chain_id: u64,
+    contract: String,
+    function: &str,
+    args: Vec<AbiValue>,
+) -> CallResult<AbiValue> {
+    let mut values = read_contract_values(
+        chain_id,
+        ContractRead {
+            contract,
+            function: function.to_string(),
+            args,
+            block: None,
+        },
+    )
+    .await?;
+    match (values.pop(), values.is_empty()) {
+        (Some(value), true) => Ok(value),
+        _ => Err(format!("Expected a single return value from {}", function)),
+    }
+}
+
+async fn read_uint(
+    chain_id: u64,
+    contract: String,
+    function: &str,
+    args: Vec<AbiValue>,
+) -> CallResult<String> {
+    match read_single(chain_id, contract, function, args).await? {
+        AbiValue::Uint(value) => Ok(value),
+        other => Err(format!(
+            "Expected uint256 from {}, got {:?}",
+            function, other
+        )),
+    }
+}
+
+async fn has_minter_role(chain_id: u64, contract: String, account: String) -> CallResult<bool> {
+    match read_single(
+        chain_id,
+        contract,
+        "hasRole(bytes32,address) returns (bool)",
+        vec![
+            AbiValue::FixedBytes(minter_role()),
+            AbiValue::Address(account),
+        ],
+    )
+    .await?
+    {
+        AbiValue::Bool(flag) => Ok(flag),
+        other => Err(format!("Expected bool from hasRole, got {:?}", other)),
+    }
+}
+
+async fn canister_address(chain_id: u64) -> CallResult<String> {
+    let chain = get_chain(chain_id)?;
+    ensure_identity(None, chain.derivation_path)
+        .await
+        .map(|identity| identity.eth_address)
+}
+
+#[update]
+pub async fn hst_icp_total_supply(chain_id: u64, contract: String) -> CallResult<String> {
+    ensure_permission(Permission::ReadContract)?;
+    read_uint(
+        chain_id,
+        contract,
+        "totalSupply() returns (uint256)",
+        vec![],
+    )
+    .await
+}
+
+#[update]
+pub async fn hst_icp_balance_of(
+    chain_id: u64,
+    contract: String,
+    owner: String,
+) -> CallResult<String> {
+    ensure_permission(Permission::ReadContract)?;
+    read_uint(
+        chain_id,
+        contract,
+        "balanceOf(address) returns (uint256)",
+        vec![AbiValue::Address(owner)],
+    )
+    .await
+}
+
+#[update]
+pub async fn hst_icp_allowance(
+    chain_id: u64,
+    contract: String,
+    owner: String,
+    spender: String,
+) -> CallResult<String> {
+    ensure_permission(Permission::ReadContract)?;
+    read_uint(
+        chain_id,
+        contract,
+        "allowance(address,address) returns (uint256)",
+        vec![AbiValue::Address(owner), AbiValue::Address(spender)],
+    )
+    .await
+}
+
+/// Checks `MINTER_ROLE` for `account`, or for this canister's own signing
+/// address when unset.
+#[update]
+pub async fn hst_icp_has_minter_role(
+    chain_id: u64,
+    contract: String,
+    account: Option<String>,
+) -> CallResult<bool> {
+    ensure_permission(Permission::ReadContract)?;
+    let account = match account {
+        Some(account) => account,
+        None => canister_address(chain_id).await?,
+    };
+    has_minter_role(chain_id, contract, account).await
+}
+
+#[update]
+pub async fn hst_icp_status(chain_id: u64, contract: String) -> CallResult<HstIcpStatus> {
+    ensure_permission(Permission::ReadContract)?;
+    let minter = canister_address(chain_id).await?;
+    let total_supply = read_uint(
+        chain_id,
+        contract.clone(),
+        "totalSupply() returns (uint256)",
+        vec![],
+    )
+    .await?;
+    let minter_has_role = has_minter_role(chain_id, contract, minter.clone()).await?;
+    Ok(HstIcpStatus {
+        total_supply,
+        minter,
+        minter_has_role,
+    })
+}
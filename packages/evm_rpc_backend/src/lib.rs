@@ -1,19 +1,18 @@
 use candid::{CandidType, Principal};
-use ethers_core::abi::{Function, Param, ParamType, StateMutability, Token};
 use ethers_core::types::{
     transaction::eip1559::Eip1559TransactionRequest, Address, NameOrAddress, Signature, H160, U256,
     U64,
 };
 use ethers_core::utils::{hex, keccak256};
 use evm_rpc_canister_types::{
-    BlockTag, EvmRpcCanister, FeeHistoryArgs, FeeHistoryResult, GetTransactionCountArgs,
-    GetTransactionCountResult, GetTransactionReceiptResult, MultiFeeHistoryResult,
-    MultiGetTransactionCountResult, MultiGetTransactionReceiptResult,
-    MultiSendRawTransactionResult, RpcApi, RpcConfig, RpcServices, SendRawTransactionResult,
-    SendRawTransactionStatus, EVM_RPC,
+    BlockTag, CallArgs, CallResult as EthCallResult, FeeHistory, FeeHistoryArgs, FeeHistoryResult,
+    GetBlockByNumberResult, GetTransactionCountArgs, GetTransactionCountResult,
+    GetTransactionReceiptResult, MultiCallResult, MultiFeeHistoryResult,
+    MultiGetBlockByNumberResult, MultiGetTransactionCountResult, MultiGetTransactionReceiptResult,
+    MultiSendRawTransactionResult, RequestResult, SendRawTransactionResult,
+    SendRawTransactionStatus, TransactionReceipt, EVM_RPC,
 };
 use futures::channel::oneshot;
-use ic_cdk::api::call::call_with_payment128;
 use ic_cdk::api::call::RejectionCode;
 use ic_cdk::storage::{stable_restore, stable_save};
 use ic_cdk::{
@@ -24,27 +23,67 @@ use ic_cdk::{
     query, update,
 };
 use ic_cdk_timers::set_timer;
+use num_traits::ToPrimitive;
 use serde::{Deserialize, Serialize};
 use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 use std::time::Duration;
 
-const HELIX_VAULT_CANISTER_ID: &str = "b77ix-eeaaa-aaaaa-qaada-cai";
-const BRIDGE_CONTRACT_ADDRESS: &str = "0x272aEe5159a257359e84EAB3a6e3bd7F90b712EC";
+mod abi;
+mod allow_list;
+mod burns;
+mod chains;
+mod consensus;
+mod gas;
+mod hst_icp;
+mod nonce;
+mod replacement;
+mod roles;
+
+use abi::{submit_contract_call, AbiValue, ContractCall, ContractRead};
+use allow_list::AllowListEvent;
+use burns::{BurnScanCursor, DetectedBurn};
+use chains::{
+    get_chain, ChainConfig, GasPolicy, LegacyProviderConfig, LegacyRpcConfig,
+    LEGACY_BRIDGE_CONTRACT_ADDRESS,
+};
+use gas::{DeferredTransaction, FeeCapMode, FeeQuote, NetworkFees};
+use hst_icp::HstIcpStatus;
+use nonce::{NonceState, PendingTransaction};
+use replacement::ReplacementPolicy;
+use roles::{ensure_permission, InitArgs, Permission, Role, RoleGrant};
+
+// Only caller admitted besides controllers before the role table existed.
+const LEGACY_HELIX_VAULT_CANISTER_ID: &str = "b77ix-eeaaa-aaaaa-qaada-cai";
 const PROD_ECDSA_KEY_NAME: &str = "secp256k1";
 const LOCAL_ECDSA_KEY_NAME: &str = "dfx_test_key";
 const ECDSA_KEY_NAMES: &[&str] = &[PROD_ECDSA_KEY_NAME, LOCAL_ECDSA_KEY_NAME];
 const MAX_ECDSA_RETRIES: u8 = 5;
 const ECDSA_RETRY_BASE_DELAY_MS: u64 = 50;
-const MIN_PRIORITY_FEE_WEI: u64 = 1_000_000_000; // 1 gwei safety floor
+const ESTIMATE_GAS_MAX_RESPONSE_BYTES: u64 = 1_000;
+/// Prefix of errors for burns that are valid but not final yet; callers may
+/// retry them later.
+const PENDING_CONFIRMATIONS: &str = "Pending confirmations";
 
 thread_local! {
     static STATE: RefCell<CanisterState> = RefCell::new(CanisterState::default());
-    static CACHED_IDENTITY: RefCell<Option<CachedIdentity>> = RefCell::new(None);
+    static CACHED_IDENTITIES: RefCell<Vec<CachedIdentity>> = const { RefCell::new(Vec::new()) };
 }
 
 #[derive(Clone, Default)]
 struct CanisterState {
-    rpc_config: Option<RpcConfigState>,
+    chains: BTreeMap<u64, ChainConfig>,
+    nonce_states: BTreeMap<u64, NonceState>,
+    replacement_policy: ReplacementPolicy,
+    /// Normalized token contracts each chain may sign for or verify against.
+    allowed_contracts: BTreeMap<u64, BTreeSet<String>>,
+    allow_list_events: Vec<AllowListEvent>,
+    roles: BTreeMap<Principal, BTreeSet<Role>>,
+    deferred_transactions: BTreeMap<u64, BTreeMap<u64, DeferredTransaction>>,
+    next_deferred_id: u64,
+    burn_cursors: BTreeMap<u64, BurnScanCursor>,
+    detected_burns: Vec<DetectedBurn>,
+    next_burn_id: u64,
 }
 
 #[derive(Clone)]
@@ -56,40 +95,135 @@ struct CachedIdentity {
     eth_address: String,
 }
 
-#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
-pub struct RpcConfigState {
-    pub chain_id: u64,
-    pub rpc_url: String,
-}
-
 #[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
 pub struct BurnValidation {
     pub from: String,
     pub amount_wei: String,
     pub contract: String,
+    /// Account named by a `burnToICP` event. Plain burns name none and are
+    /// unlocked to whoever proves them.
+    pub recipient: Option<Principal>,
+    pub recipient_subaccount: Option<Vec<u8>>,
+    /// Index of the `BurnToICP` log within its block.
+    pub log_index: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TransactionStatus {
+    NotFound,
+    /// Broadcast by this canister (possibly re-signed) and not mined yet.
+    Pending,
+    Success,
+    Reverted,
+    /// The nonce was mined as a 0-value self-transfer instead.
+    Cancelled,
 }
 
-#[derive(Default, CandidType, Deserialize, Serialize)]
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct TransactionConfirmation {
+    pub tx_hash: String,
+    pub status: TransactionStatus,
+    pub block_number: Option<u64>,
+    pub confirmations: u64,
+    /// The chain's configured confirmation depth.
+    pub required_confirmations: u64,
+}
+
+#[derive(Default, CandidType, Deserialize)]
 struct StableState {
-    rpc_config: Option<RpcConfigState>,
+    // Single-chain fields from before the chain registry; only read.
+    rpc_config: Option<LegacyRpcConfig>,
+    rpc: Option<LegacyProviderConfig>,
+    nonce_state: Option<NonceState>,
+    chains: Option<BTreeMap<u64, ChainConfig>>,
+    nonce_states: Option<BTreeMap<u64, NonceState>>,
+    replacement_policy: Option<ReplacementPolicy>,
+    allowed_contracts: Option<BTreeMap<u64, BTreeSet<String>>>,
+    allow_list_events: Option<Vec<AllowListEvent>>,
+    roles: Option<BTreeMap<Principal, BTreeSet<Role>>>,
+    deferred_transactions: Option<BTreeMap<u64, BTreeMap<u64, DeferredTransaction>>>,
+    next_deferred_id: Option<u64>,
+    burn_cursors: Option<BTreeMap<u64, BurnScanCursor>>,
+    detected_burns: Option<Vec<DetectedBurn>>,
+    next_burn_id: Option<u64>,
 }
 
 impl From<CanisterState> for StableState {
     fn from(state: CanisterState) -> Self {
         StableState {
-            rpc_config: state.rpc_config,
+            rpc_config: None,
+            rpc: None,
+            nonce_state: None,
+            chains: Some(state.chains),
+            nonce_states: Some(state.nonce_states),
+            replacement_policy: Some(state.replacement_policy),
+            allowed_contracts: Some(state.allowed_contracts),
+            allow_list_events: Some(state.allow_list_events),
+            roles: Some(state.roles),
+            deferred_transactions: Some(state.deferred_transactions),
+            next_deferred_id: Some(state.next_deferred_id),
+            burn_cursors: Some(state.burn_cursors),
+            detected_burns: Some(state.detected_burns),
+            next_burn_id: Some(state.next_burn_id),
         }
     }
 }
 
 impl From<StableState> for CanisterState {
     fn from(state: StableState) -> Self {
+        let mut chains = state.chains.unwrap_or_default();
+        let mut nonce_states = state.nonce_states.unwrap_or_default();
+        let seed_allow_list = state.allowed_contracts.is_none();
+        let mut allowed_contracts = state.allowed_contracts.unwrap_or_default();
+        if let Some(legacy) = state.rpc.or(state.rpc_config.map(Into::into)) {
+            let chain_id = legacy.chain_id;
+            chains.entry(chain_id).or_insert_with(|| legacy.into());
+            if let Some(nonce_state) = state.nonce_state {
+                nonce_states.entry(chain_id).or_insert(nonce_state);
+            }
+        }
+        // Before the allow-list every chain accepted the one hard-coded bridge
+        // contract; keep that working until controllers curate the list.
+        if seed_allow_list {
+            for chain_id in chains.keys() {
+                allowed_contracts
+                    .entry(*chain_id)
+                    .or_default()
+                    .insert(LEGACY_BRIDGE_CONTRACT_ADDRESS.to_lowercase());
+            }
+        }
+
         CanisterState {
-            rpc_config: state.rpc_config,
+            chains,
+            nonce_states,
+            replacement_policy: state.replacement_policy.unwrap_or_default(),
+            allowed_contracts,
+            allow_list_events: state.allow_list_events.unwrap_or_default(),
+            roles: state.roles.unwrap_or_else(legacy_roles),
+            deferred_transactions: state.deferred_transactions.unwrap_or_default(),
+            next_deferred_id: state.next_deferred_id.unwrap_or_default(),
+            burn_cursors: state.burn_cursors.unwrap_or_default(),
+            detected_burns: state.detected_burns.unwrap_or_default(),
+            next_burn_id: state.next_burn_id.unwrap_or_default(),
         }
     }
 }
 
+// Keeps the vault that used to be hard-coded working across the upgrade.
+fn legacy_roles() -> BTreeMap<Principal, BTreeSet<Role>> {
+    Principal::from_text(LEGACY_HELIX_VAULT_CANISTER_ID)
+        .map(|vault| BTreeMap::from([(vault, BTreeSet::from([Role::Minter, Role::Verifier]))]))
+        .unwrap_or_default()
+}
+
+#[ic_cdk::init]
+fn init(args: Option<InitArgs>) {
+    roles::apply_init_args(args);
+    replacement::start_replacement_timer();
+    gas::start_deferral_timer();
+    burns::start_burn_scanner();
+}
+
 #[ic_cdk::pre_upgrade]
 fn pre_upgrade() {
     STATE.with(|state| {
@@ -97,11 +231,11 @@ fn pre_upgrade() {
         stable_save((stable,))
             .unwrap_or_else(|e| ic_cdk::trap(&format!("failed to persist state: {}", e)));
     });
-    CACHED_IDENTITY.with(|cache| cache.borrow_mut().take());
+    CACHED_IDENTITIES.with(|cache| cache.borrow_mut().clear());
 }
 
 #[ic_cdk::post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(args: Option<InitArgs>) {
     match stable_restore::<(StableState,)>() {
         Ok((stable,)) => {
             STATE.with(|state| {
@@ -112,30 +246,11 @@ fn post_upgrade() {
             STATE.with(|state| *state.borrow_mut() = CanisterState::default());
         }
     }
-    CACHED_IDENTITY.with(|cache| cache.borrow_mut().take());
-}
-
-fn ensure_authorized() -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if ic_cdk::api::is_controller(&caller) {
-        return Ok(());
-    }
-
-    let helix_vault = Principal::from_text(HELIX_VAULT_CANISTER_ID)
-        .map_err(|_| "Invalid HELIX_VAULT_CANISTER_ID constant".to_string())?;
-    if caller == helix_vault {
-        return Ok(());
-    }
-
-    Err(format!("Caller {} is not authorized", caller))
-}
-
-fn require_bridge_contract(input: &str) -> Result<(), String> {
-    if !input.eq_ignore_ascii_case(BRIDGE_CONTRACT_ADDRESS) {
-        Err("Unrecognized bridge contract address".to_string())
-    } else {
-        Ok(())
-    }
+    CACHED_IDENTITIES.with(|cache| cache.borrow_mut().clear());
+    roles::apply_init_args(args);
+    replacement::start_replacement_timer();
+    gas::start_deferral_timer();
+    burns::start_burn_scanner();
 }
 
 fn decode_h160(value: &str, context: &str) -> Result<H160, String> {
@@ -240,18 +355,23 @@ async fn ensure_identity_for_key(
     canister_id: Option<Principal>,
     derivation_path: Vec<Vec<u8>>,
 ) -> Result<CachedIdentity, String> {
-    if let Some(identity) = CACHED_IDENTITY.with(|cache| cache.borrow().clone()) {
-        if identity.key_id == key_id
-            && identity.canister_id == canister_id
-            && identity.derivation_path == derivation_path
-        {
-            return Ok(identity);
-        }
+    let cached = CACHED_IDENTITIES.with(|cache| {
+        cache
+            .borrow()
+            .iter()
+            .find(|identity| {
+                identity.key_id == key_id
+                    && identity.canister_id == canister_id
+                    && identity.derivation_path == derivation_path
+            })
+            .cloned()
+    });
+    if let Some(identity) = cached {
+        return Ok(identity);
     }
 
     let public_key =
-        fetch_public_key_with_retry(key_id.clone(), canister_id.clone(), derivation_path.clone())
-            .await?;
+        fetch_public_key_with_retry(key_id.clone(), canister_id, derivation_path.clone()).await?;
     let eth_address = pubkey_bytes_to_address(&public_key)?;
 
     let identity = CachedIdentity {
@@ -262,8 +382,8 @@ async fn ensure_identity_for_key(
         eth_address,
     };
 
-    CACHED_IDENTITY.with(|cache| {
-        *cache.borrow_mut() = Some(identity.clone());
+    CACHED_IDENTITIES.with(|cache| {
+        cache.borrow_mut().push(identity.clone());
     });
 
     Ok(identity)
@@ -291,9 +411,7 @@ async fn ensure_identity(
     let mut last_err: Option<String> = None;
 
     for key_id in ecdsa_key_candidates() {
-        match ensure_identity_for_key(key_id.clone(), canister_id.clone(), derivation_path.clone())
-            .await
-        {
+        match ensure_identity_for_key(key_id.clone(), canister_id, derivation_path.clone()).await {
             Ok(identity) => return Ok(identity),
             Err(err) if is_unknown_key_error(&err) => {
                 last_err = Some(err);
@@ -305,50 +423,6 @@ async fn ensure_identity(
     Err(last_err.unwrap_or_else(|| "Failed to resolve ECDSA identity".to_string()))
 }
 
-#[update]
-pub fn set_rpc_config(chain_id: u64, rpc_url: String) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
-    if !ic_cdk::api::is_controller(&caller) {
-        return Err("Only controllers may update RPC configuration".to_string());
-    }
-
-    STATE.with(|cfg| {
-        cfg.borrow_mut().rpc_config = Some(RpcConfigState { chain_id, rpc_url });
-    });
-
-    Ok(())
-}
-
-#[query]
-pub fn get_rpc_config() -> Option<RpcConfigState> {
-    STATE.with(|cfg| cfg.borrow().rpc_config.clone())
-}
-
-#[derive(Clone)]
-struct RpcContext {
-    services: RpcServices,
-    chain_id: u64,
-}
-
-fn get_rpc_context() -> Result<RpcContext, String> {
-    STATE.with(|cfg| {
-        cfg.borrow()
-            .rpc_config
-            .clone()
-            .map(|config| RpcContext {
-                chain_id: config.chain_id,
-                services: RpcServices::Custom {
-                    chainId: config.chain_id,
-                    services: vec![RpcApi {
-                        url: config.rpc_url,
-                        headers: None,
-                    }],
-                },
-            })
-            .ok_or("RPC config not set".to_string())
-    })
-}
-
 #[derive(CandidType, serde::Serialize, serde::Deserialize)]
 pub struct TransferArgs {
     to: String,       // Hex-encoded address
@@ -362,6 +436,20 @@ pub struct SignedTransaction {
     pub tx_hash: String,
 }
 
+/// Result of every signing endpoint.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct SubmittedTransaction {
+    pub tx_hash: String,
+    /// Gas limit the transaction was signed with.
+    pub gas_limit: u64,
+    /// Raw `eth_estimateGas` answer; `None` when the caller fixed the limit.
+    pub estimated_gas: Option<u64>,
+    /// Held back by the fee cap. `tx_hash` is then a placeholder that
+    /// `get_transaction_confirmations` reports as `Pending` until broadcast.
+    pub deferred: bool,
+}
+
+#[derive(Clone, Copy)]
 struct FeeEstimates {
     max_fee_per_gas: U256,
     max_priority_fee_per_gas: U256,
@@ -475,627 +563,1174 @@ fn y_parity(prehash: &[u8], sig: &[u8], pubkey: &[u8]) -> Result<u64, String> {
 }
 
 #[update]
-pub async fn get_canister_eth_address() -> Result<String, String> {
-    ensure_authorized()?;
-    ensure_identity(None, vec![])
+pub async fn get_canister_eth_address(chain_id: u64) -> Result<String, String> {
+    ensure_permission(Permission::ReadAddress)?;
+    let chain = get_chain(chain_id)?;
+    ensure_identity(None, chain.derivation_path)
         .await
         .map(|identity| identity.eth_address)
 }
 
-async fn estimate_transaction_fees(
-    block_count: u64,
-    rpc_services: RpcServices,
-    evm_rpc: EvmRpcCanister,
-) -> CallResult<FeeEstimates> {
+fn nat_to_u256(nat: &candid::Nat, context: &str) -> CallResult<U256> {
+    U256::from_str_radix(&nat_to_hex(nat), 16)
+        .map_err(|e| format!("Failed to parse {}: {:?}", context, e))
+}
+
+/// Base fee of the next block and the median of the sampled priority-fee
+/// percentile across the fee history window.
+fn network_fees_from_history(
+    fee_history: &FeeHistory,
+    min_priority_fee_wei: u64,
+) -> CallResult<NetworkFees> {
+    let base_fee_per_gas = fee_history
+        .baseFeePerGas
+        .last()
+        .ok_or_else(|| "Fee history did not include a base fee".to_string())
+        .and_then(|base_fee| nat_to_u256(base_fee, "base fee"))?;
+
+    let rewards = fee_history
+        .reward
+        .iter()
+        .filter_map(|block| block.first())
+        .map(|reward| nat_to_u256(reward, "reward"))
+        .collect::<CallResult<Vec<U256>>>()?;
+    let priority_fee_per_gas = consensus::median(rewards, 1)
+        .unwrap_or_default()
+        .max(U256::from(min_priority_fee_wei));
+
+    Ok(NetworkFees {
+        base_fee_per_gas,
+        priority_fee_per_gas,
+    })
+}
+
+async fn fetch_network_fees(chain: &ChainConfig) -> CallResult<NetworkFees> {
+    let policy = &chain.gas_policy;
     let args = FeeHistoryArgs {
-        blockCount: block_count.into(),
+        blockCount: policy.fee_history_blocks.into(),
         newestBlock: BlockTag::Latest,
-        rewardPercentiles: Some(vec![20u8].into()),
+        rewardPercentiles: Some(vec![policy.reward_percentile].into()),
     };
-    let (result,) = evm_rpc
-        .eth_fee_history(rpc_services.clone(), None, args, 10_000_000_000)
+    let (result,) = EVM_RPC
+        .eth_fee_history(chain.services(), chain.rpc_config(), args, 10_000_000_000)
         .await
         .map_err(|e| format!("Failed to get fee history: {:?}", e))?;
 
     match result {
         MultiFeeHistoryResult::Consistent(r) => match r {
             FeeHistoryResult::Ok(fee_history) => {
-                // Define a default Nat value with a longer lifetime
-                let default_base_fee = candid::Nat::from(10_000_000_000u64);
-                let base_fee_nat = fee_history
-                    .baseFeePerGas
-                    .last()
-                    .unwrap_or(&default_base_fee);
-                ic_cdk::println!("base_fee_nat: {:?}", base_fee_nat);
-                let base_fee_str = nat_to_hex(base_fee_nat);
-                ic_cdk::println!("base_fee_str: {}", base_fee_str);
-                let base_fee = U256::from_str_radix(&base_fee_str, 16)
-                    .map_err(|e| format!("Failed to parse base_fee: {:?}", e))?;
-
-                // Parse reward (already correctly handled with a Vec)
-                let default_reward = vec![candid::Nat::from(1_000_000_000u64)];
-                let reward_vec = fee_history.reward.last().unwrap_or(&default_reward);
-                ic_cdk::println!("reward_vec[0]: {:?}", reward_vec[0]);
-                let reward_str = nat_to_hex(&reward_vec[0]);
-                ic_cdk::println!("reward_str: {}", reward_str);
-                let mut reward = U256::from_str_radix(&reward_str, 16)
-                    .map_err(|e| format!("Failed to parse reward: {:?}", e))?;
-
-                let min_priority_fee = U256::from(MIN_PRIORITY_FEE_WEI);
-                if reward < min_priority_fee {
-                    ic_cdk::println!(
-                        "priority fee {} below minimum {}; overriding",
-                        reward,
-                        MIN_PRIORITY_FEE_WEI
-                    );
-                    reward = min_priority_fee;
-                }
-
-                let mut max_fee_per_gas = base_fee.checked_add(reward).unwrap_or_else(|| U256::MAX);
-                if max_fee_per_gas < reward {
-                    max_fee_per_gas = reward;
-                }
-
-                Ok(FeeEstimates {
-                    max_fee_per_gas,
-                    max_priority_fee_per_gas: reward,
-                })
+                network_fees_from_history(&fee_history, policy.min_priority_fee_wei)
             }
             FeeHistoryResult::Err(err) => Err(format!("Fee history error: {:?}", err)),
         },
-        MultiFeeHistoryResult::Inconsistent(_) => {
-            Err("Inconsistent fee history results".to_string())
+        // Fee history differs by a block or two between providers; take the
+        // median of each fee so one provider cannot inflate or starve them.
+        MultiFeeHistoryResult::Inconsistent(responses) => {
+            let samples: Vec<NetworkFees> = responses
+                .iter()
+                .filter_map(|(_, result)| match result {
+                    FeeHistoryResult::Ok(fee_history) => {
+                        network_fees_from_history(fee_history, policy.min_priority_fee_wei).ok()
+                    }
+                    FeeHistoryResult::Err(_) => None,
+                })
+                .collect();
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            let base_fee_per_gas = consensus::median(
+                samples.iter().map(|fees| fees.base_fee_per_gas).collect(),
+                quorum,
+            );
+            let priority_fee_per_gas = consensus::median(
+                samples
+                    .iter()
+                    .map(|fees| fees.priority_fee_per_gas)
+                    .collect(),
+                quorum,
+            );
+            match (base_fee_per_gas, priority_fee_per_gas) {
+                (Some(base_fee_per_gas), Some(priority_fee_per_gas)) => Ok(NetworkFees {
+                    base_fee_per_gas,
+                    priority_fee_per_gas,
+                }),
+                _ => Err(format!(
+                    "Inconsistent fee history results: {} of {} providers answered",
+                    samples.len(),
+                    responses.len()
+                )),
+            }
         }
     }
 }
 
+/// Prices a transaction under the chain's gas policy.
+async fn estimate_transaction_fees(chain: &ChainConfig) -> CallResult<FeeQuote> {
+    let network = fetch_network_fees(chain).await?;
+    Ok(gas::quote(network, &chain.gas_policy))
+}
+
+enum SendRawError {
+    NonceTooLow(TransactionHash),
+    NonceTooHigh(TransactionHash),
+    Rejected(String),
+}
+
+impl SendRawError {
+    fn into_message(self) -> String {
+        match self {
+            SendRawError::NonceTooLow(tx_hash) => {
+                format!("RPC rejected transaction {}: nonce too low", tx_hash)
+            }
+            SendRawError::NonceTooHigh(tx_hash) => {
+                format!("RPC rejected transaction {}: nonce too high", tx_hash)
+            }
+            SendRawError::Rejected(message) => message,
+        }
+    }
+}
+
+fn send_raw_result(
+    result: SendRawTransactionResult,
+    tx: &SignedTransaction,
+) -> Result<TransactionHash, SendRawError> {
+    match result {
+        SendRawTransactionResult::Ok(status) => match status {
+            SendRawTransactionStatus::Ok(provider_hash) => {
+                let hash = provider_hash.unwrap_or_else(|| tx.tx_hash.clone());
+                ic_cdk::println!("Transaction hash: {}", hash);
+                Ok(hash)
+            }
+            SendRawTransactionStatus::NonceTooLow => {
+                Err(SendRawError::NonceTooLow(tx.tx_hash.clone()))
+            }
+            SendRawTransactionStatus::NonceTooHigh => {
+                Err(SendRawError::NonceTooHigh(tx.tx_hash.clone()))
+            }
+            SendRawTransactionStatus::InsufficientFunds => Err(SendRawError::Rejected(format!(
+                "RPC rejected transaction {}: insufficient funds for gas",
+                tx.tx_hash
+            ))),
+        },
+        SendRawTransactionResult::Err(err) => Err(SendRawError::Rejected(format!(
+            "RPC provider returned error for {}: {:?}",
+            tx.tx_hash, err
+        ))),
+    }
+}
+
 async fn send_raw_transaction(
     tx: SignedTransaction,
-    rpc_services: RpcServices,
-    evm_rpc: EvmRpcCanister,
-) -> CallResult<TransactionHash> {
+    chain: &ChainConfig,
+) -> Result<TransactionHash, SendRawError> {
     let cycles = 10_000_000_000;
 
-    match evm_rpc
-        .eth_send_raw_transaction(rpc_services, None, tx.tx_hex.clone(), cycles)
+    match EVM_RPC
+        .eth_send_raw_transaction(
+            chain.services(),
+            chain.rpc_config(),
+            tx.tx_hex.clone(),
+            cycles,
+        )
         .await
     {
-        Ok((MultiSendRawTransactionResult::Consistent(result),)) => match result {
-            SendRawTransactionResult::Ok(status) => match status {
-                SendRawTransactionStatus::Ok(provider_hash) => {
-                    let hash = provider_hash.unwrap_or_else(|| tx.tx_hash.clone());
-                    ic_cdk::println!("Transaction hash: {}", hash);
-                    Ok(hash)
-                }
-                SendRawTransactionStatus::NonceTooLow => Err(format!(
-                    "RPC rejected transaction {}: nonce too low",
-                    tx.tx_hash
-                )),
-                SendRawTransactionStatus::NonceTooHigh => Err(format!(
-                    "RPC rejected transaction {}: nonce too high",
-                    tx.tx_hash
-                )),
-                SendRawTransactionStatus::InsufficientFunds => Err(format!(
-                    "RPC rejected transaction {}: insufficient funds for gas",
-                    tx.tx_hash
-                )),
-            },
-            SendRawTransactionResult::Err(err) => Err(format!(
-                "RPC provider returned error for {}: {:?}",
-                tx.tx_hash, err
-            )),
-        },
-        Ok((MultiSendRawTransactionResult::Inconsistent(responses),)) => Err(format!(
-            "Inconsistent sendRawTransaction responses for {}: {:?}",
-            tx.tx_hash, responses
-        )),
-        Err(e) => Err(format!("RPC call failed: {:?}", e)),
+        Ok((MultiSendRawTransactionResult::Consistent(result),)) => send_raw_result(result, &tx),
+        // The signed payload is identical everywhere, so one provider accepting
+        // it is enough; otherwise report the status most providers agree on.
+        Ok((MultiSendRawTransactionResult::Inconsistent(responses),)) => {
+            let accepted = responses.iter().any(|(_, result)| {
+                matches!(
+                    result,
+                    SendRawTransactionResult::Ok(SendRawTransactionStatus::Ok(_))
+                )
+            });
+            if accepted {
+                ic_cdk::println!("Transaction hash: {}", tx.tx_hash);
+                return Ok(tx.tx_hash);
+            }
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            match consensus::agreed(&responses, quorum) {
+                Some(result) => send_raw_result(result, &tx),
+                None => Err(SendRawError::Rejected(format!(
+                    "Inconsistent sendRawTransaction responses for {}: {:?}",
+                    tx.tx_hash, responses
+                ))),
+            }
+        }
+        Err(e) => Err(SendRawError::Rejected(format!("RPC call failed: {:?}", e))),
     }
 }
 
-#[update]
-pub async fn transfer_eth(transfer_args: TransferArgs, nonce: u64) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    let to_bytes = hex::decode(
-        transfer_args
-            .to
-            .strip_prefix("0x")
-            .unwrap_or(&transfer_args.to),
-    )
-    .map_err(|e| format!("Invalid 'to' address: {:?}", e))?;
-    let to = Some(NameOrAddress::Address(Address::from_slice(&to_bytes)));
+async fn get_nonce(address: String, block: BlockTag, chain: &ChainConfig) -> Result<u64, String> {
+    let args = GetTransactionCountArgs { address, block };
 
-    let value =
-        U256::from_dec_str(&transfer_args.value).map_err(|e| format!("Invalid value: {:?}", e))?;
+    let (result,) = EVM_RPC
+        .eth_get_transaction_count(chain.services(), chain.rpc_config(), args, 10_000_000_000)
+        .await
+        .map_err(|e| format!("Failed to fetch nonce: {:?}", e))?;
 
-    let gas = transfer_args
-        .gas
-        .map(U256::from)
-        .unwrap_or(U256::from(21000));
+    let inner = match result {
+        MultiGetTransactionCountResult::Consistent(inner) => inner,
+        // A wrong count either reuses a nonce or leaves a gap that stalls the
+        // queue, so only a quorum answer is accepted.
+        MultiGetTransactionCountResult::Inconsistent(responses) => {
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            consensus::agreed(&responses, quorum)
+                .ok_or_else(|| "Inconsistent nonce result from provider.".to_string())?
+        }
+    };
 
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
+    match inner {
+        GetTransactionCountResult::Ok(nonce_candid_nat) => nat_to_u64(&nonce_candid_nat, "nonce"),
+        GetTransactionCountResult::Err(err) => Err(format!("Error fetching nonce: {:?}", err)),
+    }
+}
 
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
+async fn resync_nonce_from_provider(address: &str, chain: &ChainConfig) -> CallResult<u64> {
+    let pending = get_nonce(address.to_string(), BlockTag::Pending, chain).await?;
+    let mined = get_nonce(address.to_string(), BlockTag::Latest, chain).await?;
+    nonce::apply_resync(chain.chain_id, pending, mined);
+    nonce::next_nonce(chain.chain_id)
+        .ok_or_else(|| "Nonce resync did not produce a nonce".to_string())
+}
 
-    let evm_rpc = EVM_RPC;
+fn nat_to_u64(nat: &candid::Nat, context: &str) -> Result<u64, String> {
+    nat.0
+        .to_u64()
+        .ok_or_else(|| format!("{} does not fit in u64: {}", context, nat))
+}
 
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone())
+async fn get_latest_block_number(chain: &ChainConfig) -> CallResult<u64> {
+    let (result,) = EVM_RPC
+        .eth_get_block_by_number(
+            chain.services(),
+            chain.rpc_config(),
+            BlockTag::Latest,
+            10_000_000_000,
+        )
         .await
-        .map_err(|e| format!("Fee estimation failed: {:?}", e))?;
+        .map_err(|e| format!("Failed to fetch latest block: {:?}", e))?;
 
-    let tx = Eip1559TransactionRequest {
-        from: None,
-        to,
-        value: Some(value),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(gas),
-        nonce: Some(U256::from(nonce)),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Default::default(),
-        access_list: Default::default(),
-    };
+    match result {
+        MultiGetBlockByNumberResult::Consistent(GetBlockByNumberResult::Ok(block)) => {
+            nat_to_u64(&block.number, "block number")
+        }
+        MultiGetBlockByNumberResult::Consistent(GetBlockByNumberResult::Err(err)) => {
+            Err(format!("Error fetching latest block: {:?}", err))
+        }
+        // Providers routinely sit a block apart at the head; the lower median
+        // keeps confirmation counts conservative.
+        MultiGetBlockByNumberResult::Inconsistent(responses) => {
+            let numbers: Vec<u64> = responses
+                .iter()
+                .filter_map(|(_, result)| match result {
+                    GetBlockByNumberResult::Ok(block) => block.number.0.to_u64(),
+                    GetBlockByNumberResult::Err(_) => None,
+                })
+                .collect();
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            consensus::median(numbers, quorum)
+                .ok_or_else(|| "Inconsistent latest block result from provider.".to_string())
+        }
+    }
+}
 
-    let tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
+/// Collapses a multi-provider receipt answer. Receipts gate minting and
+/// unlocking, so `Inconsistent` answers are only accepted with a quorum.
+async fn fetch_receipt(
+    tx_hash: &str,
+    chain: &ChainConfig,
+) -> CallResult<Option<TransactionReceipt>> {
+    let (result,) = EVM_RPC
+        .eth_get_transaction_receipt(
+            chain.services(),
+            chain.rpc_config(),
+            tx_hash.to_string(),
+            10_000_000_000,
+        )
+        .await
+        .map_err(|e| format!("Call failed: {:?}", e))?;
+
+    let result = match result {
+        MultiGetTransactionReceiptResult::Consistent(result) => result,
+        MultiGetTransactionReceiptResult::Inconsistent(responses) => {
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            consensus::agreed(&responses, quorum).ok_or_else(|| {
+                format!(
+                    "Inconsistent result from RPC: no {} of {} providers agree on receipt {}",
+                    quorum,
+                    responses.len(),
+                    tx_hash
+                )
+            })?
+        }
+    };
 
-    send_raw_transaction(tx, rpc_services, evm_rpc).await
+    match result {
+        GetTransactionReceiptResult::Ok(receipt) => Ok(receipt),
+        GetTransactionReceiptResult::Err(err) => Err(format!("Transaction error: {:?}", err)),
+    }
 }
 
-async fn get_nonce(
-    address: String,
-    rpc_services: RpcServices,
-    evm_rpc: EvmRpcCanister,
-) -> Result<U256, String> {
-    let args = GetTransactionCountArgs {
-        address,
-        block: BlockTag::Latest,
+/// Hash of the canonical block at `block_number`, under the same quorum as
+/// receipts.
+async fn block_hash_at(chain: &ChainConfig, block_number: u64) -> CallResult<String> {
+    let (result,) = EVM_RPC
+        .eth_get_block_by_number(
+            chain.services(),
+            chain.rpc_config(),
+            BlockTag::Number(candid::Nat::from(block_number)),
+            10_000_000_000,
+        )
+        .await
+        .map_err(|e| format!("Failed to fetch block {}: {:?}", block_number, e))?;
+
+    let hash = match result {
+        MultiGetBlockByNumberResult::Consistent(GetBlockByNumberResult::Ok(block)) => block.hash,
+        MultiGetBlockByNumberResult::Consistent(GetBlockByNumberResult::Err(err)) => {
+            return Err(format!("Error fetching block {}: {:?}", block_number, err))
+        }
+        MultiGetBlockByNumberResult::Inconsistent(responses) => {
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            let hashes: Vec<_> = responses
+                .into_iter()
+                .map(|(service, result)| match result {
+                    GetBlockByNumberResult::Ok(block) => (service, Some(block.hash)),
+                    GetBlockByNumberResult::Err(_) => (service, None),
+                })
+                .collect();
+            consensus::agreed(&hashes, quorum)
+                .flatten()
+                .ok_or_else(|| {
+                    format!(
+                        "Inconsistent result from RPC: no {} of {} providers agree on block {}",
+                        quorum,
+                        hashes.len(),
+                        block_number
+                    )
+                })?
+        }
     };
+    Ok(hash.to_lowercase())
+}
+
+/// The block holding a transaction counts as its first confirmation. A
+/// provider lagging behind `block_number` yields none.
+fn ensure_burn_confirmations(
+    chain: &ChainConfig,
+    tx_hash: &str,
+    block_number: u64,
+    latest_block: u64,
+) -> CallResult<()> {
+    let confirmations = (latest_block + 1).saturating_sub(block_number);
+    let required = chain.burn_confirmations();
+    if confirmations < required {
+        return Err(format!(
+            "{}: transaction {} has {} of {} confirmations",
+            PENDING_CONFIRMATIONS, tx_hash, confirmations, required
+        ));
+    }
+    Ok(())
+}
+
+/// Accepts `receipt` as the basis of an unlock only once it succeeded, has
+/// the chain's burn confirmations and its block is still canonical.
+async fn ensure_final_receipt(
+    chain: &ChainConfig,
+    tx_hash: &str,
+    receipt: &TransactionReceipt,
+) -> CallResult<()> {
+    if receipt.status.as_ref().is_some_and(|status| *status == 0u8) {
+        return Err(format!("Transaction {} reverted", tx_hash));
+    }
+
+    let block_number = nat_to_u64(&receipt.blockNumber, "receipt block number")?;
+    let latest_block = get_latest_block_number(chain).await?;
+    ensure_burn_confirmations(chain, tx_hash, block_number, latest_block)?;
+
+    // A provider can still serve a receipt from a block that was since
+    // replaced.
+    if block_hash_at(chain, block_number).await? != receipt.blockHash.to_lowercase() {
+        return Err(format!(
+            "Transaction {} is no longer in block {}; it may be mined again",
+            tx_hash, block_number
+        ));
+    }
+    Ok(())
+}
 
-    let (result,) = evm_rpc
-        .eth_get_transaction_count(rpc_services.clone(), None, args, 10_000_000_000)
+/// Runs `eth_call` against `to` with `data` and returns the raw return data.
+/// `Inconsistent` answers need a quorum like receipts do.
+async fn eth_call(
+    chain: &ChainConfig,
+    to: &str,
+    data: &[u8],
+    block: Option<BlockTag>,
+) -> CallResult<Vec<u8>> {
+    let args = CallArgs {
+        transaction: evm_rpc_canister_types::TransactionRequest {
+            to: Some(to.to_string()),
+            input: Some(format!("0x{}", hex::encode(data))),
+            ..Default::default()
+        },
+        block,
+    };
+    let (result,) = EVM_RPC
+        .eth_call(chain.services(), chain.rpc_config(), args, 10_000_000_000)
         .await
-        .map_err(|e| format!("Failed to fetch nonce: {:?}", e))?;
+        .map_err(|e| format!("Call failed: {:?}", e))?;
+
+    let result = match result {
+        MultiCallResult::Consistent(result) => result,
+        MultiCallResult::Inconsistent(responses) => {
+            let quorum = consensus::quorum(&chain.consensus, responses.len());
+            consensus::agreed(&responses, quorum).ok_or_else(|| {
+                format!(
+                    "Inconsistent result from RPC: no {} of {} providers agree on eth_call to {}",
+                    quorum,
+                    responses.len(),
+                    to
+                )
+            })?
+        }
+    };
 
     match result {
-        MultiGetTransactionCountResult::Consistent(inner) => match inner {
-            GetTransactionCountResult::Ok(nonce_candid_nat) => {
-                let hex_str = nat_to_hex(&nonce_candid_nat);
-                U256::from_str_radix(&hex_str, 16)
-                    .map_err(|e| format!("Failed to parse nonce: {:?}", e))
-            }
-            GetTransactionCountResult::Err(err) => Err(format!("Error fetching nonce: {:?}", err)),
-        },
-        _ => Err("Inconsistent nonce result from provider.".to_string()),
+        EthCallResult::Ok(output) => hex::decode(output.trim_start_matches("0x"))
+            .map_err(|e| format!("Invalid eth_call output: {}", e)),
+        EthCallResult::Err(err) => Err(format!("eth_call error: {:?}", err)),
     }
 }
 
-#[update]
-pub async fn approve_erc20(
-    contract_address: String,
-    spender: String,
-    amount: String,
-) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    require_bridge_contract(&contract_address)?;
-
-    let contract_addr = decode_h160(&contract_address, "contract")?;
-    let spender_addr = decode_h160(&spender, "spender")?;
-    let amount_u256 = U256::from_dec_str(&amount).map_err(|e| format!("Invalid amount: {}", e))?;
-
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
-
-    let evm_rpc = EVM_RPC;
-
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
-    let sender = identity.eth_address.clone();
-    let nonce = get_nonce(sender.clone(), rpc_services.clone(), evm_rpc.clone()).await?;
-
-    let approve_fn = Function {
-        name: "approve".to_string(),
-        inputs: vec![
-            Param {
-                name: "spender".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            },
-            Param {
-                name: "amount".to_string(),
-                kind: ParamType::Uint(256),
-                internal_type: None,
-            },
-        ],
-        outputs: vec![],
-        constant: Some(false),
-        state_mutability: StateMutability::NonPayable,
-    };
+/// A canister-signed transaction before signing; kept in the pending queue so
+/// the same nonce can be re-signed later with different fees.
+struct OutgoingTransaction {
+    nonce: u64,
+    to: Address,
+    value: U256,
+    data: Vec<u8>,
+    gas: U256,
+    fees: FeeEstimates,
+}
 
-    let data = approve_fn
-        .encode_input(&[Token::Address(spender_addr), Token::Uint(amount_u256)])
-        .map_err(|e| format!("Failed to encode data: {}", e))?;
+impl OutgoingTransaction {
+    fn from_pending(tx: &PendingTransaction, fees: FeeEstimates) -> Result<Self, String> {
+        Ok(OutgoingTransaction {
+            nonce: tx.nonce,
+            to: decode_h160(&tx.to, "pending 'to' address")?,
+            value: U256::from_dec_str(&tx.value)
+                .map_err(|e| format!("Invalid pending value: {:?}", e))?,
+            data: hex::decode(tx.data.trim_start_matches("0x"))
+                .map_err(|e| format!("Invalid pending data: {}", e))?,
+            gas: U256::from(tx.gas),
+            fees,
+        })
+    }
 
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone()).await?;
+    fn into_pending(self, tx_hash: TransactionHash, replacements: u32) -> PendingTransaction {
+        PendingTransaction {
+            nonce: self.nonce,
+            tx_hash,
+            to: format!("{:?}", self.to),
+            value: self.value.to_string(),
+            data: format!("0x{}", hex::encode(&self.data)),
+            gas: self.gas.as_u64(),
+            max_fee_per_gas: self.fees.max_fee_per_gas.to_string(),
+            max_priority_fee_per_gas: self.fees.max_priority_fee_per_gas.to_string(),
+            submitted_at_ns: ic_cdk::api::time(),
+            replacements,
+            first_seen_block: None,
+        }
+    }
+}
 
-    let tx = Eip1559TransactionRequest {
+async fn sign_and_send(
+    identity: &CachedIdentity,
+    chain: &ChainConfig,
+    tx: &OutgoingTransaction,
+) -> Result<TransactionHash, SendRawError> {
+    let request = Eip1559TransactionRequest {
         from: None,
-        to: Some(NameOrAddress::Address(contract_addr)),
-        value: Some(U256::zero()),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(U256::from(300_000)),
-        nonce: Some(nonce),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Some(data.into()),
+        to: Some(NameOrAddress::Address(tx.to)),
+        value: Some(tx.value),
+        max_fee_per_gas: Some(tx.fees.max_fee_per_gas),
+        max_priority_fee_per_gas: Some(tx.fees.max_priority_fee_per_gas),
+        gas: Some(tx.gas),
+        nonce: Some(U256::from(tx.nonce)),
+        chain_id: Some(U64::from(chain.chain_id)),
+        data: Some(tx.data.clone().into()),
         access_list: Default::default(),
     };
 
-    let signed_tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
-    send_raw_transaction(signed_tx, rpc_services, evm_rpc).await
+    let signed_tx = sign_eip1559_transaction(
+        request,
+        identity.key_id.clone(),
+        identity.derivation_path.clone(),
+    )
+    .await
+    .map_err(SendRawError::Rejected)?;
+    send_raw_transaction(signed_tx, chain).await
 }
 
-#[update]
-pub async fn transfer_from_erc20(
-    contract_address: String,
-    from: String,
-    to: String,
-    amount: String,
-) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    require_bridge_contract(&contract_address)?;
-
-    let contract_addr = decode_h160(&contract_address, "contract")?;
-    let from_addr = decode_h160(&from, "from")?;
-    let to_addr = decode_h160(&to, "to")?;
-    let amount_u256 = U256::from_dec_str(&amount).map_err(|e| format!("Invalid amount: {}", e))?;
-
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
-
-    let evm_rpc = EVM_RPC;
-
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
-    let sender = identity.eth_address.clone();
-    let nonce = get_nonce(sender.clone(), rpc_services.clone(), evm_rpc.clone()).await?;
-
-    let transfer_from_fn = Function {
-        name: "transferFrom".to_string(),
-        inputs: vec![
-            Param {
-                name: "from".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            },
-            Param {
-                name: "to".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            },
-            Param {
-                name: "amount".to_string(),
-                kind: ParamType::Uint(256),
-                internal_type: None,
-            },
-        ],
-        outputs: vec![],
-        constant: Some(false),
-        state_mutability: StateMutability::NonPayable,
-    };
-    let data = transfer_from_fn
-        .encode_input(&[
-            Token::Address(from_addr),
-            Token::Address(to_addr),
-            Token::Uint(amount_u256),
-        ])
-        .map_err(|e| format!("Failed to encode data: {}", e))?;
-
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone()).await?;
-
-    let tx = Eip1559TransactionRequest {
-        from: None,
-        to: Some(NameOrAddress::Address(contract_addr)),
-        value: Some(U256::zero()),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(U256::from(300_000)),
-        nonce: Some(nonce),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Some(data.into()),
-        access_list: Default::default(),
+fn parse_estimate_gas(result: RequestResult) -> CallResult<U256> {
+    let body = match result {
+        RequestResult::Ok(body) => body,
+        RequestResult::Err(err) => return Err(format!("{:?}", err)),
     };
+    let response: serde_json::Value =
+        serde_json::from_str(&body).map_err(|e| format!("Invalid JSON-RPC response: {}", e))?;
+    if let Some(error) = response.get("error") {
+        return Err(error.to_string());
+    }
+    let gas = response
+        .get("result")
+        .and_then(|result| result.as_str())
+        .ok_or_else(|| format!("Missing result in {}", body))?;
+    U256::from_str_radix(gas.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Invalid gas estimate {}: {:?}", gas, e))
+}
 
-    let signed_tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
-    send_raw_transaction(signed_tx, rpc_services, evm_rpc).await
+/// Asks every provider for `eth_estimateGas` through the EVM RPC canister's
+/// generic `request` method and takes the median once a quorum answered.
+async fn estimate_gas(
+    chain: &ChainConfig,
+    from: &str,
+    to: Address,
+    value: U256,
+    data: &[u8],
+) -> CallResult<U256> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "eth_estimateGas",
+        "params": [{
+            "from": from,
+            "to": format!("{:?}", to),
+            "value": format!("{:#x}", value),
+            "data": format!("0x{}", hex::encode(data)),
+        }],
+    })
+    .to_string();
+
+    let services = chain.service_list();
+    let responses = futures::future::join_all(services.iter().map(|service| {
+        EVM_RPC.request(
+            service.clone(),
+            request.clone(),
+            ESTIMATE_GAS_MAX_RESPONSE_BYTES,
+            10_000_000_000,
+        )
+    }))
+    .await;
+
+    let mut estimates = Vec::new();
+    let mut last_error = None;
+    for response in responses {
+        match response
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|(result,)| parse_estimate_gas(result))
+        {
+            Ok(gas) => estimates.push(gas),
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    let quorum = consensus::quorum(&chain.consensus, services.len());
+    consensus::median(estimates, quorum).ok_or_else(|| {
+        format!(
+            "eth_estimateGas failed: {}",
+            last_error.unwrap_or_else(|| "providers did not reach a quorum".to_string())
+        )
+    })
 }
 
-#[update]
-pub async fn mint(
-    contract_address: String,
-    to: String,
-    amount: String,
-) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    require_bridge_contract(&contract_address)?;
-
-    let contract_addr = decode_h160(&contract_address, "contract")?;
-    let to_addr = decode_h160(&to, "to")?;
-    let amount_u256 = U256::from_dec_str(&amount).map_err(|e| format!("Invalid amount: {}", e))?;
-
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
-
-    let evm_rpc = EVM_RPC;
-
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
-    let sender = identity.eth_address.clone();
-    let nonce = get_nonce(sender.clone(), rpc_services.clone(), evm_rpc.clone()).await?;
-
-    let mint_fn = Function {
-        name: "mint".to_string(),
-        inputs: vec![
-            Param {
-                name: "to".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            },
-            Param {
-                name: "amount".to_string(),
-                kind: ParamType::Uint(256),
-                internal_type: None,
-            },
-        ],
-        outputs: vec![],
-        constant: Some(false),
-        state_mutability: StateMutability::NonPayable,
+/// Applies the chain's safety multiplier to `estimate`, capped at its ceiling.
+/// Estimates already above the ceiling are refused rather than truncated, as
+/// the transaction would run out of gas.
+fn gas_limit_from_estimate(estimate: U256, chain: &ChainConfig) -> CallResult<U256> {
+    let max_gas_limit = U256::from(chain.gas_policy.max_gas_limit());
+    if estimate > max_gas_limit {
+        return Err(format!(
+            "Estimated gas {} exceeds the chain's limit of {}",
+            estimate, max_gas_limit
+        ));
+    }
+    let padded = estimate
+        .saturating_mul(U256::from(chain.gas_policy.gas_limit_multiplier_percent()))
+        .checked_div(U256::from(100))
+        .unwrap_or(max_gas_limit);
+    Ok(padded.min(max_gas_limit))
+}
+
+/// Shared fee/nonce/sign/send pipeline for every canister-signed transaction.
+/// Nonces are allocated under the signing lock and only consumed once the
+/// provider accepts the transaction; a nonce rejection triggers one resync.
+/// Without an explicit `gas` limit one is derived from `eth_estimateGas`.
+async fn submit_transaction(
+    chain: &ChainConfig,
+    to: Address,
+    value: U256,
+    data: Vec<u8>,
+    gas: Option<U256>,
+) -> CallResult<SubmittedTransaction> {
+    let identity = ensure_identity(None, chain.derivation_path.clone()).await?;
+    let (gas, estimated_gas) = match gas {
+        Some(gas) => (gas, None),
+        None => {
+            let estimate = estimate_gas(chain, &identity.eth_address, to, value, &data).await?;
+            (gas_limit_from_estimate(estimate, chain)?, Some(estimate))
+        }
     };
-    let data = mint_fn
-        .encode_input(&[Token::Address(to_addr), Token::Uint(amount_u256)])
-        .map_err(|e| format!("Failed to encode data: {}", e))?;
+    let gas_limit = gas.as_u64();
+    let estimated_gas = estimated_gas.map(|estimate| estimate.as_u64());
+    let _guard = nonce::acquire_signing_lock(chain.chain_id).await;
 
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone()).await?;
+    let quote = estimate_transaction_fees(chain).await?;
+    if quote.above_cap && chain.gas_policy.fee_cap_mode() == FeeCapMode::Reject {
+        return Err(format!(
+            "Network fee {} wei exceeds the cap of {} wei",
+            quote.required_max_fee_per_gas,
+            chain.gas_policy.max_fee_per_gas_cap_wei.unwrap_or_default()
+        ));
+    }
 
-    let tx = Eip1559TransactionRequest {
-        from: None,
-        to: Some(NameOrAddress::Address(contract_addr)),
-        value: Some(U256::zero()),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(U256::from(300_000)),
-        nonce: Some(nonce),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Some(data.into()),
-        access_list: Default::default(),
-    };
+    // Keep arrival order: anything deferred earlier goes out first, and while
+    // some of it is still queued the new transaction waits behind it.
+    let queue_clear = !quote.above_cap
+        && (!gas::has_deferred(chain.chain_id)
+            || gas::flush_deferred(&identity, chain, quote.fees).await);
+    if !queue_clear {
+        return Ok(SubmittedTransaction {
+            tx_hash: gas::defer(
+                chain.chain_id,
+                format!("{:?}", to),
+                value,
+                &data,
+                gas_limit,
+                estimated_gas,
+                &quote,
+            ),
+            gas_limit,
+            estimated_gas,
+            deferred: true,
+        });
+    }
 
-    let signed_tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
-    send_raw_transaction(signed_tx, rpc_services, evm_rpc).await
+    let tx_hash = sign_with_next_nonce(&identity, chain, to, value, data, gas, quote.fees).await?;
+    Ok(SubmittedTransaction {
+        tx_hash,
+        gas_limit,
+        estimated_gas,
+        deferred: false,
+    })
 }
 
-#[update]
-pub async fn burn(contract_address: String, amount: String) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    require_bridge_contract(&contract_address)?;
+/// Signs with the next nonce and broadcasts, resyncing once if the provider
+/// disagrees about the nonce. Callers must hold the signing lock.
+async fn sign_with_next_nonce(
+    identity: &CachedIdentity,
+    chain: &ChainConfig,
+    to: Address,
+    value: U256,
+    data: Vec<u8>,
+    gas: U256,
+    fees: FeeEstimates,
+) -> CallResult<TransactionHash> {
+    let mut resynced = false;
+    loop {
+        let nonce = match nonce::next_nonce(chain.chain_id) {
+            Some(nonce) => nonce,
+            None => {
+                resynced = true;
+                resync_nonce_from_provider(&identity.eth_address, chain).await?
+            }
+        };
 
-    let contract_addr = decode_h160(&contract_address, "contract")?;
-    let amount_u256 = U256::from_dec_str(&amount).map_err(|e| format!("Invalid amount: {}", e))?;
+        let tx = OutgoingTransaction {
+            nonce,
+            to,
+            value,
+            data: data.clone(),
+            gas,
+            fees,
+        };
 
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
+        match sign_and_send(identity, chain, &tx).await {
+            Ok(tx_hash) => {
+                nonce::record_submitted(chain.chain_id, tx.into_pending(tx_hash.clone(), 0));
+                return Ok(tx_hash);
+            }
+            Err(SendRawError::NonceTooLow(_) | SendRawError::NonceTooHigh(_)) if !resynced => {
+                nonce::invalidate(chain.chain_id);
+            }
+            Err(err) => return Err(err.into_message()),
+        }
+    }
+}
 
-    let evm_rpc = EVM_RPC;
+#[update]
+pub async fn transfer_eth(
+    chain_id: u64,
+    transfer_args: TransferArgs,
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Transfer)?;
+    let chain = get_chain(chain_id)?;
+    let to = decode_h160(&transfer_args.to, "'to' address")?;
 
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
-    let sender = identity.eth_address.clone();
-    let nonce = get_nonce(sender.clone(), rpc_services.clone(), evm_rpc.clone()).await?;
+    let value =
+        U256::from_dec_str(&transfer_args.value).map_err(|e| format!("Invalid value: {:?}", e))?;
 
-    let mint_fn = Function {
-        name: "burn".to_string(),
-        inputs: vec![Param {
-            name: "amount".to_string(),
-            kind: ParamType::Uint(256),
-            internal_type: None,
-        }],
-        outputs: vec![],
-        constant: Some(false),
-        state_mutability: StateMutability::NonPayable,
-    };
-    let data = mint_fn
-        .encode_input(&[Token::Uint(amount_u256)])
-        .map_err(|e| format!("Failed to encode data: {}", e))?;
+    let gas = transfer_args.gas.map(U256::from);
 
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone()).await?;
+    submit_transaction(&chain, to, value, vec![], gas).await
+}
 
-    let tx = Eip1559TransactionRequest {
-        from: None,
-        to: Some(NameOrAddress::Address(contract_addr)),
-        value: Some(U256::zero()),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(U256::from(300_000)),
-        nonce: Some(nonce),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Some(data.into()),
-        access_list: Default::default(),
-    };
+#[update]
+pub async fn approve_erc20(
+    chain_id: u64,
+    contract_address: String,
+    spender: String,
+    amount: String,
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Transfer)?;
+    submit_contract_call(
+        chain_id,
+        ContractCall {
+            contract: contract_address,
+            function: "approve(address,uint256)".to_string(),
+            args: vec![AbiValue::Address(spender), AbiValue::Uint(amount)],
+            value: None,
+            gas: None,
+        },
+    )
+    .await
+}
+
+#[update]
+pub async fn transfer_from_erc20(
+    chain_id: u64,
+    contract_address: String,
+    from: String,
+    to: String,
+    amount: String,
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Transfer)?;
+    submit_contract_call(
+        chain_id,
+        ContractCall {
+            contract: contract_address,
+            function: "transferFrom(address,address,uint256)".to_string(),
+            args: vec![
+                AbiValue::Address(from),
+                AbiValue::Address(to),
+                AbiValue::Uint(amount),
+            ],
+            value: None,
+            gas: None,
+        },
+    )
+    .await
+}
 
-    let signed_tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
-    send_raw_transaction(signed_tx, rpc_services, evm_rpc).await
+#[update]
+pub async fn mint(
+    chain_id: u64,
+    contract_address: String,
+    to: String,
+    amount: String,
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Mint)?;
+    submit_contract_call(
+        chain_id,
+        ContractCall {
+            contract: contract_address,
+            function: "mint(address,uint256)".to_string(),
+            args: vec![AbiValue::Address(to), AbiValue::Uint(amount)],
+            value: None,
+            gas: None,
+        },
+    )
+    .await
+}
+
+#[update]
+pub async fn burn(
+    chain_id: u64,
+    contract_address: String,
+    amount: String,
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Burn)?;
+    submit_contract_call(
+        chain_id,
+        ContractCall {
+            contract: contract_address,
+            function: "burn(uint256)".to_string(),
+            args: vec![AbiValue::Uint(amount)],
+            value: None,
+            gas: None,
+        },
+    )
+    .await
 }
 
 #[update]
 pub async fn burn_from(
+    chain_id: u64,
     contract_address: String,
     from: String,
     amount: String,
-) -> CallResult<TransactionHash> {
-    ensure_authorized()?;
-    require_bridge_contract(&contract_address)?;
-
-    let contract_addr = decode_h160(&contract_address, "contract")?;
-    let from_addr = decode_h160(&from, "from")?;
-    let amount_u256 = U256::from_dec_str(&amount).map_err(|e| format!("Invalid amount: {}", e))?;
-
-    let rpc_context = get_rpc_context()?;
-    let rpc_services = rpc_context.services.clone();
-
-    let evm_rpc = EVM_RPC;
-
-    let identity = ensure_identity(None, vec![]).await?;
-    let key_id = identity.key_id.clone();
-    let sender = identity.eth_address.clone();
-    let nonce = get_nonce(sender.clone(), rpc_services.clone(), evm_rpc.clone()).await?;
-
-    let burn_from_fn = Function {
-        name: "burnFrom".to_string(),
-        inputs: vec![
-            Param {
-                name: "from".to_string(),
-                kind: ParamType::Address,
-                internal_type: None,
-            },
-            Param {
-                name: "amount".to_string(),
-                kind: ParamType::Uint(256),
-                internal_type: None,
-            },
-        ],
-        outputs: vec![],
-        constant: Some(false),
-        state_mutability: StateMutability::NonPayable,
-    };
-    let data = burn_from_fn
-        .encode_input(&[Token::Address(from_addr), Token::Uint(amount_u256)])
-        .map_err(|e| format!("Failed to encode data: {}", e))?;
+) -> CallResult<SubmittedTransaction> {
+    ensure_permission(Permission::Burn)?;
+    submit_contract_call(
+        chain_id,
+        ContractCall {
+            contract: contract_address,
+            function: "burnFrom(address,uint256)".to_string(),
+            args: vec![AbiValue::Address(from), AbiValue::Uint(amount)],
+            value: None,
+            gas: None,
+        },
+    )
+    .await
+}
 
-    let FeeEstimates {
-        max_fee_per_gas,
-        max_priority_fee_per_gas,
-    } = estimate_transaction_fees(9, rpc_services.clone(), evm_rpc.clone()).await?;
+#[update]
+pub async fn resync_nonce(chain_id: u64) -> CallResult<u64> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may resync the nonce".to_string());
+    }
 
-    let tx = Eip1559TransactionRequest {
-        from: None,
-        to: Some(NameOrAddress::Address(contract_addr)),
-        value: Some(U256::zero()),
-        max_fee_per_gas: Some(max_fee_per_gas),
-        max_priority_fee_per_gas: Some(max_priority_fee_per_gas),
-        gas: Some(U256::from(300_000)),
-        nonce: Some(nonce),
-        chain_id: Some(U64::from(rpc_context.chain_id)),
-        data: Some(data.into()),
-        access_list: Default::default(),
-    };
+    let chain = get_chain(chain_id)?;
+    let identity = ensure_identity(None, chain.derivation_path.clone()).await?;
+    let _guard = nonce::acquire_signing_lock(chain_id).await;
+    resync_nonce_from_provider(&identity.eth_address, &chain).await
+}
 
-    let signed_tx = sign_eip1559_transaction(tx, key_id, vec![]).await?;
-    send_raw_transaction(signed_tx, rpc_services, evm_rpc).await
+#[query]
+pub fn get_nonce_state(chain_id: u64) -> NonceState {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .nonce_states
+            .get(&chain_id)
+            .cloned()
+            .unwrap_or_default()
+    })
 }
 
+/// Finds the burn of `expected_amount` by `expected_from` in `tx_hash`. Burns
+/// without enough confirmations fail with an error starting with
+/// "Pending confirmations" and can be retried later.
 #[ic_cdk::update]
 pub async fn verify_tx_receipt_with_validation(
+    chain_id: u64,
     tx_hash: String,
     expected_from: String,
     expected_amount: String,
     expected_contract: String,
 ) -> Result<BurnValidation, String> {
-    ensure_authorized()?;
-    require_bridge_contract(&expected_contract)?;
-
-    let RpcContext {
-        services: rpc_services,
-        ..
-    } = get_rpc_context()?;
-    let evm_rpc = EVM_RPC;
-    let cycles = 10_000_000_000;
+    ensure_permission(Permission::Verify)?;
+    let chain = get_chain(chain_id)?;
+    let contract_lower = allow_list::require_allowed_contract(chain_id, &expected_contract)?;
 
-    let (result,): (MultiGetTransactionReceiptResult,) = call_with_payment128(
-        evm_rpc.0,
-        "eth_getTransactionReceipt",
-        (rpc_services.clone(), None::<RpcConfig>, tx_hash.clone()),
-        cycles,
-    )
-    .await
-    .map_err(|e| format!("Call failed: {:?}", e))?;
+    let receipt = fetch_receipt(&tx_hash, &chain)
+        .await?
+        .ok_or_else(|| "Transaction receipt not yet available.".to_string())?;
+    ensure_final_receipt(&chain, &tx_hash, &receipt).await?;
 
     let expected_from_normalized = normalize_address(&expected_from)?;
-    let contract_lower = BRIDGE_CONTRACT_ADDRESS.to_lowercase();
 
-    match result {
-        MultiGetTransactionReceiptResult::Consistent(receipt_result) => match receipt_result {
-            GetTransactionReceiptResult::Ok(receipt_opt) => {
-                let receipt = receipt_opt
-                    .ok_or_else(|| "Transaction receipt not yet available.".to_string())?;
-
-                for log in receipt.logs {
-                    if log.address.to_lowercase() != contract_lower {
-                        continue;
-                    }
+    // `burnToICP` also emits a `Transfer` to the zero address. Only the event
+    // may be matched for it, so the account it names is always the one paid.
+    let burn_to_icp_logs: Vec<_> = receipt
+        .logs
+        .iter()
+        .filter(|log| {
+            log.address.to_lowercase() == contract_lower && burns::is_burn_to_icp_log(log)
+        })
+        .collect();
+    if !burn_to_icp_logs.is_empty() {
+        let expected_amount_u256 = U256::from_dec_str(&expected_amount)
+            .map_err(|e| format!("Invalid expected amount: {:?}", e))?;
+        for log in burn_to_icp_logs {
+            let Some(burn) = burns::parse_burn_log(chain_id, log)? else {
+                continue;
+            };
+            let amount_u256 = U256::from_dec_str(&burn.amount_wei)
+                .map_err(|e| format!("Failed to parse burn amount: {:?}", e))?;
+            if burn.from != expected_from_normalized || amount_u256 != expected_amount_u256 {
+                continue;
+            }
+            return Ok(BurnValidation {
+                from: burn.from,
+                amount_wei: burn.amount_wei,
+                contract: contract_lower,
+                recipient: burn.recipient,
+                recipient_subaccount: burn.recipient_subaccount,
+                log_index: Some(burn.log_index),
+            });
+        }
+        return Err("BurnToICP event with matching details not found.".to_string());
+    }
 
-                    if log.topics.len() < 3 {
-                        continue;
-                    }
+    for log in receipt.logs {
+        if log.address.to_lowercase() != contract_lower || !burns::is_transfer_log(&log) {
+            continue;
+        }
 
-                    let from_topic = &log.topics[1];
-                    let to_topic = &log.topics[2];
+        if log.topics.len() < 3 {
+            continue;
+        }
 
-                    if from_topic.len() < 66 || to_topic.len() < 66 {
-                        continue;
-                    }
+        let from_topic = &log.topics[1];
+        let to_topic = &log.topics[2];
 
-                    let from_suffix = &from_topic[from_topic.len() - 40..];
-                    let to_suffix = &to_topic[to_topic.len() - 40..];
+        if from_topic.len() < 66 || to_topic.len() < 66 {
+            continue;
+        }
 
-                    let from_candidate = normalize_address(&format!("0x{}", from_suffix))?;
-                    let is_zero_address = to_suffix.chars().all(|c| c == '0');
+        let from_suffix = &from_topic[from_topic.len() - 40..];
+        let to_suffix = &to_topic[to_topic.len() - 40..];
 
-                    if !is_zero_address || from_candidate != expected_from_normalized {
-                        continue;
-                    }
+        let from_candidate = normalize_address(&format!("0x{}", from_suffix))?;
+        let is_zero_address = to_suffix.chars().all(|c| c == '0');
 
-                    let data_hex = log.data.trim_start_matches("0x");
-                    let amount_u256 =
-                        U256::from_str_radix(if data_hex.is_empty() { "0" } else { data_hex }, 16)
-                            .map_err(|e| format!("Failed to parse burn amount: {:?}", e))?;
-                    let expected_amount_u256 = U256::from_dec_str(&expected_amount)
-                        .map_err(|e| format!("Invalid expected amount: {:?}", e))?;
+        if !is_zero_address || from_candidate != expected_from_normalized {
+            continue;
+        }
 
-                    if amount_u256 != expected_amount_u256 {
-                        continue;
-                    }
+        let data_hex = log.data.trim_start_matches("0x");
+        let amount_u256 =
+            U256::from_str_radix(if data_hex.is_empty() { "0" } else { data_hex }, 16)
+                .map_err(|e| format!("Failed to parse burn amount: {:?}", e))?;
+        let expected_amount_u256 = U256::from_dec_str(&expected_amount)
+            .map_err(|e| format!("Invalid expected amount: {:?}", e))?;
 
-                    return Ok(BurnValidation {
-                        from: expected_from_normalized.clone(),
-                        amount_wei: amount_u256.to_string(),
-                        contract: contract_lower.clone(),
-                    });
-                }
+        if amount_u256 != expected_amount_u256 {
+            continue;
+        }
 
-                Err("Burn log with matching details not found.".to_string())
-            }
-            GetTransactionReceiptResult::Err(err) => Err(format!("Transaction error: {:?}", err)),
-        },
-        MultiGetTransactionReceiptResult::Inconsistent(_) => {
-            Err("Inconsistent result from RPC".to_string())
+        return Ok(BurnValidation {
+            from: expected_from_normalized.clone(),
+            amount_wei: amount_u256.to_string(),
+            contract: contract_lower.clone(),
+            recipient: None,
+            recipient_subaccount: None,
+            log_index: None,
+        });
+    }
+
+    Err("Burn log with matching details not found.".to_string())
+}
+
+async fn fetch_receipt_block(
+    tx_hash: &str,
+    chain: &ChainConfig,
+) -> CallResult<Option<(u64, TransactionStatus)>> {
+    let receipt = fetch_receipt(tx_hash, chain).await?;
+
+    let Some(receipt) = receipt else {
+        return Ok(None);
+    };
+
+    let block_number = nat_to_u64(&receipt.blockNumber, "receipt block number")?;
+    // Receipts without a status field predate Byzantium and only exist for
+    // successful transactions.
+    let status = match receipt.status {
+        Some(status) if status == 0u8 => TransactionStatus::Reverted,
+        _ => TransactionStatus::Success,
+    };
+    Ok(Some((block_number, status)))
+}
+
+/// Reports the receipt state of `tx_hash`, following any speed-up or cancel
+/// replacements so callers can keep polling the hash they were first given.
+/// `tx_hash` in the result is the hash that was actually mined.
+#[update]
+pub async fn get_transaction_confirmations(
+    chain_id: u64,
+    tx_hash: String,
+) -> CallResult<TransactionConfirmation> {
+    ensure_permission(Permission::Verify)?;
+
+    let chain = get_chain(chain_id)?;
+
+    // Only one transaction per nonce can be mined; the newest replacement is
+    // the most likely candidate.
+    let replacements = nonce::replacement_chain(chain_id, &tx_hash);
+    for candidate in replacements.iter().rev() {
+        if gas::is_deferred_handle(candidate) {
+            continue;
         }
+        let Some((block_number, status)) = fetch_receipt_block(candidate, &chain).await? else {
+            continue;
+        };
+
+        let latest_block = get_latest_block_number(&chain).await?;
+        let status = if nonce::is_cancellation(chain_id, candidate) {
+            TransactionStatus::Cancelled
+        } else {
+            status
+        };
+        return Ok(TransactionConfirmation {
+            tx_hash: candidate.clone(),
+            status,
+            block_number: Some(block_number),
+            confirmations: latest_block.saturating_sub(block_number) + 1,
+            required_confirmations: chain.confirmation_depth,
+        });
     }
+
+    let latest_hash = replacements.last().cloned().unwrap_or(tx_hash);
+    let status =
+        if nonce::is_pending(chain_id, &latest_hash) || gas::is_deferred(chain_id, &latest_hash) {
+            TransactionStatus::Pending
+        } else {
+            TransactionStatus::NotFound
+        };
+    Ok(TransactionConfirmation {
+        tx_hash: latest_hash,
+        status,
+        block_number: None,
+        confirmations: 0,
+        required_confirmations: chain.confirmation_depth,
+    })
 }
 
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chains::RpcProviders;
+    use candid::Nat;
+    use evm_rpc_canister_types::ConsensusStrategy;
+
+    fn chain(gas_policy: GasPolicy) -> ChainConfig {
+        ChainConfig {
+            chain_id: 1,
+            providers: RpcProviders::EthMainnet(None),
+            consensus: ConsensusStrategy::Equality,
+            gas_policy,
+            confirmation_depth: 12,
+            min_burn_confirmations: None,
+            derivation_path: vec![],
+        }
+    }
+
+    fn fee_history(base_fees: &[u64], rewards: &[u64]) -> FeeHistory {
+        FeeHistory {
+            reward: rewards
+                .iter()
+                .map(|reward| vec![Nat::from(*reward)])
+                .collect(),
+            gasUsedRatio: vec![],
+            oldestBlock: Nat::from(0u64),
+            baseFeePerGas: base_fees.iter().map(|fee| Nat::from(*fee)).collect(),
+        }
+    }
+
+    #[test]
+    fn network_fees_use_the_next_base_fee_and_median_tip() {
+        let fees = network_fees_from_history(&fee_history(&[90, 100], &[5, 1, 3]), 0).unwrap();
+
+        assert_eq!(fees.base_fee_per_gas, U256::from(100));
+        assert_eq!(fees.priority_fee_per_gas, U256::from(3));
+    }
+
+    #[test]
+    fn network_fees_never_tip_below_the_policy_minimum() {
+        let fees = network_fees_from_history(&fee_history(&[100], &[]), 7).unwrap();
+        assert_eq!(fees.priority_fee_per_gas, U256::from(7));
+
+        assert!(network_fees_from_history(&fee_history(&[], &[5]), 0).is_err());
+    }
+
+    #[test]
+    fn gas_limit_pads_the_estimate_by_the_multiplier() {
+        let chain = chain(GasPolicy {
+            gas_limit_multiplier_percent: Some(150),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            gas_limit_from_estimate(U256::from(40_000), &chain),
+            Ok(U256::from(60_000))
+        );
+    }
+
+    #[test]
+    fn gas_limit_defaults_to_twenty_percent_headroom() {
+        assert_eq!(
+            gas_limit_from_estimate(U256::from(50_000), &chain(GasPolicy::default())),
+            Ok(U256::from(60_000))
+        );
+    }
+
+    #[test]
+    fn gas_limit_is_capped_at_the_ceiling() {
+        let chain = chain(GasPolicy {
+            max_gas_limit: Some(100_000),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            gas_limit_from_estimate(U256::from(90_000), &chain),
+            Ok(U256::from(100_000))
+        );
+        assert_eq!(
+            gas_limit_from_estimate(U256::from(100_000), &chain),
+            Ok(U256::from(100_000))
+        );
+    }
+
+    #[test]
+    fn gas_limit_refuses_estimates_above_the_ceiling() {
+        let chain = chain(GasPolicy {
+            max_gas_limit: Some(100_000),
+            ..Default::default()
+        });
+
+        assert!(gas_limit_from_estimate(U256::from(100_001), &chain).is_err());
+    }
+
+    #[test]
+    fn estimate_gas_responses_are_parsed_or_rejected() {
+        let ok = RequestResult::Ok(r#"{"jsonrpc":"2.0","id":1,"result":"0x5208"}"#.to_string());
+        assert_eq!(parse_estimate_gas(ok), Ok(U256::from(21_000)));
+
+        let reverted = RequestResult::Ok(
+            r#"{"jsonrpc":"2.0","id":1,"error":{"code":3,"message":"execution reverted"}}"#
+                .to_string(),
+        );
+        assert!(parse_estimate_gas(reverted).is_err());
+        assert!(parse_estimate_gas(RequestResult::Ok("not json".to_string())).is_err());
+    }
+
+    #[test]
+    fn burns_wait_for_the_configured_confirmations() {
+        let mut chain = chain(GasPolicy::default());
+        chain.min_burn_confirmations = Some(3);
+
+        let pending = ensure_burn_confirmations(&chain, "0xa", 100, 101).unwrap_err();
+        assert!(pending.starts_with(PENDING_CONFIRMATIONS));
+        assert!(pending.contains("2 of 3"));
+        assert_eq!(ensure_burn_confirmations(&chain, "0xa", 100, 102), Ok(()));
+    }
+
+    #[test]
+    fn burn_confirmations_default_to_the_confirmation_depth() {
+        let chain = chain(GasPolicy::default());
+
+        assert!(ensure_burn_confirmations(&chain, "0xa", 100, 110).is_err());
+        assert_eq!(ensure_burn_confirmations(&chain, "0xa", 100, 111), Ok(()));
+    }
+
+    #[test]
+    fn burns_need_at_least_their_own_block() {
+        let mut chain = chain(GasPolicy::default());
+        chain.confirmation_depth = 0;
+
+        assert!(ensure_burn_confirmations(&chain, "0xa", 100, 99).is_err());
+        assert_eq!(ensure_burn_confirmations(&chain, "0xa", 100, 100), Ok(()));
+    }
+}
@@ -0,0 +1,356 @@
+use crate::{CanisterState, STATE};
+use candid::CandidType;
+use futures::channel::oneshot;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+// Replacement links and cancellations of nonces this far below the mined
+// count are dropped; receipts are looked up long before then.
+const RETAINED_MINED_NONCES: u64 = 1_000;
+
+thread_local! {
+    static SIGNING_LOCKS: RefCell<BTreeMap<u64, SigningLock>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+/// A transaction signed and accepted by the RPC provider whose nonce has not
+/// been observed as mined yet.
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct PendingTransaction {
+    pub nonce: u64,
+    pub tx_hash: String,
+    pub to: String,
+    pub value: String,
+    pub data: String,
+    pub gas: u64,
+    pub max_fee_per_gas: String,
+    pub max_priority_fee_per_gas: String,
+    pub submitted_at_ns: u64,
+    /// Number of times this nonce has been re-signed with higher fees.
+    pub replacements: u32,
+    /// Latest block seen by the replacement timer after submission; stuck
+    /// detection counts blocks from here.
+    pub first_seen_block: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Deserialize, Serialize)]
+pub struct NonceState {
+    /// Nonce for the next signed transaction; `None` forces a resync from the
+    /// provider's pending transaction count before it is used.
+    pub next_nonce: Option<u64>,
+    pub pending: BTreeMap<u64, PendingTransaction>,
+    /// Superseded transaction hash -> hash of the transaction that replaced it.
+    pub replaced_by: BTreeMap<String, String>,
+    /// Hashes of 0-value self-transfers used to cancel a nonce.
+    pub cancellations: BTreeSet<String>,
+    /// Nonce of each `replaced_by` key and cancellation, so both can be
+    /// pruned once it is mined. `None` in state saved before pruning.
+    pub link_nonces: Option<BTreeMap<u64, Vec<String>>>,
+}
+
+impl NonceState {
+    fn record_link(&mut self, nonce: u64, tx_hash: String) {
+        self.link_nonces
+            .get_or_insert_with(BTreeMap::new)
+            .entry(nonce)
+            .or_default()
+            .push(tx_hash);
+    }
+
+    /// Drops queued transactions below `mined_count`, and links of nonces
+    /// more than `RETAINED_MINED_NONCES` below it.
+    fn prune(&mut self, mined_count: u64) {
+        self.pending.retain(|nonce, _| *nonce >= mined_count);
+        let Some(link_nonces) = self.link_nonces.as_mut() else {
+            return;
+        };
+        let kept = link_nonces.split_off(&mined_count.saturating_sub(RETAINED_MINED_NONCES));
+        for tx_hash in std::mem::replace(link_nonces, kept).into_values().flatten() {
+            self.replaced_by.remove(&tx_hash);
+            self.cancellations.remove(&tx_hash);
+        }
+    }
+}
+
+#[derive(Default)]
+struct SigningLock {
+    locked: bool,
+    waiters: VecDeque<oneshot::Sender<()>>,
+}
+
+/// Held for the whole allocate/sign/send sequence so that two bridge calls
+/// interleaving at an await can never sign with the same nonce on a chain.
+pub struct SigningGuard(u64);
+
+impl Drop for SigningGuard {
+    fn drop(&mut self) {
+        SIGNING_LOCKS.with(|locks| {
+            let mut locks = locks.borrow_mut();
+            let lock = locks.entry(self.0).or_default();
+            // Hand the lock straight to the next live waiter, in arrival order.
+            while let Some(waiter) = lock.waiters.pop_front() {
+                if waiter.send(()).is_ok() {
+                    return;
+                }
+            }
+            lock.locked = false;
+        });
+    }
+}
+
+pub async fn acquire_signing_lock(chain_id: u64) -> SigningGuard {
+    let receiver = SIGNING_LOCKS.with(|locks| {
+        let mut locks = locks.borrow_mut();
+        let lock = locks.entry(chain_id).or_default();
+        if !lock.locked {
+            lock.locked = true;
+            return None;
+        }
+        let (sender, receiver) = oneshot::channel();
+        lock.waiters.push_back(sender);
+        Some(receiver)
+    });
+
+    if let Some(receiver) = receiver {
+        let _ = receiver.await;
+    }
+    SigningGuard(chain_id)
+}
+
+fn with_nonce_state<R>(chain_id: u64, f: impl FnOnce(&mut NonceState) -> R) -> R {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let CanisterState { nonce_states, .. } = &mut *state;
+        f(nonce_states.entry(chain_id).or_default())
+    })
+}
+
+pub fn next_nonce(chain_id: u64) -> Option<u64> {
+    with_nonce_state(chain_id, |nonce_state| nonce_state.next_nonce)
+}
+
+pub fn invalidate(chain_id: u64) {
+    with_nonce_state(chain_id, |nonce_state| nonce_state.next_nonce = None);
+}
+
+/// Applies a resync: `pending_count` becomes the next nonce and every queued
+/// transaction below `mined_count` is dropped from the in-flight queue.
+pub fn apply_resync(chain_id: u64, pending_count: u64, mined_count: u64) {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state.prune(mined_count);
+        // Never move backwards past a transaction we know we already broadcast.
+        let after_queue = nonce_state
+            .pending
+            .keys()
+            .next_back()
+            .map(|nonce| nonce + 1)
+            .unwrap_or(0);
+        nonce_state.next_nonce = Some(pending_count.max(after_queue));
+    });
+}
+
+pub fn record_submitted(chain_id: u64, tx: PendingTransaction) {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state.next_nonce = Some(tx.nonce + 1);
+        nonce_state.pending.insert(tx.nonce, tx);
+    });
+}
+
+/// Drops queued transactions whose nonce is below the mined transaction count
+/// without touching the allocator.
+pub fn prune_mined(chain_id: u64, mined_count: u64) {
+    with_nonce_state(chain_id, |nonce_state| nonce_state.prune(mined_count));
+}
+
+pub fn pending_transaction(chain_id: u64, nonce: u64) -> Option<PendingTransaction> {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state.pending.get(&nonce).cloned()
+    })
+}
+
+pub fn pending_transactions(chain_id: u64) -> Vec<PendingTransaction> {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state.pending.values().cloned().collect()
+    })
+}
+
+/// Chains with at least one queued transaction.
+pub fn chains_with_pending() -> Vec<u64> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .nonce_states
+            .iter()
+            .filter(|(_, nonce_state)| !nonce_state.pending.is_empty())
+            .map(|(chain_id, _)| *chain_id)
+            .collect()
+    })
+}
+
+pub fn mark_first_seen(chain_id: u64, nonce: u64, block: u64) {
+    with_nonce_state(chain_id, |nonce_state| {
+        if let Some(tx) = nonce_state.pending.get_mut(&nonce) {
+            tx.first_seen_block.get_or_insert(block);
+        }
+    });
+}
+
+/// Swaps the queued transaction at `tx.nonce` for its re-signed replacement and
+/// remembers the link so receipts can still be found from the original hash.
+pub fn record_replacement(chain_id: u64, tx: PendingTransaction, cancellation: bool) {
+    with_nonce_state(chain_id, |nonce_state| {
+        let nonce = tx.nonce;
+        let new_hash = tx.tx_hash.to_lowercase();
+        if let Some(previous) = nonce_state.pending.insert(nonce, tx) {
+            let previous_hash = previous.tx_hash.to_lowercase();
+            nonce_state
+                .replaced_by
+                .insert(previous_hash.clone(), new_hash.clone());
+            nonce_state.record_link(nonce, previous_hash);
+        }
+        if cancellation {
+            nonce_state.cancellations.insert(new_hash.clone());
+            nonce_state.record_link(nonce, new_hash);
+        }
+    });
+}
+
+/// Links the placeholder handed out for a deferred transaction to the hash
+/// it was eventually broadcast under.
+pub fn record_promotion(chain_id: u64, handle: &str, tx_hash: &str) {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state
+            .replaced_by
+            .insert(handle.to_lowercase(), tx_hash.to_lowercase());
+        let nonce = nonce_state
+            .pending
+            .values()
+            .find(|tx| tx.tx_hash.eq_ignore_ascii_case(tx_hash))
+            .map(|tx| tx.nonce);
+        if let Some(nonce) = nonce {
+            nonce_state.record_link(nonce, handle.to_lowercase());
+        }
+    });
+}
+
+/// Returns `tx_hash` followed by every hash that replaced it, oldest first.
+pub fn replacement_chain(chain_id: u64, tx_hash: &str) -> Vec<String> {
+    with_nonce_state(chain_id, |nonce_state| {
+        let replaced_by = &nonce_state.replaced_by;
+        let mut chain = vec![tx_hash.to_lowercase()];
+        while let Some(next) = chain.last().and_then(|hash| replaced_by.get(hash)) {
+            if chain.len() > replaced_by.len() {
+                break;
+            }
+            chain.push(next.clone());
+        }
+        chain
+    })
+}
+
+pub fn is_cancellation(chain_id: u64, tx_hash: &str) -> bool {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state.cancellations.contains(&tx_hash.to_lowercase())
+    })
+}
+
+pub fn is_pending(chain_id: u64, tx_hash: &str) -> bool {
+    with_nonce_state(chain_id, |nonce_state| {
+        nonce_state
+            .pending
+            .values()
+            .any(|tx| tx.tx_hash.eq_ignore_ascii_case(tx_hash))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHAIN: u64 = 1;
+
+    fn tx(nonce: u64, tx_hash: &str) -> PendingTransaction {
+        PendingTransaction {
+            nonce,
+            tx_hash: tx_hash.to_string(),
+            to: "0x0".to_string(),
+            value: "0".to_string(),
+            data: String::new(),
+            gas: 21_000,
+            max_fee_per_gas: "1".to_string(),
+            max_priority_fee_per_gas: "1".to_string(),
+            submitted_at_ns: 0,
+            replacements: 0,
+            first_seen_block: None,
+        }
+    }
+
+    fn queued() -> Vec<u64> {
+        pending_transactions(CHAIN)
+            .iter()
+            .map(|tx| tx.nonce)
+            .collect()
+    }
+
+    #[test]
+    fn resync_drops_mined_transactions_and_adopts_pending_count() {
+        record_submitted(CHAIN, tx(3, "0xa"));
+        record_submitted(CHAIN, tx(4, "0xb"));
+
+        apply_resync(CHAIN, 6, 4);
+
+        assert_eq!(next_nonce(CHAIN), Some(6));
+        assert_eq!(queued(), vec![4]);
+    }
+
+    #[test]
+    fn resync_never_reuses_a_queued_nonce() {
+        record_submitted(CHAIN, tx(5, "0xa"));
+        record_submitted(CHAIN, tx(6, "0xb"));
+        invalidate(CHAIN);
+        assert_eq!(next_nonce(CHAIN), None);
+
+        // A lagging provider still reports the queue as unseen.
+        apply_resync(CHAIN, 5, 5);
+
+        assert_eq!(next_nonce(CHAIN), Some(7));
+        assert_eq!(queued(), vec![5, 6]);
+    }
+
+    #[test]
+    fn resync_with_empty_queue_follows_the_provider() {
+        record_submitted(CHAIN, tx(9, "0xa"));
+        apply_resync(CHAIN, 2, 10);
+
+        assert_eq!(next_nonce(CHAIN), Some(2));
+        assert!(queued().is_empty());
+    }
+
+    #[test]
+    fn replacements_are_followed_from_the_original_hash() {
+        record_submitted(CHAIN, tx(7, "0xA"));
+        record_replacement(CHAIN, tx(7, "0xB"), false);
+        record_replacement(CHAIN, tx(7, "0xC"), true);
+
+        assert_eq!(replacement_chain(CHAIN, "0xa"), vec!["0xa", "0xb", "0xc"]);
+        assert!(is_cancellation(CHAIN, "0xc"));
+        assert!(!is_pending(CHAIN, "0xa"));
+        assert!(is_pending(CHAIN, "0xC"));
+        assert_eq!(queued(), vec![7]);
+    }
+
+    #[test]
+    fn replacement_links_survive_until_long_mined() {
+        record_submitted(CHAIN, tx(7, "0xA"));
+        record_replacement(CHAIN, tx(7, "0xB"), false);
+        record_replacement(CHAIN, tx(7, "0xC"), true);
+
+        apply_resync(CHAIN, 8, 8);
+        assert_eq!(replacement_chain(CHAIN, "0xa").len(), 3);
+        assert!(is_cancellation(CHAIN, "0xc"));
+
+        apply_resync(CHAIN, 8 + RETAINED_MINED_NONCES, 8 + RETAINED_MINED_NONCES);
+        assert_eq!(replacement_chain(CHAIN, "0xa"), vec!["0xa"]);
+        assert!(!is_cancellation(CHAIN, "0xc"));
+    }
+}
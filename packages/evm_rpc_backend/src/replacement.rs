@@ -0,0 +1,271 @@
+use crate::chains::get_chain;
+use crate::nonce;
+use crate::{
+    ensure_identity, estimate_transaction_fees, get_latest_block_number, get_nonce, sign_and_send,
+    CallResult, FeeEstimates, OutgoingTransaction, SendRawError, TransactionHash, STATE,
+};
+use candid::CandidType;
+use ethers_core::types::U256;
+use evm_rpc_canister_types::BlockTag;
+use ic_cdk::{query, update};
+use ic_cdk_timers::set_timer_interval;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const REPLACEMENT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+// Geth and most clients refuse a same-nonce replacement below a 10% bump.
+const MIN_FEE_BUMP_PERCENT: u64 = 10;
+const CANCEL_GAS: u64 = 21_000;
+
+#[derive(Clone, Debug, CandidType, Deserialize, Serialize)]
+pub struct ReplacementPolicy {
+    /// Blocks a queued transaction may stay unmined before it is re-signed.
+    pub stuck_after_blocks: u64,
+    /// Percentage added to both fee fields on every replacement.
+    pub fee_bump_percent: u64,
+    /// Automatic replacements per nonce; explicit speed-ups ignore this and
+    /// the chain's fee cap.
+    pub max_replacements: u32,
+}
+
+impl Default for ReplacementPolicy {
+    fn default() -> Self {
+        Self {
+            stuck_after_blocks: 20,
+            fee_bump_percent: 25,
+            max_replacements: 5,
+        }
+    }
+}
+
+pub fn start_replacement_timer() {
+    set_timer_interval(REPLACEMENT_CHECK_INTERVAL, || {
+        ic_cdk::spawn(async {
+            if let Err(err) = check_stuck_transactions().await {
+                ic_cdk::println!("stuck transaction check failed: {}", err);
+            }
+        })
+    });
+}
+
+fn bump(value: U256, percent: u64) -> U256 {
+    value
+        .checked_mul(U256::from(100 + percent))
+        .map(|v| v / U256::from(100))
+        .unwrap_or(U256::MAX)
+}
+
+// The replacement must beat the previous fees by the bump and should never
+// undercut what the network currently asks for.
+fn bumped_fees(previous: FeeEstimates, current: FeeEstimates, percent: u64) -> FeeEstimates {
+    let max_priority_fee_per_gas =
+        bump(previous.max_priority_fee_per_gas, percent).max(current.max_priority_fee_per_gas);
+    let max_fee_per_gas = bump(previous.max_fee_per_gas, percent)
+        .max(current.max_fee_per_gas)
+        .max(max_priority_fee_per_gas);
+    FeeEstimates {
+        max_fee_per_gas,
+        max_priority_fee_per_gas,
+    }
+}
+
+/// Re-signs the queued transaction at `nonce` with bumped fees. When `cancel`
+/// is set the replacement is a 0-value self-transfer; with `capped` the bump
+/// may not exceed the chain's fee cap. Callers must hold the signing lock.
+async fn replace_transaction(
+    chain_id: u64,
+    nonce: u64,
+    cancel: bool,
+    capped: bool,
+) -> CallResult<TransactionHash> {
+    let pending = nonce::pending_transaction(chain_id, nonce)
+        .ok_or_else(|| format!("No pending transaction with nonce {}", nonce))?;
+    let policy = STATE.with(|state| state.borrow().replacement_policy.clone());
+    let chain = get_chain(chain_id)?;
+    let identity = ensure_identity(None, chain.derivation_path.clone()).await?;
+
+    let previous = FeeEstimates {
+        max_fee_per_gas: U256::from_dec_str(&pending.max_fee_per_gas)
+            .map_err(|e| format!("Invalid pending max fee: {:?}", e))?,
+        max_priority_fee_per_gas: U256::from_dec_str(&pending.max_priority_fee_per_gas)
+            .map_err(|e| format!("Invalid pending priority fee: {:?}", e))?,
+    };
+    let current = estimate_transaction_fees(&chain).await?.fees;
+    let fees = bumped_fees(previous, current, policy.fee_bump_percent);
+    if let Some(cap) = chain.gas_policy.max_fee_per_gas_cap_wei {
+        if capped && fees.max_fee_per_gas > U256::from(cap) {
+            return Err(format!(
+                "Replacement fee {} wei would exceed the cap of {} wei",
+                fees.max_fee_per_gas, cap
+            ));
+        }
+    }
+
+    let cancel = cancel || nonce::is_cancellation(chain_id, &pending.tx_hash);
+    let tx = if cancel {
+        OutgoingTransaction {
+            nonce,
+            to: crate::decode_h160(&identity.eth_address, "canister address")?,
+            value: U256::zero(),
+            data: vec![],
+            gas: U256::from(CANCEL_GAS),
+            fees,
+        }
+    } else {
+        OutgoingTransaction::from_pending(&pending, fees)?
+    };
+
+    match sign_and_send(&identity, &chain, &tx).await {
+        Ok(tx_hash) => {
+            ic_cdk::println!(
+                "replaced {} at nonce {} with {}",
+                pending.tx_hash,
+                nonce,
+                tx_hash
+            );
+            nonce::record_replacement(
+                chain_id,
+                tx.into_pending(tx_hash.clone(), pending.replacements + 1),
+                cancel,
+            );
+            Ok(tx_hash)
+        }
+        Err(SendRawError::NonceTooLow(_)) => Err(format!(
+            "Nonce {} was already mined; nothing to replace",
+            nonce
+        )),
+        Err(err) => Err(err.into_message()),
+    }
+}
+
+async fn check_stuck_transactions() -> CallResult<()> {
+    for chain_id in nonce::chains_with_pending() {
+        if let Err(err) = check_stuck_transactions_on(chain_id).await {
+            ic_cdk::println!(
+                "stuck transaction check on chain {} failed: {}",
+                chain_id,
+                err
+            );
+        }
+    }
+    Ok(())
+}
+
+async fn check_stuck_transactions_on(chain_id: u64) -> CallResult<()> {
+    let chain = get_chain(chain_id)?;
+    let identity = ensure_identity(None, chain.derivation_path.clone()).await?;
+    let _guard = nonce::acquire_signing_lock(chain_id).await;
+
+    let latest_block = get_latest_block_number(&chain).await?;
+    let mined_count = get_nonce(identity.eth_address.clone(), BlockTag::Latest, &chain).await?;
+    nonce::prune_mined(chain_id, mined_count);
+
+    let policy = STATE.with(|state| state.borrow().replacement_policy.clone());
+    for tx in nonce::pending_transactions(chain_id) {
+        let Some(first_seen_block) = tx.first_seen_block else {
+            nonce::mark_first_seen(chain_id, tx.nonce, latest_block);
+            continue;
+        };
+        if latest_block.saturating_sub(first_seen_block) < policy.stuck_after_blocks
+            || tx.replacements >= policy.max_replacements
+        {
+            continue;
+        }
+        if let Err(err) = replace_transaction(chain_id, tx.nonce, false, true).await {
+            ic_cdk::println!("failed to speed up nonce {}: {}", tx.nonce, err);
+        }
+    }
+    Ok(())
+}
+
+#[update]
+pub async fn speed_up_transaction(chain_id: u64, nonce: u64) -> CallResult<TransactionHash> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may speed up transactions".to_string());
+    }
+
+    let _guard = nonce::acquire_signing_lock(chain_id).await;
+    replace_transaction(chain_id, nonce, false, false).await
+}
+
+#[update]
+pub async fn cancel_transaction(chain_id: u64, nonce: u64) -> CallResult<TransactionHash> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may cancel transactions".to_string());
+    }
+
+    let _guard = nonce::acquire_signing_lock(chain_id).await;
+    replace_transaction(chain_id, nonce, true, false).await
+}
+
+#[query]
+pub fn get_replacement_policy() -> ReplacementPolicy {
+    STATE.with(|state| state.borrow().replacement_policy.clone())
+}
+
+#[update]
+pub fn set_replacement_policy(policy: ReplacementPolicy) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may update the replacement policy".to_string());
+    }
+    if policy.fee_bump_percent < MIN_FEE_BUMP_PERCENT {
+        return Err(format!(
+            "fee_bump_percent must be at least {}",
+            MIN_FEE_BUMP_PERCENT
+        ));
+    }
+    if policy.stuck_after_blocks == 0 {
+        return Err("stuck_after_blocks must be greater than zero".to_string());
+    }
+
+    STATE.with(|state| state.borrow_mut().replacement_policy = policy);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fees(max_fee_per_gas: u64, max_priority_fee_per_gas: u64) -> FeeEstimates {
+        FeeEstimates {
+            max_fee_per_gas: U256::from(max_fee_per_gas),
+            max_priority_fee_per_gas: U256::from(max_priority_fee_per_gas),
+        }
+    }
+
+    #[test]
+    fn replacement_bumps_both_fees_over_the_previous_transaction() {
+        let bumped = bumped_fees(fees(100, 10), fees(50, 5), 25);
+
+        assert_eq!(bumped.max_fee_per_gas, U256::from(125));
+        assert_eq!(bumped.max_priority_fee_per_gas, U256::from(12));
+    }
+
+    #[test]
+    fn replacement_never_undercuts_current_network_fees() {
+        let bumped = bumped_fees(fees(100, 10), fees(300, 40), 25);
+
+        assert_eq!(bumped.max_fee_per_gas, U256::from(300));
+        assert_eq!(bumped.max_priority_fee_per_gas, U256::from(40));
+    }
+
+    #[test]
+    fn replacement_max_fee_covers_the_bumped_tip() {
+        let bumped = bumped_fees(fees(10, 10), fees(0, 90), 10);
+
+        assert_eq!(bumped.max_priority_fee_per_gas, U256::from(90));
+        assert_eq!(bumped.max_fee_per_gas, U256::from(90));
+    }
+
+    #[test]
+    fn bump_saturates_instead_of_overflowing() {
+        assert_eq!(bump(U256::MAX, 25), U256::MAX);
+        assert_eq!(
+            bump(U256::from(1_000), MIN_FEE_BUMP_PERCENT),
+            U256::from(1_100)
+        );
+    }
+}
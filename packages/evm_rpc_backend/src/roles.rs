@@ -0,0 +1,170 @@
+use crate::STATE;
+use candid::{CandidType, Principal};
+use ic_cdk::{query, update};
+use serde::{Deserialize, Serialize};
+
+#[derive(
+    Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum Role {
+    /// Operates the canister's own funds and approvals, and may make
+    /// arbitrary contract calls.
+    Admin,
+    /// The vault side of the bridge: mints and burns hstICP.
+    Minter,
+    /// Reads receipts to confirm mints and burns.
+    Verifier,
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Deserialize, Serialize, PartialEq, Eq)]
+pub enum Permission {
+    ReadAddress,
+    Transfer,
+    Mint,
+    Burn,
+    Verify,
+    /// Arbitrary calls to allow-listed contracts through `call_contract`.
+    CallContract,
+    /// Read-only `eth_call`s.
+    ReadContract,
+}
+
+impl Role {
+    pub fn permissions(&self) -> &'static [Permission] {
+        match self {
+            Role::Admin => &[
+                Permission::ReadAddress,
+                Permission::Transfer,
+                Permission::Mint,
+                Permission::Burn,
+                Permission::Verify,
+                Permission::CallContract,
+                Permission::ReadContract,
+            ],
+            Role::Minter => &[
+                Permission::ReadAddress,
+                Permission::Mint,
+                Permission::Burn,
+                Permission::ReadContract,
+            ],
+            Role::Verifier => &[
+                Permission::ReadAddress,
+                Permission::Verify,
+                Permission::ReadContract,
+            ],
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct RoleGrant {
+    pub principal: Principal,
+    pub roles: Vec<Role>,
+}
+
+/// Accepted on install and upgrade; grants are added to the persisted table.
+#[derive(Clone, Debug, Default, CandidType, Deserialize)]
+pub struct InitArgs {
+    pub grants: Vec<RoleGrant>,
+}
+
+pub fn apply_init_args(args: Option<InitArgs>) {
+    let Some(args) = args else {
+        return;
+    };
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        for grant in args.grants {
+            state
+                .roles
+                .entry(grant.principal)
+                .or_default()
+                .extend(grant.roles);
+        }
+    });
+}
+
+/// Controllers may call everything; anyone else needs a role that carries
+/// `permission`.
+pub fn ensure_permission(permission: Permission) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if ic_cdk::api::is_controller(&caller) {
+        return Ok(());
+    }
+
+    let allowed = STATE.with(|state| {
+        state.borrow().roles.get(&caller).is_some_and(|roles| {
+            roles
+                .iter()
+                .any(|role| role.permissions().contains(&permission))
+        })
+    });
+    if allowed {
+        Ok(())
+    } else {
+        Err(format!(
+            "Caller {} lacks the {:?} permission",
+            caller, permission
+        ))
+    }
+}
+
+#[update]
+pub fn grant_role(principal: Principal, role: Role) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may grant roles".to_string());
+    }
+
+    STATE.with(|state| {
+        state
+            .borrow_mut()
+            .roles
+            .entry(principal)
+            .or_default()
+            .insert(role);
+    });
+    Ok(())
+}
+
+#[update]
+pub fn revoke_role(principal: Principal, role: Role) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    if !ic_cdk::api::is_controller(&caller) {
+        return Err("Only controllers may revoke roles".to_string());
+    }
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let roles = state
+            .roles
+            .get_mut(&principal)
+            .filter(|roles| roles.contains(&role))
+            .ok_or_else(|| format!("{} does not hold the {:?} role", principal, role))?;
+        roles.remove(&role);
+        if roles.is_empty() {
+            state.roles.remove(&principal);
+        }
+        Ok(())
+    })
+}
+
+#[query]
+pub fn list_role_grants() -> Vec<RoleGrant> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .roles
+            .iter()
+            .map(|(principal, roles)| RoleGrant {
+                principal: *principal,
+                roles: roles.iter().copied().collect(),
+            })
+            .collect()
+    })
+}
+
+#[query]
+pub fn get_role_permissions(role: Role) -> Vec<Permission> {
+    role.permissions().to_vec()
+}
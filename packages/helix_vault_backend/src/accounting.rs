@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::deposits::unminted_deposits_in;
+use crate::staking::neuron_stake_in;
+use crate::{State, STATE};
+use candid::{CandidType, Int, Nat};
+use serde::{Deserialize, Serialize};
+
+/// Asset side of the vault's books, in ledger units, posted with every
+/// transfer the vault makes or receives. It must always equal the liability
+/// side: what holders are owed (`total_deposited`), deposits pulled but not
+/// yet minted or refunded, and the treasury.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct Books {
+    /// ICP booked in the vault's main account.
+    pub liquid: Nat,
+    /// ICP booked in neurons.
+    pub staked: Nat,
+    /// Ledger fees the vault paid on transfers owed to no one.
+    pub fee_expense: Nat,
+}
+
+/// Recorded liabilities against the last ledger balance read, with every
+/// difference between them broken down.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Reconciliation {
+    /// Balance of the vault's main account when last read.
+    pub ledger_balance: Nat,
+    pub ledger_read_at_ns: Option<u64>,
+    pub booked_liquid: Nat,
+    pub booked_staked: Nat,
+    /// Stake of the vault's neurons as last refreshed from governance.
+    pub neuron_stake: Nat,
+    pub fee_expense: Nat,
+    /// Owed to hstICP holders: `total_deposited`.
+    pub holders: Nat,
+    pub pending_deposits: Nat,
+    pub treasury: Nat,
+    /// `holders + pending_deposits + treasury`.
+    pub liabilities: Nat,
+    /// `ledger_balance - liabilities`, which is
+    /// `unbooked + imbalance - booked_staked`.
+    pub difference: Int,
+    /// `ledger_balance - booked_liquid`: transfers into or out of the main
+    /// account the vault did not make, such as direct donations.
+    pub unbooked: Int,
+    /// `booked_liquid + booked_staked - liabilities`. Zero unless an
+    /// operation was booked on one side only.
+    pub imbalance: Int,
+}
+
+fn debit(balance: &mut Nat, amount: &Nat) {
+    if *balance > *amount {
+        *balance -= amount.clone();
+    } else {
+        *balance = Nat::from(0u64);
+    }
+}
+
+/// Holders' claim, deposits in flight and the treasury, in ledger units.
+pub(crate) fn liabilities_in(state: &State) -> Nat {
+    state.total_deposited.clone() + unminted_deposits_in(state) + state.treasury_balance.clone()
+}
+
+/// Books for state saved before double-entry accounting: the assets are
+/// taken to match the liabilities, neuron stakes included.
+pub(crate) fn opening_books_in(state: &State) -> Books {
+    let staked = neuron_stake_in(state);
+    let mut liquid = liabilities_in(state);
+    debit(&mut liquid, &staked);
+    Books {
+        liquid,
+        staked,
+        fee_expense: Nat::from(0u64),
+    }
+}
+
+/// `amount` arrived in the main account against a new liability.
+pub(crate) fn book_inflow_in(state: &mut State, amount: &Nat) {
+    state.books.liquid += amount.clone();
+}
+
+/// `amount`, ledger fee included, left the main account to settle a
+/// liability of the same size.
+pub(crate) fn book_outflow_in(state: &mut State, amount: &Nat) {
+    debit(&mut state.books.liquid, amount);
+}
+
+/// Ledger fee the vault paid on a transfer owed to no one, such as staking.
+/// Transfers settling a liability carry their fee in the amount owed instead.
+/// The treasury covers it; holders only bear what the treasury cannot.
+pub(crate) fn charge_fee_in(state: &mut State, fee: &Nat) {
+    debit(&mut state.books.liquid, fee);
+    state.books.fee_expense += fee.clone();
+    let from_holders = if *fee > state.treasury_balance {
+        fee.clone() - state.treasury_balance.clone()
+    } else {
+        Nat::from(0u64)
+    };
+    debit(&mut state.treasury_balance, fee);
+    debit(&mut state.total_deposited, &from_holders);
+}
+
+/// `amount` moved from the main account into a neuron.
+pub(crate) fn book_stake_in(state: &mut State, amount: &Nat, fee: &Nat) {
+    debit(&mut state.books.liquid, amount);
+    state.books.staked += amount.clone();
+    charge_fee_in(state, fee);
+}
+
+/// A disbursed neuron's `stake` came back to the main account, less the
+/// ledger fee governance pays out of it.
+pub(crate) fn book_unstake_in(state: &mut State, stake: &Nat, fee: &Nat) {
+    debit(&mut state.books.staked, stake);
+    state.books.liquid += stake.clone();
+    charge_fee_in(state, fee);
+}
+
+pub(crate) fn reconciliation_in(state: &State) -> Reconciliation {
+    let pending_deposits = unminted_deposits_in(state);
+    let liabilities = liabilities_in(state);
+    let ledger_balance = state.liquid_balance.clone();
+    let books = &state.books;
+    Reconciliation {
+        difference: Int::from(ledger_balance.clone()) - Int::from(liabilities.clone()),
+        unbooked: Int::from(ledger_balance.clone()) - Int::from(books.liquid.clone()),
+        imbalance: Int::from(books.liquid.clone() + books.staked.clone())
+            - Int::from(liabilities.clone()),
+        ledger_balance,
+        ledger_read_at_ns: state.liquid_read_at_ns,
+        booked_liquid: books.liquid.clone(),
+        booked_staked: books.staked.clone(),
+        neuron_stake: neuron_stake_in(state),
+        fee_expense: books.fee_expense.clone(),
+        holders: state.total_deposited.clone(),
+        pending_deposits,
+        treasury: state.treasury_balance.clone(),
+        liabilities,
+    }
+}
+
+#[ic_cdk::query]
+fn get_books() -> Books {
+    STATE.with(|state| state.borrow().books.clone())
+}
+
+/// Explains the last ledger balance read against recorded liabilities. Call
+/// `sync_state` first for a fresh balance.
+#[ic_cdk::query]
+fn get_reconciliation() -> Reconciliation {
+    STATE.with(|state| reconciliation_in(&state.borrow()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{deposit_op, staked_neuron};
+    use crate::{deposits, staking, StableState};
+
+    #[test]
+    fn books_explain_the_ledger_balance() {
+        let mut state = State::new();
+        // 1_000 owed to holders, of which 400 is staked, plus a pulled deposit
+        // of 50 and 20 of accrued fees.
+        state.total_deposited = Nat::from(1_000u64);
+        state.treasury_balance = Nat::from(20u64);
+        state
+            .neurons
+            .insert(0, staked_neuron(0, 400, staking::NeuronStatus::Locked));
+        state
+            .deposits
+            .insert(0, deposit_op(0, deposits::DepositStatus::Pulled, 50));
+
+        // State saved before the books opens them balanced.
+        let mut stable = StableState::from(&state);
+        stable.books = None;
+        let mut state: State = stable.into();
+        assert_eq!(state.books.liquid, Nat::from(670u64));
+        assert_eq!(state.books.staked, Nat::from(400u64));
+
+        // An unlock owed 110 pays out 100; its ledger fee of 10 comes out of
+        // the payout.
+        state.total_deposited -= Nat::from(110u64);
+        book_outflow_in(&mut state, &Nat::from(110u64));
+        // The fee of a transfer owed to no one is covered by the treasury.
+        charge_fee_in(&mut state, &Nat::from(10u64));
+        assert_eq!(state.total_deposited, Nat::from(890u64));
+        assert_eq!(state.treasury_balance, Nat::from(10u64));
+        // A donation of 5 arrives unbooked.
+        state.liquid_balance = Nat::from(555u64);
+        state.liquid_read_at_ns = Some(7);
+
+        let report = reconciliation_in(&state);
+        assert_eq!(report.booked_liquid, Nat::from(550u64));
+        assert_eq!(report.liabilities, Nat::from(950u64));
+        assert_eq!(report.fee_expense, Nat::from(10u64));
+        assert_eq!(report.imbalance, Int::from(0));
+        assert_eq!(report.unbooked, Int::from(5));
+        assert_eq!(report.difference, Int::from(-395));
+        assert_eq!(
+            report.difference,
+            report.unbooked + report.imbalance - Int::from(report.booked_staked)
+        );
+
+        // Booking only one side of an operation shows up as an imbalance.
+        book_inflow_in(&mut state, &Nat::from(3u64));
+        let report = reconciliation_in(&state);
+        assert_eq!(report.imbalance, Int::from(3));
+        assert_eq!(report.unbooked, Int::from(2));
+    }
+}
@@ -0,0 +1,281 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::deposits::{self, DepositSource, DepositStatus};
+use crate::pause::ensure_deposits_open;
+use crate::rate_limits::{self, BridgeDirection};
+use crate::{
+    current_config, refresh_transfer_fee_if_stale, resolve_chain, State, DEFAULT_TRANSFER_FEE,
+    STATE,
+};
+use candid::{CandidType, Nat};
+use ic_cdk::call;
+use ic_principal::Principal;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+const WATCH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Where funds sent to a user's deposit account are minted.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct DepositTarget {
+    pub eth_address: String,
+    pub chain_id: u64,
+}
+
+/// The vault subaccount assigned to `user`: the length of the principal
+/// followed by its bytes, zero padded.
+pub(crate) fn deposit_subaccount(user: Principal) -> Subaccount {
+    let bytes = user.as_slice();
+    let mut subaccount = [0u8; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..=bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+fn deposit_account(user: Principal) -> Account {
+    Account {
+        owner: ic_cdk::id(),
+        subaccount: Some(deposit_subaccount(user)),
+    }
+}
+
+/// How much of a deposit account holding `balance` can be swept now: all of
+/// it minus `fee`, capped by the deposit quota so a balance above the limits
+/// goes in over several sweeps. `None` when nothing can move yet.
+fn sweep_amount_in(
+    state: &State,
+    user: Principal,
+    balance: &Nat,
+    fee: &Nat,
+    now_ns: u64,
+) -> Option<Nat> {
+    if balance <= fee {
+        return None;
+    }
+    let amount = balance.clone() - fee.clone();
+    let amount =
+        match rate_limits::quota_in(state, BridgeDirection::Deposit, user, now_ns).available {
+            Some(available) => amount.min(available),
+            None => amount,
+        };
+    (amount > 0u64).then_some(amount)
+}
+
+/// Turns the balance of `user`'s deposit account into a deposit, minus the
+/// fee of moving it into the vault and up to the deposit quota. Returns the
+/// deposit id, or `None` when there is nothing to sweep.
+async fn sweep(user: Principal) -> Result<Option<u64>, String> {
+    let target = STATE
+        .with(|state| state.borrow().deposit_targets.get(&user).cloned())
+        .ok_or_else(|| "No deposit address is registered".to_string())?;
+    ensure_deposits_open()?;
+
+    let fee = refresh_transfer_fee_if_stale(&Nat::from(DEFAULT_TRANSFER_FEE)).await?;
+    let (balance,): (Nat,) = call(
+        current_config().ledger_canister,
+        "icrc1_balance_of",
+        (deposit_account(user),),
+    )
+    .await
+    .map_err(|e| format!("Failed to get deposit account balance: {:?}", e))?;
+    // Checked again after the await: a sweep still pulling holds the same funds.
+    let subaccount = deposit_subaccount(user);
+    let sweeping = STATE.with(|state| {
+        state.borrow().deposits.values().any(|op| {
+            op.deposit_subaccount == Some(subaccount) && op.status == DepositStatus::Pending
+        })
+    });
+    if sweeping {
+        return Ok(None);
+    }
+    let now = ic_cdk::api::time();
+    let Some(amount) =
+        STATE.with(|state| sweep_amount_in(&state.borrow(), user, &balance, &fee, now))
+    else {
+        return Ok(None);
+    };
+    let usage_id = rate_limits::reserve(BridgeDirection::Deposit, user, &amount)?;
+    let deposit_id = deposits::create_deposit(
+        user,
+        target.eth_address,
+        target.chain_id,
+        amount,
+        fee,
+        DepositSource {
+            from_subaccount: None,
+            deposit_subaccount: Some(subaccount),
+            refund_to: None,
+        },
+    );
+    if let Err(e) = deposits::advance_deposit(deposit_id).await {
+        if deposits::pull_refused(deposit_id) {
+            rate_limits::release(usage_id);
+        }
+        return Err(e);
+    }
+    Ok(Some(deposit_id))
+}
+
+async fn sweep_all() {
+    let users: Vec<Principal> =
+        STATE.with(|state| state.borrow().deposit_targets.keys().copied().collect());
+    for user in users {
+        if let Err(e) = sweep(user).await {
+            ic_cdk::println!("sweep deposit account of {}: {}", user, e);
+        }
+    }
+}
+
+pub(crate) fn start_deposit_account_watcher() {
+    ic_cdk_timers::set_timer_interval(WATCH_INTERVAL, || ic_cdk::spawn(sweep_all()));
+}
+
+/// Assigns the caller a vault subaccount. Plain ICRC-1 transfers to it are
+/// picked up within a minute and minted to `eth_address`, without an ICRC-2
+/// approval. Calling it again changes the target.
+#[ic_cdk::update]
+fn set_deposit_target(eth_address: String, chain_id: Option<u64>) -> Result<Account, String> {
+    let caller = ic_cdk::api::caller();
+    if caller == Principal::anonymous() {
+        return Err("Anonymous callers cannot register a deposit account".to_string());
+    }
+    let eth_address = eth_address.trim().to_string();
+    if eth_address.is_empty() {
+        return Err("Ethereum address must be provided".to_string());
+    }
+    let (chain_id, _) = resolve_chain(chain_id)?;
+
+    STATE.with(|state| {
+        state.borrow_mut().deposit_targets.insert(
+            caller,
+            DepositTarget {
+                eth_address,
+                chain_id,
+            },
+        )
+    });
+    Ok(deposit_account(caller))
+}
+
+/// Stops watching the caller's deposit account. Funds already in it stay
+/// there until a target is set again.
+#[ic_cdk::update]
+fn remove_deposit_target() -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    STATE
+        .with(|state| state.borrow_mut().deposit_targets.remove(&caller))
+        .map(|_| ())
+        .ok_or_else(|| "No deposit address is registered".to_string())
+}
+
+/// Sweeps the caller's deposit account now instead of waiting for the timer.
+#[ic_cdk::update]
+async fn sweep_deposit_account() -> Result<Option<u64>, String> {
+    sweep(ic_cdk::api::caller()).await
+}
+
+#[ic_cdk::query]
+fn get_deposit_account(user: Principal) -> Account {
+    deposit_account(user)
+}
+
+#[ic_cdk::query]
+fn get_deposit_target(user: Principal) -> Option<DepositTarget> {
+    STATE.with(|state| state.borrow().deposit_targets.get(&user).cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rate_limits::{DirectionLimits, RateLimitConfig, WindowLimit};
+    use crate::tests::deposit_op;
+    use crate::StableState;
+
+    #[test]
+    fn deposit_subaccounts_and_refund_destinations() {
+        let user = Principal::from_slice(&[1, 2, 3]);
+        let subaccount = deposit_subaccount(user);
+        assert_eq!(subaccount[..4], [3, 1, 2, 3]);
+        assert!(subaccount[4..].iter().all(|byte| *byte == 0));
+        assert_ne!(subaccount, deposit_subaccount(Principal::anonymous()));
+
+        let source = Some([9; 32]);
+        let mut op = deposit_op(0, deposits::DepositStatus::Pending, 100);
+        op.caller = user;
+        op.from_subaccount = source;
+        assert_eq!(
+            op.refund_account(),
+            Account {
+                owner: user,
+                subaccount: source,
+            }
+        );
+
+        op.deposit_subaccount = Some(subaccount);
+        assert_eq!(op.refund_account().subaccount, None);
+
+        let refund_to = Account {
+            owner: Principal::anonymous(),
+            subaccount: Some([4; 32]),
+        };
+        op.refund_to = Some(refund_to);
+        assert_eq!(op.refund_account(), refund_to);
+
+        let mut state = State::new();
+        state.deposits.insert(0, op);
+        let restored: State = StableState::from(&state).into();
+        assert_eq!(restored.deposits[&0].refund_to, Some(refund_to));
+    }
+
+    #[test]
+    fn sweeps_are_capped_by_the_deposit_quota() {
+        let user = Principal::from_slice(&[7]);
+        let fee = Nat::from(10u64);
+        let mut state = State::new();
+        assert_eq!(
+            sweep_amount_in(&state, user, &Nat::from(10u64), &fee, 0),
+            None
+        );
+        assert_eq!(
+            sweep_amount_in(&state, user, &Nat::from(1_010u64), &fee, 0),
+            Some(Nat::from(1_000u64))
+        );
+
+        state.rate_limits = RateLimitConfig {
+            deposits: DirectionLimits {
+                max_transfer: Some(Nat::from(300u64)),
+                windows: vec![WindowLimit {
+                    window_secs: 60,
+                    per_principal: Some(Nat::from(500u64)),
+                    global: None,
+                }],
+            },
+            ..Default::default()
+        };
+        // Over the per-transfer cap: sweep what fits and leave the rest.
+        let amount = sweep_amount_in(&state, user, &Nat::from(1_010u64), &fee, 0).unwrap();
+        assert_eq!(amount, Nat::from(300u64));
+        rate_limits::reserve_in(&mut state, BridgeDirection::Deposit, user, &amount, 1).unwrap();
+        rate_limits::reserve_in(&mut state, BridgeDirection::Deposit, user, &amount, 2)
+            .unwrap_err();
+        assert_eq!(
+            sweep_amount_in(&state, user, &Nat::from(710u64), &fee, 2),
+            Some(Nat::from(200u64))
+        );
+        rate_limits::reserve_in(
+            &mut state,
+            BridgeDirection::Deposit,
+            user,
+            &Nat::from(200u64),
+            2,
+        )
+        .unwrap();
+        // Window used up: wait for it instead of failing.
+        assert_eq!(
+            sweep_amount_in(&state, user, &Nat::from(510u64), &fee, 3),
+            None
+        );
+    }
+}
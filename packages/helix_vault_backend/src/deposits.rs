@@ -0,0 +1,1234 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::accounting::{book_inflow_in, book_outflow_in, charge_fee_in};
+use crate::exchange_rate::shares_for_in;
+use crate::fees::{accrue_in, fee_for_in, FeeKind};
+use crate::pause::{
+    ensure_deposits_open, record_mint_failure, record_mint_failure_in, record_mint_success_in,
+    record_refund_failure,
+};
+use crate::transfer_log::{
+    record_operation, record_operation_in, BridgeOperationKind, NewOperation,
+};
+use crate::{
+    apply_successful_deposit_in, current_config, ensure_controller, resolve_chain, State, STATE,
+};
+use candid::{CandidType, Nat};
+use ic_cdk::api::call::RejectionCode;
+use ic_cdk::call;
+use ic_principal::Principal;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
+use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+thread_local! {
+    // Deposits currently being driven by an in-flight call. Not persisted: after an
+    // upgrade nothing is in flight and every unfinished deposit is eligible again.
+    static IN_FLIGHT: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DepositStatus {
+    /// Recorded, `icrc2_transfer_from` not yet known to have succeeded.
+    Pending,
+    /// Funds are in the vault, mint not yet submitted.
+    Pulled,
+    /// Mint call issued. With `mint_tx_hash` set the reconciler polls its receipt;
+    /// without it the outcome is unknown and needs a controller to resolve it.
+    MintSubmitted,
+    /// The mint receipt succeeded and reached the configured confirmation depth.
+    MintConfirmed,
+    /// Mint failed, the pulled funds still have to be returned.
+    RefundPending,
+    Refunded,
+    Failed,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct DepositOperation {
+    pub id: u64,
+    pub caller: Principal,
+    pub eth_address: String,
+    pub amount: Nat,
+    pub fee: Nat,
+    pub status: DepositStatus,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+    pub pull_block_index: Option<Nat>,
+    pub mint_tx_hash: Option<String>,
+    pub refund_created_at_ns: Option<u64>,
+    pub refund_block_index: Option<Nat>,
+    pub log_operation: Option<u64>,
+    pub last_error: Option<String>,
+    pub mint_submitted_at_ns: Option<u64>,
+    pub mint_attempts: Option<u32>,
+    pub confirmations: Option<u64>,
+    /// Chain the mint targets; `None` for deposits made before multi-chain
+    /// support, which use the default chain.
+    pub chain_id: Option<u64>,
+    /// Subaccount of `caller` the funds were pulled from.
+    pub from_subaccount: Option<Subaccount>,
+    /// Vault subaccount the funds were swept from, for deposits made by a
+    /// plain transfer.
+    pub deposit_subaccount: Option<Subaccount>,
+    /// Where a refund is paid; see `refund_account`.
+    pub refund_to: Option<Account>,
+    /// hstICP wei minted, fixed at the exchange rate when the funds were
+    /// pulled. `None` for deposits pulled at the 1:1 peg.
+    pub evm_amount: Option<String>,
+    /// Deposit fee kept for the treasury, fixed along with `evm_amount`.
+    /// Only accrued once the mint is confirmed; refunds return it.
+    pub protocol_fee: Option<Nat>,
+    /// Paid back by the refund, fixed on its first attempt: `amount` less
+    /// the ledger fee. `None` for refunds first attempted before that,
+    /// which paid the full `amount`.
+    pub refund_amount: Option<Nat>,
+}
+
+/// Where the funds of a new deposit come from and where they go back to.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DepositSource {
+    pub from_subaccount: Option<Subaccount>,
+    pub deposit_subaccount: Option<Subaccount>,
+    pub refund_to: Option<Account>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct MintTrackingConfig {
+    pub required_confirmations: u64,
+    /// How long a submitted mint may stay without a receipt before it is
+    /// considered dropped.
+    pub mint_timeout_secs: u64,
+    /// Total mint submissions per deposit before falling back to a refund.
+    pub max_mint_attempts: u32,
+}
+
+impl Default for MintTrackingConfig {
+    fn default() -> Self {
+        Self {
+            required_confirmations: 12,
+            mint_timeout_secs: 30 * 60,
+            max_mint_attempts: 3,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, PartialEq, Eq)]
+enum TransactionStatus {
+    NotFound,
+    Pending,
+    Success,
+    Reverted,
+    Cancelled,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct TransactionConfirmation {
+    tx_hash: String,
+    status: TransactionStatus,
+    confirmations: u64,
+    required_confirmations: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct SubmittedTransaction {
+    tx_hash: String,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct HstIcpStatus {
+    total_supply: String,
+    minter: String,
+    minter_has_role: bool,
+}
+
+impl DepositOperation {
+    fn is_resumable(&self) -> bool {
+        matches!(
+            self.status,
+            DepositStatus::Pending | DepositStatus::Pulled | DepositStatus::RefundPending
+        ) || self.awaits_confirmation()
+    }
+
+    fn awaits_confirmation(&self) -> bool {
+        self.status == DepositStatus::MintSubmitted && self.mint_tx_hash.is_some()
+    }
+
+    /// `refund_to` when given, otherwise the account the funds were pulled
+    /// from. Funds sent by plain transfer came from an account the vault does
+    /// not know, so they go to the caller's default account.
+    pub(crate) fn refund_account(&self) -> Account {
+        self.refund_to.unwrap_or(Account {
+            owner: self.caller,
+            subaccount: if self.deposit_subaccount.is_some() {
+                None
+            } else {
+                self.from_subaccount
+            },
+        })
+    }
+}
+
+/// Marks a deposit as being driven by the current call; released on drop, which
+/// also runs when the call traps after an await.
+struct DepositGuard(u64);
+
+impl DepositGuard {
+    fn acquire(id: u64) -> Option<Self> {
+        IN_FLIGHT.with(|set| set.borrow_mut().insert(id).then_some(DepositGuard(id)))
+    }
+}
+
+impl Drop for DepositGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|set| {
+            set.borrow_mut().remove(&self.0);
+        });
+    }
+}
+
+fn deposit(id: u64) -> Result<DepositOperation, String> {
+    STATE
+        .with(|state| state.borrow().deposits.get(&id).cloned())
+        .ok_or_else(|| format!("Deposit {} not found", id))
+}
+
+fn update_deposit_in(
+    state: &mut State,
+    id: u64,
+    now_ns: u64,
+    f: impl FnOnce(&mut DepositOperation),
+) {
+    if let Some(op) = state.deposits.get_mut(&id) {
+        f(op);
+        op.updated_at_ns = now_ns;
+    }
+}
+
+fn update_deposit(id: u64, f: impl FnOnce(&mut DepositOperation)) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| update_deposit_in(&mut state.borrow_mut(), id, now, f));
+}
+
+fn fail_in(state: &mut State, id: u64, status: DepositStatus, err: String, now_ns: u64) {
+    update_deposit_in(state, id, now_ns, |op| {
+        op.status = status;
+        op.last_error = Some(err);
+    });
+}
+
+fn fail_with(id: u64, status: DepositStatus, err: String) -> String {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| fail_in(&mut state.borrow_mut(), id, status, err.clone(), now));
+    err
+}
+
+pub(crate) fn create_deposit(
+    caller: Principal,
+    eth_address: String,
+    chain_id: u64,
+    amount: Nat,
+    fee: Nat,
+    source: DepositSource,
+) -> u64 {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let id = state.next_deposit_id;
+        state.next_deposit_id += 1;
+        state.deposits.insert(
+            id,
+            DepositOperation {
+                id,
+                caller,
+                eth_address,
+                amount,
+                fee,
+                status: DepositStatus::Pending,
+                created_at_ns: now,
+                updated_at_ns: now,
+                pull_block_index: None,
+                mint_tx_hash: None,
+                refund_created_at_ns: None,
+                refund_block_index: None,
+                log_operation: None,
+                last_error: None,
+                mint_submitted_at_ns: None,
+                mint_attempts: None,
+                confirmations: None,
+                chain_id: Some(chain_id),
+                from_subaccount: source.from_subaccount,
+                deposit_subaccount: source.deposit_subaccount,
+                refund_to: source.refund_to,
+                evm_amount: None,
+                protocol_fee: None,
+                refund_amount: None,
+            },
+        );
+        id
+    })
+}
+
+/// Moves the deposit into the vault's main account: pulled from the caller
+/// under their ICRC-2 approval, or swept from the vault's deposit subaccount.
+/// A refusal by the ledger is returned as `Ok(Err)`.
+async fn transfer_in(op: &DepositOperation) -> Result<Result<Nat, String>, String> {
+    let token_canister = current_config().ledger_canister;
+    let vault = Account {
+        owner: ic_cdk::id(),
+        subaccount: None,
+    };
+
+    if let Some(deposit_subaccount) = op.deposit_subaccount {
+        let transfer_arg = TransferArg {
+            from_subaccount: Some(deposit_subaccount),
+            to: vault,
+            amount: op.amount.clone(),
+            fee: Some(op.fee.clone()),
+            memo: Some(Memo::from(op.id)),
+            created_at_time: Some(op.created_at_ns),
+        };
+        return match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+            token_canister,
+            "icrc1_transfer",
+            (transfer_arg,),
+        )
+        .await
+        {
+            Ok((Ok(block_index),)) => Ok(Ok(block_index)),
+            Ok((Err(TransferError::Duplicate { duplicate_of }),)) => Ok(Ok(duplicate_of)),
+            Ok((Err(e),)) => Ok(Err(format!("Transfer failed: {:?}", e))),
+            Err(e) => Err(format!("Call failed: {:?}", e)),
+        };
+    }
+
+    let transfer_arg = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account {
+            owner: op.caller,
+            subaccount: op.from_subaccount,
+        },
+        to: vault,
+        amount: op.amount.clone(),
+        fee: Some(op.fee.clone()),
+        memo: Some(Memo::from(op.id)),
+        created_at_time: Some(op.created_at_ns),
+    };
+    match call::<(TransferFromArgs,), (Result<Nat, TransferFromError>,)>(
+        token_canister,
+        "icrc2_transfer_from",
+        (transfer_arg,),
+    )
+    .await
+    {
+        Ok((Ok(block_index),)) => Ok(Ok(block_index)),
+        Ok((Err(TransferFromError::Duplicate { duplicate_of }),)) => Ok(Ok(duplicate_of)),
+        Ok((Err(e),)) => Ok(Err(format!("Transfer failed: {:?}", e))),
+        Err(e) => Err(format!("Call failed: {:?}", e)),
+    }
+}
+
+// `created_at_time` and `memo` are fixed per deposit so that re-sending the
+// pull after a trap is deduplicated by the ledger instead of charged twice.
+async fn pull_funds(id: u64) -> Result<(), String> {
+    let op = deposit(id)?;
+
+    let block_index = match transfer_in(&op).await {
+        Ok(Ok(block_index)) => block_index,
+        Ok(Err(message)) => return Err(fail_with(id, DepositStatus::Failed, message)),
+        Err(message) => {
+            update_deposit(id, |op| op.last_error = Some(message.clone()));
+            return Err(message);
+        }
+    };
+
+    let now = ic_cdk::api::time();
+    STATE.with(|state| record_pull_in(&mut state.borrow_mut(), id, block_index, now));
+    Ok(())
+}
+
+/// Books the pull of deposit `id` in `block_index` and prices its mint at the
+/// current rate.
+fn record_pull_in(state: &mut State, id: u64, block_index: Nat, now_ns: u64) {
+    let Some(op) = state.deposits.get(&id).cloned() else {
+        return;
+    };
+    let protocol_fee = fee_for_in(state, FeeKind::Deposit, &op.amount);
+    let evm_amount = shares_for_in(state, &(op.amount.clone() - protocol_fee.clone()))
+        .0
+        .to_string();
+    let log_id = record_operation_in(
+        state,
+        NewOperation {
+            kind: BridgeOperationKind::Deposit,
+            principal: op.caller,
+            eth_address: Some(op.eth_address.clone()),
+            amount: op.amount.clone(),
+            evm_amount: Some(evm_amount.clone()),
+            fee: op.fee.clone(),
+            ledger_block_index: Some(block_index.clone()),
+            evm_tx_hash: None,
+            related_operation: None,
+            chain_id: op.chain_id,
+        },
+        now_ns,
+    );
+    update_deposit_in(state, id, now_ns, |op| {
+        op.status = DepositStatus::Pulled;
+        op.pull_block_index = Some(block_index);
+        op.log_operation = Some(log_id);
+        op.evm_amount = Some(evm_amount);
+        op.protocol_fee = Some(protocol_fee);
+        op.last_error = None;
+    });
+    // The ledger fee of the pull was charged to the sender on top of `amount`.
+    book_inflow_in(state, &op.amount);
+}
+
+fn scaled_amount(amount: &Nat) -> String {
+    (amount.0.clone() * 10u128.pow(10)).to_string()
+}
+
+fn mint_amount(op: &DepositOperation) -> String {
+    op.evm_amount
+        .clone()
+        .unwrap_or_else(|| scaled_amount(&op.amount))
+}
+
+/// hstICP wei of deposits whose mint was submitted but not yet credited.
+pub(crate) fn in_flight_mint_shares_in(state: &State) -> Nat {
+    state
+        .deposits
+        .values()
+        .filter(|op| op.status == DepositStatus::MintSubmitted)
+        .fold(Nat::from(0u64), |sum, op| {
+            sum + Nat::from_str(&mint_amount(op)).unwrap_or_default()
+        })
+}
+
+/// Whether `caller` had hstICP minted to `eth_address`, which binds a plain
+/// burn from that address to them.
+pub(crate) fn minted_to_in(state: &State, caller: &Principal, eth_address: &str) -> bool {
+    state.deposits.values().any(|op| {
+        op.caller == *caller
+            && op.status == DepositStatus::MintConfirmed
+            && op.eth_address.eq_ignore_ascii_case(eth_address)
+    })
+}
+
+/// Ledger units held for deposits that have been pulled but not minted or
+/// refunded yet.
+pub(crate) fn unminted_deposits_in(state: &State) -> Nat {
+    state
+        .deposits
+        .values()
+        .filter(|op| {
+            matches!(
+                op.status,
+                DepositStatus::Pulled | DepositStatus::MintSubmitted | DepositStatus::RefundPending
+            )
+        })
+        .fold(Nat::from(0u64), |sum, op| sum + op.amount.clone())
+}
+
+/// Ledger units of deposits whose mint was submitted but not yet credited.
+pub(crate) fn in_flight_mints_in(state: &State) -> Nat {
+    state
+        .deposits
+        .values()
+        .filter(|op| op.status == DepositStatus::MintSubmitted)
+        .fold(Nat::from(0u64), |sum, op| sum + op.amount.clone())
+}
+
+/// hstICP wei the vault may have minted once deposit `id` is: every share
+/// already credited plus every mint still in flight.
+pub(crate) fn mint_backing_in(state: &State, id: u64) -> Nat {
+    state
+        .deposits
+        .values()
+        .filter(|op| op.id == id || op.status == DepositStatus::MintSubmitted)
+        .fold(state.share_supply.clone(), |backing, op| {
+            backing + Nat::from_str(&mint_amount(op)).unwrap_or_default()
+        })
+}
+
+/// Refuses a mint that would leave the chain's hstICP supply larger than the
+/// `backing` shares the vault minted. Supply on one chain is only a lower
+/// bound of what is outstanding overall, so this catches over-minting but not
+/// every imbalance.
+pub(crate) fn check_supply_headroom(
+    total_supply_wei: &str,
+    mint_wei: &str,
+    backing: &Nat,
+) -> Result<(), String> {
+    let total_supply =
+        Nat::from_str(total_supply_wei).map_err(|_| "Invalid hstICP total supply".to_string())?;
+    let mint = Nat::from_str(mint_wei).map_err(|_| "Invalid mint amount".to_string())?;
+    if total_supply.clone() + mint > *backing {
+        return Err(format!(
+            "Minting {} would take hstICP supply {} past the {} backed by the vault",
+            mint_wei, total_supply, backing
+        ));
+    }
+    Ok(())
+}
+
+// Reads the contract before signing anything: a missing MINTER_ROLE would
+// only surface as a reverted mint, and excess supply means the books are off.
+async fn preflight_mint(
+    op: &DepositOperation,
+    chain_id: u64,
+    contract_address: &str,
+    evm_amount: &str,
+) -> Result<(), String> {
+    ensure_deposits_open()?;
+    let (result,): (Result<HstIcpStatus, String>,) = call(
+        current_config().evm_backend_canister,
+        "hst_icp_status",
+        (chain_id, contract_address.to_string()),
+    )
+    .await
+    .map_err(|e| format!("Call failed: {:?}", e))?;
+    let status = result?;
+
+    if !status.minter_has_role {
+        return Err(format!(
+            "EVM backend address {} lacks MINTER_ROLE on {}",
+            status.minter, contract_address
+        ));
+    }
+    let backing = STATE.with(|state| mint_backing_in(&state.borrow(), op.id));
+    check_supply_headroom(&status.total_supply, evm_amount, &backing)
+}
+
+async fn submit_mint(id: u64) -> Result<String, String> {
+    let op = deposit(id)?;
+    let config = current_config();
+    let (chain_id, contract_address) = resolve_chain(op.chain_id)?;
+    let evm_amount = mint_amount(&op);
+
+    // Stays `Pulled` on failure so the reconciler retries once it is fixed.
+    if let Err(err) = preflight_mint(&op, chain_id, &contract_address, &evm_amount).await {
+        update_deposit(id, |op| op.last_error = Some(err.clone()));
+        return Err(err);
+    }
+
+    update_deposit(id, |op| op.status = DepositStatus::MintSubmitted);
+
+    let result: Result<(Result<SubmittedTransaction, String>,), _> = call(
+        config.evm_backend_canister,
+        "mint",
+        (
+            chain_id,
+            contract_address,
+            op.eth_address.clone(),
+            evm_amount.clone(),
+        ),
+    )
+    .await;
+
+    match result {
+        Ok((Ok(SubmittedTransaction { tx_hash }),)) => {
+            let now = ic_cdk::api::time();
+            STATE.with(|state| record_mint_sent_in(&mut state.borrow_mut(), id, &tx_hash, now));
+            Ok(tx_hash)
+        }
+        Ok((Err(e),)) => {
+            record_mint_failure();
+            Err(fail_with(id, DepositStatus::RefundPending, e))
+        }
+        // The backend trapped part-way through: the transaction may or may not have
+        // been broadcast, so leave the deposit for a controller to resolve.
+        Err((RejectionCode::CanisterError, msg)) => Err(fail_with(
+            id,
+            DepositStatus::MintSubmitted,
+            format!("Mint outcome unknown: {}", msg),
+        )),
+        Err(e) => Err(fail_with(
+            id,
+            DepositStatus::Pulled,
+            format!("Call failed: {:?}", e),
+        )),
+    }
+}
+
+fn record_mint_sent_in(state: &mut State, id: u64, tx_hash: &str, now_ns: u64) {
+    update_deposit_in(state, id, now_ns, |op| {
+        op.mint_tx_hash = Some(tx_hash.to_string());
+        op.mint_submitted_at_ns = Some(now_ns);
+        op.mint_attempts = Some(op.mint_attempts.unwrap_or(0) + 1);
+        op.confirmations = None;
+        op.last_error = None;
+    });
+}
+
+fn confirm_mint_in(state: &mut State, id: u64, now_ns: u64) {
+    let Some(op) = state.deposits.get(&id).cloned() else {
+        return;
+    };
+    let evm_amount = mint_amount(&op);
+    let shares = Nat::from_str(&evm_amount).unwrap_or_default();
+    let protocol_fee = op.protocol_fee.clone().unwrap_or_else(|| Nat::from(0u64));
+    apply_successful_deposit_in(
+        state,
+        op.caller,
+        &(op.amount.clone() - protocol_fee.clone()),
+        &shares,
+    );
+    accrue_in(state, FeeKind::Deposit, &protocol_fee, now_ns);
+    record_operation_in(
+        state,
+        NewOperation {
+            kind: BridgeOperationKind::Mint,
+            principal: op.caller,
+            eth_address: Some(op.eth_address.clone()),
+            amount: op.amount.clone(),
+            evm_amount: Some(evm_amount),
+            fee: protocol_fee,
+            ledger_block_index: None,
+            evm_tx_hash: op.mint_tx_hash.clone(),
+            related_operation: op.log_operation,
+            chain_id: op.chain_id,
+        },
+        now_ns,
+    );
+    update_deposit_in(state, id, now_ns, |op| {
+        op.status = DepositStatus::MintConfirmed;
+        op.last_error = None;
+    });
+    record_mint_success_in(state);
+}
+
+// A reverted or dropped mint minted nothing, so it is safe to submit again
+// until the attempt budget runs out.
+fn retry_or_refund_in(state: &mut State, id: u64, reason: String, now_ns: u64) {
+    let Some(op) = state.deposits.get(&id) else {
+        return;
+    };
+    let next_status = if op.mint_attempts.unwrap_or(0) < state.mint_tracking.max_mint_attempts {
+        DepositStatus::Pulled
+    } else {
+        DepositStatus::RefundPending
+    };
+    let reason = format!(
+        "Mint {} failed: {}",
+        op.mint_tx_hash.as_deref().unwrap_or_default(),
+        reason
+    );
+    update_deposit_in(state, id, now_ns, |op| {
+        op.status = next_status;
+        op.mint_tx_hash = None;
+        op.confirmations = None;
+        op.last_error = Some(reason);
+    });
+    record_mint_failure_in(state, now_ns);
+}
+
+async fn check_mint(id: u64) -> Result<(), String> {
+    let op = deposit(id)?;
+    let Some(tx_hash) = op.mint_tx_hash.clone() else {
+        return Ok(());
+    };
+    let evm_backend = current_config().evm_backend_canister;
+    let (chain_id, _) = resolve_chain(op.chain_id)?;
+
+    let (result,): (Result<TransactionConfirmation, String>,) = call(
+        evm_backend,
+        "get_transaction_confirmations",
+        (chain_id, tx_hash),
+    )
+    .await
+    .map_err(|e| format!("Call failed: {:?}", e))?;
+    let confirmation = result?;
+    let now = ic_cdk::api::time();
+    STATE.with(|state| record_confirmation_in(&mut state.borrow_mut(), id, confirmation, now));
+    Ok(())
+}
+
+/// Moves a submitted mint on from what the EVM backend reports about it.
+fn record_confirmation_in(
+    state: &mut State,
+    id: u64,
+    confirmation: TransactionConfirmation,
+    now_ns: u64,
+) {
+    let Some(op) = state.deposits.get(&id).cloned() else {
+        return;
+    };
+    // The EVM backend may have re-signed the mint with higher fees; track the
+    // hash that actually landed.
+    let tracked = op.mint_tx_hash.as_deref().unwrap_or_default();
+    if !confirmation.tx_hash.eq_ignore_ascii_case(tracked) {
+        update_deposit_in(state, id, now_ns, |op| {
+            op.mint_tx_hash = Some(confirmation.tx_hash.clone())
+        });
+    }
+
+    // Honour whichever is stricter: the vault-wide setting or the chain's depth.
+    let tracking = state.mint_tracking.clone();
+    let required_confirmations = tracking
+        .required_confirmations
+        .max(confirmation.required_confirmations);
+    match confirmation.status {
+        TransactionStatus::Success if confirmation.confirmations >= required_confirmations => {
+            confirm_mint_in(state, id, now_ns)
+        }
+        TransactionStatus::Success => update_deposit_in(state, id, now_ns, |op| {
+            op.confirmations = Some(confirmation.confirmations);
+        }),
+        TransactionStatus::Reverted => {
+            retry_or_refund_in(state, id, "receipt status 0".to_string(), now_ns)
+        }
+        TransactionStatus::Cancelled => {
+            retry_or_refund_in(state, id, "nonce cancelled".to_string(), now_ns)
+        }
+        // Still queued on the EVM side, possibly being sped up; resubmitting
+        // now could mint twice.
+        TransactionStatus::Pending => {}
+        TransactionStatus::NotFound => {
+            let submitted_at = op.mint_submitted_at_ns.unwrap_or(op.updated_at_ns);
+            let elapsed = now_ns.saturating_sub(submitted_at);
+            if elapsed > tracking.mint_timeout_secs * 1_000_000_000 {
+                retry_or_refund_in(state, id, "no receipt before timeout".to_string(), now_ns);
+            }
+        }
+    }
+}
+
+async fn refund(id: u64) -> Result<Nat, String> {
+    let now = ic_cdk::api::time();
+    let mut op = deposit(id)?;
+    if op.refund_created_at_ns.is_none() {
+        // The vault only received `amount`, so the refund's ledger fee comes
+        // out of it rather than out of holders' ICP.
+        let refund_amount = if op.amount > op.fee {
+            op.amount.clone() - op.fee.clone()
+        } else {
+            Nat::from(0u64)
+        };
+        update_deposit(id, |op| {
+            op.refund_created_at_ns = Some(now);
+            op.refund_amount = Some(refund_amount.clone());
+        });
+        op.refund_created_at_ns = Some(now);
+        op.refund_amount = Some(refund_amount);
+    }
+    let refund_amount = op
+        .refund_amount
+        .clone()
+        .unwrap_or_else(|| op.amount.clone());
+    let token_canister = current_config().ledger_canister;
+
+    let refund_arg = TransferArg {
+        from_subaccount: None,
+        to: op.refund_account(),
+        amount: refund_amount.clone(),
+        fee: Some(op.fee.clone()),
+        memo: Some(Memo::from(id)),
+        created_at_time: op.refund_created_at_ns,
+    };
+
+    let block_index = match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+        token_canister,
+        "icrc1_transfer",
+        (refund_arg,),
+    )
+    .await
+    {
+        Ok((Ok(block_index),)) => block_index,
+        Ok((Err(TransferError::Duplicate { duplicate_of }),)) => duplicate_of,
+        // An earlier attempt may have landed outside the deduplication window;
+        // retrying with a fresh timestamp could pay twice.
+        Ok((Err(TransferError::TooOld),)) => {
+            let message = fail_with(
+                id,
+                DepositStatus::Failed,
+                "Refund transfer failed: deduplication window expired".to_string(),
+            );
+            record_refund_failure(id, &message);
+            return Err(message);
+        }
+        // Refused by the ledger rather than unreachable, e.g. the vault no
+        // longer holds the funds.
+        Ok((Err(err),)) => {
+            let message = format!("Refund transfer failed: {:?}", err);
+            update_deposit(id, |op| op.last_error = Some(message.clone()));
+            record_refund_failure(id, &message);
+            return Err(message);
+        }
+        Err(err) => {
+            let message = format!("Refund transfer call failed: {:?}", err);
+            update_deposit(id, |op| op.last_error = Some(message.clone()));
+            return Err(message);
+        }
+    };
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let paid = refund_amount.clone() + op.fee.clone();
+        if paid > op.amount {
+            book_outflow_in(&mut state, &op.amount);
+            charge_fee_in(&mut state, &(paid - op.amount.clone()));
+        } else {
+            book_outflow_in(&mut state, &paid);
+        }
+    });
+    record_operation(NewOperation {
+        kind: BridgeOperationKind::Refund,
+        principal: op.caller,
+        eth_address: Some(op.eth_address),
+        amount: refund_amount,
+        evm_amount: None,
+        fee: op.fee,
+        ledger_block_index: Some(block_index.clone()),
+        evm_tx_hash: None,
+        related_operation: op.log_operation,
+        chain_id: op.chain_id,
+    });
+    update_deposit(id, |op| {
+        op.status = DepositStatus::Refunded;
+        op.refund_block_index = Some(block_index.clone());
+    });
+    Ok(block_index)
+}
+
+/// Drives a deposit forward from whatever state it is in. Used both by
+/// `deposit_icrc1` and by the reconciler.
+pub(crate) async fn advance_deposit(id: u64) -> Result<String, String> {
+    let _guard = DepositGuard::acquire(id)
+        .ok_or_else(|| format!("Deposit {} is already in progress", id))?;
+
+    loop {
+        let op = deposit(id)?;
+        match op.status {
+            DepositStatus::Pending => pull_funds(id).await?,
+            DepositStatus::Pulled => match submit_mint(id).await {
+                Ok(tx_hash) => return Ok(tx_hash),
+                Err(e) if deposit(id)?.status == DepositStatus::RefundPending => {
+                    let refund_message = match refund(id).await {
+                        Ok(_) => "Refunded deposit amount after mint failure.".to_string(),
+                        Err(err) => format!("Failed to refund deposit after mint failure: {}", err),
+                    };
+                    return Err(format!(
+                        "Deposit succeeded but mint failed: {}. {}",
+                        e, refund_message
+                    ));
+                }
+                Err(e) => return Err(e),
+            },
+            DepositStatus::RefundPending => {
+                return refund(id)
+                    .await
+                    .map(|block_index| format!("Deposit refunded in block {}", block_index))
+            }
+            DepositStatus::MintSubmitted if op.awaits_confirmation() => {
+                check_mint(id).await?;
+                let op = deposit(id)?;
+                if matches!(
+                    op.status,
+                    DepositStatus::MintSubmitted | DepositStatus::MintConfirmed
+                ) {
+                    return op
+                        .mint_tx_hash
+                        .ok_or_else(|| format!("Deposit {} is in state {:?}", id, op.status));
+                }
+            }
+            DepositStatus::MintSubmitted
+            | DepositStatus::MintConfirmed
+            | DepositStatus::Refunded
+            | DepositStatus::Failed => {
+                return op
+                    .mint_tx_hash
+                    .ok_or_else(|| format!("Deposit {} is in state {:?}", id, op.status))
+            }
+        }
+    }
+}
+
+async fn reconcile() {
+    let ids: Vec<u64> = STATE.with(|state| {
+        state
+            .borrow()
+            .deposits
+            .values()
+            .filter(|op| op.is_resumable())
+            .map(|op| op.id)
+            .collect()
+    });
+
+    for id in ids {
+        if IN_FLIGHT.with(|set| set.borrow().contains(&id)) {
+            continue;
+        }
+        if let Err(e) = advance_deposit(id).await {
+            ic_cdk::println!("reconcile deposit {}: {}", id, e);
+        }
+    }
+}
+
+pub(crate) fn start_reconciler() {
+    ic_cdk_timers::set_timer_interval(RECONCILE_INTERVAL, || ic_cdk::spawn(reconcile()));
+}
+
+#[ic_cdk::update]
+async fn reconcile_deposits() -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    reconcile().await;
+    Ok(())
+}
+
+/// Settles a deposit whose mint outcome is unknown. Pass the mint transaction
+/// hash if a mint was broadcast, or `None` to refund the depositor.
+#[ic_cdk::update]
+async fn resolve_deposit(id: u64, mint_tx_hash: Option<String>) -> Result<String, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let op = deposit(id)?;
+    if op.status != DepositStatus::MintSubmitted || op.mint_tx_hash.is_some() {
+        return Err(format!("Deposit {} does not need resolution", id));
+    }
+
+    match mint_tx_hash {
+        // The hash goes through the same confirmation tracking as any other mint.
+        Some(tx_hash) => {
+            let now = ic_cdk::api::time();
+            update_deposit(id, |op| {
+                op.mint_tx_hash = Some(tx_hash.clone());
+                op.mint_submitted_at_ns = Some(now);
+                op.mint_attempts = Some(op.mint_attempts.unwrap_or(0) + 1);
+                op.last_error = None;
+            });
+            Ok(tx_hash)
+        }
+        None => {
+            let _guard = DepositGuard::acquire(id)
+                .ok_or_else(|| format!("Deposit {} is already in progress", id))?;
+            update_deposit(id, |op| op.status = DepositStatus::RefundPending);
+            refund(id)
+                .await
+                .map(|block_index| format!("Deposit refunded in block {}", block_index))
+        }
+    }
+}
+
+#[ic_cdk::query]
+fn get_mint_tracking_config() -> MintTrackingConfig {
+    STATE.with(|state| state.borrow().mint_tracking.clone())
+}
+
+#[ic_cdk::update]
+fn set_mint_tracking_config(config: MintTrackingConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    if config.max_mint_attempts == 0 {
+        return Err("At least one mint attempt is required".to_string());
+    }
+    STATE.with(|state| state.borrow_mut().mint_tracking = config);
+    Ok(())
+}
+
+/// Whether the ledger refused to pull the deposit, so nothing ever moved.
+pub(crate) fn pull_refused(id: u64) -> bool {
+    deposit(id).is_ok_and(|op| op.status == DepositStatus::Failed && op.pull_block_index.is_none())
+}
+
+#[ic_cdk::query]
+fn get_deposit(id: u64) -> Option<DepositOperation> {
+    STATE.with(|state| state.borrow().deposits.get(&id).cloned())
+}
+
+#[ic_cdk::query]
+fn get_unsettled_deposits() -> Vec<DepositOperation> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .deposits
+            .values()
+            .filter(|op| op.is_resumable() || op.status == DepositStatus::MintSubmitted)
+            .cloned()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{apply_successful_deposit, deposit_op, reset_state};
+    use crate::{accounting, apply_burn_unlock, exchange_rate};
+
+    #[test]
+    fn supply_headroom_counts_in_flight_mints() {
+        let mut state = State::new();
+        let scale = 10u128.pow(10);
+        state.total_deposited = Nat::from(100u64);
+        state.share_supply = Nat::from(100 * scale);
+        for (id, status) in [
+            (1, DepositStatus::MintSubmitted),
+            (2, DepositStatus::Pulled),
+            (3, DepositStatus::Refunded),
+        ] {
+            state.deposits.insert(id, deposit_op(id, status, 10));
+        }
+
+        // 100 credited + 10 in flight + 10 for deposit 2 itself.
+        let backing = mint_backing_in(&state, 2);
+        assert_eq!(backing, Nat::from(120 * scale));
+
+        let mint = (10 * scale).to_string();
+        assert!(check_supply_headroom(&(110 * scale).to_string(), &mint, &backing).is_ok());
+        assert!(check_supply_headroom(&(110 * scale + 1).to_string(), &mint, &backing).is_err());
+    }
+
+    #[test]
+    fn supply_headroom_holds_after_unlocks_below_par() {
+        reset_state();
+        let caller = Principal::anonymous();
+        let scale = 10u128.pow(10);
+        apply_successful_deposit(caller, &Nat::from(100u64), &Nat::from(100 * scale));
+        STATE.with(|state| accounting::charge_fee_in(&mut state.borrow_mut(), &Nat::from(10u64)));
+        let burned = Nat::from(50 * scale);
+        let value = STATE.with(|state| exchange_rate::icp_for_in(&state.borrow(), &burned));
+        apply_burn_unlock(&caller, &burned, &value).unwrap();
+        STATE.with(|state| exchange_rate::burn_shares_in(&mut state.borrow_mut(), &burned));
+
+        // 45 units back the 50 hstICP left; the next deposit of 9 units is
+        // priced at the same rate and fits exactly.
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let mut op = deposit_op(1, DepositStatus::Pulled, 9);
+            op.evm_amount = Some(
+                exchange_rate::shares_for_in(&state, &op.amount)
+                    .0
+                    .to_string(),
+            );
+            let mint = op.evm_amount.clone().unwrap();
+            assert_eq!(mint, (10 * scale).to_string());
+            state.deposits.insert(1, op);
+
+            let backing = mint_backing_in(&state, 1);
+            assert_eq!(backing, Nat::from(60 * scale));
+            let supply = (50 * scale).to_string();
+            assert!(check_supply_headroom(&supply, &mint, &backing).is_ok());
+            let supply = (50 * scale + 1).to_string();
+            assert!(check_supply_headroom(&supply, &mint, &backing).is_err());
+        });
+    }
+
+    #[test]
+    fn deposits_move_from_pull_to_confirmed_mint() {
+        let scale = 10u128.pow(10);
+        let mut state = State::new();
+        state
+            .deposits
+            .insert(0, deposit_op(0, DepositStatus::Pending, 100));
+        assert!(state.deposits[&0].is_resumable());
+
+        record_pull_in(&mut state, 0, Nat::from(7u64), 2);
+        let op = &state.deposits[&0];
+        assert_eq!(op.status, DepositStatus::Pulled);
+        assert_eq!(op.pull_block_index, Some(Nat::from(7u64)));
+        assert_eq!(op.evm_amount, Some((100 * scale).to_string()));
+        assert_eq!(op.log_operation, Some(0));
+        assert_eq!(state.books.liquid, Nat::from(100u64));
+
+        // Marked before the mint call: until a hash comes back the outcome
+        // is unknown and only a controller can settle it.
+        update_deposit_in(&mut state, 0, 3, |op| {
+            op.status = DepositStatus::MintSubmitted
+        });
+        assert!(!state.deposits[&0].is_resumable());
+
+        record_mint_sent_in(&mut state, 0, "0xabc", 4);
+        let op = &state.deposits[&0];
+        assert!(op.awaits_confirmation() && op.is_resumable());
+        assert_eq!(op.mint_attempts, Some(1));
+        assert_eq!(op.mint_submitted_at_ns, Some(4));
+
+        state.consecutive_mint_failures = 2;
+        confirm_mint_in(&mut state, 0, 5);
+        let op = &state.deposits[&0];
+        assert_eq!(op.status, DepositStatus::MintConfirmed);
+        assert!(!op.is_resumable());
+        assert_eq!(state.user_balances[&op.caller], Nat::from(100u64));
+        assert_eq!(state.share_supply, Nat::from(100 * scale));
+        assert_eq!(state.total_deposited, Nat::from(100u64));
+        assert_eq!(state.consecutive_mint_failures, 0);
+        assert_eq!(state.operations[1].related_operation, Some(0));
+    }
+
+    #[test]
+    fn failed_mints_are_retried_then_refunded() {
+        let mut state = State::new();
+        state.circuit_breaker.max_consecutive_mint_failures = 0;
+        let mut op = deposit_op(0, DepositStatus::Pulled, 100);
+        op.pull_block_index = Some(Nat::from(7u64));
+        state.deposits.insert(0, op);
+
+        for attempt in 1..=state.mint_tracking.max_mint_attempts {
+            update_deposit_in(&mut state, 0, 1, |op| {
+                op.status = DepositStatus::MintSubmitted
+            });
+            record_mint_sent_in(&mut state, 0, "0xabc", 1);
+            retry_or_refund_in(&mut state, 0, "receipt status 0".to_string(), 2);
+            let op = &state.deposits[&0];
+            let expected = if attempt < state.mint_tracking.max_mint_attempts {
+                DepositStatus::Pulled
+            } else {
+                DepositStatus::RefundPending
+            };
+            assert_eq!(op.status, expected);
+            assert_eq!(op.mint_tx_hash, None);
+            assert_eq!(
+                op.last_error.as_deref(),
+                Some("Mint 0xabc failed: receipt status 0")
+            );
+            assert!(op.is_resumable());
+        }
+        assert_eq!(
+            state.consecutive_mint_failures,
+            state.mint_tracking.max_mint_attempts
+        );
+
+        // A pull the ledger refused, or a refund past deduplication, ends it.
+        fail_in(
+            &mut state,
+            0,
+            DepositStatus::Failed,
+            "Transfer failed".to_string(),
+            3,
+        );
+        let op = &state.deposits[&0];
+        assert_eq!(op.status, DepositStatus::Failed);
+        assert!(!op.is_resumable());
+        assert_eq!(op.updated_at_ns, 3);
+    }
+
+    #[test]
+    fn interrupted_deposits_resume_where_they_stopped() {
+        let mut state = State::new();
+        for (id, status) in [
+            (0, DepositStatus::Pending),
+            (1, DepositStatus::Pulled),
+            (2, DepositStatus::RefundPending),
+        ] {
+            state.deposits.insert(id, deposit_op(id, status, 100));
+        }
+
+        // A pull whose call failed is re-sent, deduplicated by the ledger, and
+        // booked once.
+        update_deposit_in(&mut state, 0, 5, |op| {
+            op.last_error = Some("Call failed".to_string())
+        });
+        assert!(state.deposits[&0].is_resumable());
+        record_pull_in(&mut state, 0, Nat::from(9u64), 6);
+        assert_eq!(state.deposits[&0].last_error, None);
+        assert_eq!(state.books.liquid, Nat::from(100u64));
+
+        // A mint whose preflight failed stays pulled until it passes.
+        update_deposit_in(&mut state, 1, 5, |op| {
+            op.last_error = Some("Supply check failed".to_string())
+        });
+        assert_eq!(state.deposits[&1].status, DepositStatus::Pulled);
+        assert!(state.deposits[&1].is_resumable());
+
+        // A refund that did not go through is retried.
+        assert!(state.deposits[&2].is_resumable());
+
+        // A mint still being tracked resumes by polling its hash.
+        record_mint_sent_in(&mut state, 0, "0xabc", 7);
+        update_deposit_in(&mut state, 0, 7, |op| {
+            op.status = DepositStatus::MintSubmitted
+        });
+        assert!(state.deposits[&0].awaits_confirmation());
+        assert!(state.deposits[&0].is_resumable());
+    }
+
+    #[test]
+    fn mint_polling_waits_for_confirmations_and_times_out() {
+        let secs = 1_000_000_000u64;
+        let poll = |tx_hash: &str, status, confirmations| TransactionConfirmation {
+            tx_hash: tx_hash.to_string(),
+            status,
+            confirmations,
+            required_confirmations: 20,
+        };
+        let mut state = State::new();
+        state
+            .deposits
+            .insert(0, deposit_op(0, DepositStatus::Pulled, 100));
+        record_pull_in(&mut state, 0, Nat::from(7u64), 0);
+        update_deposit_in(&mut state, 0, 0, |op| {
+            op.status = DepositStatus::MintSubmitted
+        });
+        record_mint_sent_in(&mut state, 0, "0xabc", 0);
+
+        // Pending mints are left alone, however long they take.
+        let timeout = state.mint_tracking.mint_timeout_secs * secs;
+        record_confirmation_in(
+            &mut state,
+            0,
+            poll("0xabc", TransactionStatus::Pending, 0),
+            timeout + 1,
+        );
+        assert_eq!(state.deposits[&0].status, DepositStatus::MintSubmitted);
+
+        // A speed-up landed under a new hash; the chain asks for more
+        // confirmations than the vault-wide 12.
+        record_confirmation_in(
+            &mut state,
+            0,
+            poll("0xDEF", TransactionStatus::Success, 12),
+            1,
+        );
+        let op = &state.deposits[&0];
+        assert_eq!(op.status, DepositStatus::MintSubmitted);
+        assert_eq!(op.mint_tx_hash.as_deref(), Some("0xDEF"));
+        assert_eq!(op.confirmations, Some(12));
+
+        record_confirmation_in(
+            &mut state,
+            0,
+            poll("0xdef", TransactionStatus::Success, 20),
+            2,
+        );
+        let op = &state.deposits[&0];
+        assert_eq!(op.status, DepositStatus::MintConfirmed);
+        assert_eq!(op.mint_tx_hash.as_deref(), Some("0xDEF"));
+        assert_eq!(state.user_balances[&op.caller], Nat::from(100u64));
+
+        // Without a receipt the mint is retried only after the timeout.
+        state
+            .deposits
+            .insert(1, deposit_op(1, DepositStatus::Pulled, 100));
+        record_mint_sent_in(&mut state, 1, "0x123", 10);
+        update_deposit_in(&mut state, 1, 10, |op| {
+            op.status = DepositStatus::MintSubmitted
+        });
+        record_confirmation_in(
+            &mut state,
+            1,
+            poll("0x123", TransactionStatus::NotFound, 0),
+            10 + timeout,
+        );
+        assert_eq!(state.deposits[&1].status, DepositStatus::MintSubmitted);
+        record_confirmation_in(
+            &mut state,
+            1,
+            poll("0x123", TransactionStatus::NotFound, 0),
+            11 + timeout,
+        );
+        let op = &state.deposits[&1];
+        assert_eq!(op.status, DepositStatus::Pulled);
+        assert_eq!(op.mint_tx_hash, None);
+        assert_eq!(
+            op.last_error.as_deref(),
+            Some("Mint 0x123 failed: no receipt before timeout")
+        );
+    }
+}
@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::{State, STATE};
+use candid::{CandidType, Nat};
+use serde::{Deserialize, Serialize};
+
+// Oldest entries are dropped past this.
+const MAX_RATE_HISTORY: usize = 1_000;
+const MAX_PAGE_SIZE: u64 = 100;
+pub(crate) const WEI_PER_UNIT: u128 = 10u128.pow(10);
+
+/// Price of hstICP: `total_icp` ledger units back `total_shares` hstICP wei.
+/// While either is zero hstICP is minted and redeemed 1:1.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ExchangeRate {
+    pub total_icp: Nat,
+    pub total_shares: Nat,
+    pub timestamp_ns: u64,
+}
+
+impl ExchangeRate {
+    fn same_price(&self, other: &ExchangeRate) -> bool {
+        self.total_icp.clone() * other.total_shares.clone()
+            == other.total_icp.clone() * self.total_shares.clone()
+    }
+}
+
+fn at_par(state: &State) -> bool {
+    state.share_supply == 0u64 || state.total_deposited == 0u64
+}
+
+pub(crate) fn rate_in(state: &State, timestamp_ns: u64) -> ExchangeRate {
+    ExchangeRate {
+        total_icp: state.total_deposited.clone(),
+        total_shares: state.share_supply.clone(),
+        timestamp_ns,
+    }
+}
+
+/// hstICP wei minted for `amount` ledger units, rounded down.
+pub(crate) fn shares_for_in(state: &State, amount: &Nat) -> Nat {
+    if at_par(state) {
+        return amount.clone() * Nat::from(WEI_PER_UNIT);
+    }
+    amount.clone() * state.share_supply.clone() / state.total_deposited.clone()
+}
+
+/// Ledger units paid for `shares` hstICP wei, rounded down.
+pub(crate) fn icp_for_in(state: &State, shares: &Nat) -> Nat {
+    if at_par(state) {
+        return shares.clone() / Nat::from(WEI_PER_UNIT);
+    }
+    shares.clone() * state.total_deposited.clone() / state.share_supply.clone()
+}
+
+/// Removes burned hstICP from the supply; it cannot go below zero.
+pub(crate) fn burn_shares_in(state: &mut State, shares: &Nat) {
+    if state.share_supply > *shares {
+        state.share_supply -= shares.clone();
+    } else {
+        state.share_supply = Nat::from(0u64);
+    }
+    sweep_residue_in(state);
+}
+
+/// With no hstICP outstanding, whatever is left of `total_deposited` (rounding
+/// left behind by unlocks, rewards landing late) belongs to no holder. It goes
+/// to the treasury rather than to the next depositor, who mints at par.
+pub(crate) fn sweep_residue_in(state: &mut State) {
+    if state.share_supply == 0u64 && state.total_deposited > 0u64 {
+        state.treasury_balance += state.total_deposited.clone();
+        state.total_deposited = Nat::from(0u64);
+    }
+}
+
+/// Appends the current rate to the history unless the price is unchanged.
+pub(crate) fn record_rate_in(state: &mut State, timestamp_ns: u64) {
+    let rate = rate_in(state, timestamp_ns);
+    if state
+        .rate_history
+        .last()
+        .is_some_and(|last| last.same_price(&rate))
+    {
+        return;
+    }
+    state.rate_history.push(rate);
+    if state.rate_history.len() > MAX_RATE_HISTORY {
+        let excess = state.rate_history.len() - MAX_RATE_HISTORY;
+        state.rate_history.drain(..excess);
+    }
+}
+
+#[ic_cdk::query]
+fn get_exchange_rate() -> ExchangeRate {
+    STATE.with(|state| rate_in(&state.borrow(), ic_cdk::api::time()))
+}
+
+// Newest entries first.
+#[ic_cdk::query]
+fn get_exchange_rate_history(offset: u64, limit: u64) -> Vec<ExchangeRate> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .rate_history
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+/// hstICP wei a deposit of `amount` ledger units would mint at the current rate.
+#[ic_cdk::query]
+fn quote_mint(amount: Nat) -> Nat {
+    STATE.with(|state| shares_for_in(&state.borrow(), &amount))
+}
+
+/// Ledger units burning `shares` hstICP wei would unlock at the current rate,
+/// before the transfer fee.
+#[ic_cdk::query]
+fn quote_unlock(shares: Nat) -> Nat {
+    STATE.with(|state| icp_for_in(&state.borrow(), &shares))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::detected_burn;
+    use crate::{unlocks, StableState};
+
+    #[test]
+    fn exchange_rate_prices_mints_and_unlocks_in_shares() {
+        let mut state = State::new();
+        // Nothing outstanding yet: 1:1.
+        assert_eq!(
+            shares_for_in(&state, &Nat::from(3u64)),
+            Nat::from(30_000_000_000u64)
+        );
+        assert_eq!(
+            icp_for_in(&state, &Nat::from(39_999_999_999u64)),
+            Nat::from(3u64)
+        );
+
+        // 300 units back 200 hstICP units after rewards.
+        state.total_deposited = Nat::from(300u64);
+        state.share_supply = Nat::from(2_000_000_000_000u64);
+        assert_eq!(
+            shares_for_in(&state, &Nat::from(3u64)),
+            Nat::from(20_000_000_000u64)
+        );
+        assert_eq!(
+            shares_for_in(&state, &Nat::from(1u64)),
+            Nat::from(6_666_666_666u64)
+        );
+        assert_eq!(
+            icp_for_in(&state, &Nat::from(20_000_000_000u64)),
+            Nat::from(3u64)
+        );
+        assert_eq!(
+            icp_for_in(&state, &Nat::from(6_666_666_666u64)),
+            Nat::from(0u64)
+        );
+
+        // Burns are priced at the rate when first seen; one that does not
+        // cover the ledger fee its payout pays is held for review.
+        state.burn_unlocks.clear();
+        let mut dust = detected_burn(0, 1);
+        dust.amount_wei = "10000000001".to_string();
+        unlocks::ingest_burn_in(&mut state, dust, 0);
+        assert_eq!(state.burn_unlocks[&0].amount, Nat::from(1u64));
+        assert_eq!(
+            state.burn_unlocks[&0].status,
+            unlocks::BurnUnlockStatus::NeedsReview
+        );
+
+        record_rate_in(&mut state, 1);
+        state.total_deposited = Nat::from(600u64);
+        state.share_supply = Nat::from(4_000_000_000_000u64);
+        record_rate_in(&mut state, 2);
+        assert_eq!(state.rate_history.len(), 1);
+        state.total_deposited = Nat::from(601u64);
+        record_rate_in(&mut state, 3);
+        assert_eq!(state.rate_history.len(), 2);
+
+        // State saved before share accounting was minted 1:1.
+        let mut stable = StableState::from(&state);
+        stable.share_supply = None;
+        let restored: State = stable.into();
+        assert_eq!(restored.share_supply, Nat::from(6_010_000_000_000u64));
+        assert_eq!(restored.rate_history.len(), 2);
+    }
+}
@@ -0,0 +1,323 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::accounting::book_outflow_in;
+use crate::transfer_log::{record_operation, BridgeOperationKind, NewOperation};
+use crate::{
+    current_config, ensure_controller, refresh_transfer_fee_if_stale, State, DEFAULT_TRANSFER_FEE,
+    STATE,
+};
+use candid::{CandidType, Nat};
+use ic_cdk::call;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
+use serde::{Deserialize, Serialize};
+
+const BPS_DENOMINATOR: u64 = 10_000;
+// Highest fee of any kind, in basis points.
+const MAX_FEE_BPS: u64 = 2_000;
+const PERIOD_NS: u64 = 24 * 60 * 60 * 1_000_000_000;
+// Oldest periods are dropped past this: about two years of daily totals.
+const MAX_PERIODS: usize = 730;
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct FeeConfig {
+    /// Share of each deposit kept before minting, in basis points.
+    pub deposit_fee_bps: u64,
+    /// Share of each unlock kept from the payout, in basis points.
+    pub withdraw_fee_bps: u64,
+    /// Share of disbursed staking rewards kept, in basis points.
+    pub reward_fee_bps: u64,
+    /// Where `sweep_treasury` sends accrued fees.
+    pub treasury: Option<Account>,
+}
+
+impl FeeConfig {
+    fn validate(&self) -> Result<(), String> {
+        let highest = self
+            .deposit_fee_bps
+            .max(self.withdraw_fee_bps)
+            .max(self.reward_fee_bps);
+        if highest > MAX_FEE_BPS {
+            return Err(format!("Fees cannot exceed {} bps", MAX_FEE_BPS));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FeeKind {
+    Deposit,
+    Withdraw,
+    Reward,
+}
+
+/// Fees accrued during the UTC day starting at `start_ns`, in ledger units.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct FeePeriod {
+    pub start_ns: u64,
+    pub deposit: Nat,
+    pub withdraw: Nat,
+    pub reward: Nat,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TreasuryStatus {
+    pub treasury: Option<Account>,
+    /// Accrued and not yet swept; held in the vault's main account but not
+    /// part of the ICP under management.
+    pub balance: Nat,
+    pub total_accrued: Nat,
+    pub total_swept: Nat,
+}
+
+/// Fee of `kind` on `amount`, rounded down.
+pub(crate) fn fee_for_in(state: &State, kind: FeeKind, amount: &Nat) -> Nat {
+    let bps = match kind {
+        FeeKind::Deposit => state.fee_config.deposit_fee_bps,
+        FeeKind::Withdraw => state.fee_config.withdraw_fee_bps,
+        FeeKind::Reward => state.fee_config.reward_fee_bps,
+    };
+    amount.clone() * Nat::from(bps) / Nat::from(BPS_DENOMINATOR)
+}
+
+/// `liquid` ledger units less the accrued fees held alongside them.
+pub(crate) fn untreasured_in(state: &State, liquid: &Nat) -> Nat {
+    if *liquid > state.treasury_balance {
+        liquid.clone() - state.treasury_balance.clone()
+    } else {
+        Nat::from(0u64)
+    }
+}
+
+/// Credits `fee` to the treasury and to the period containing `now_ns`.
+pub(crate) fn accrue_in(state: &mut State, kind: FeeKind, fee: &Nat, now_ns: u64) {
+    if *fee == 0u64 {
+        return;
+    }
+    state.treasury_balance += fee.clone();
+    state.fees_accrued += fee.clone();
+
+    let start_ns = now_ns - now_ns % PERIOD_NS;
+    if state
+        .fee_periods
+        .last()
+        .is_none_or(|period| period.start_ns != start_ns)
+    {
+        state.fee_periods.push(FeePeriod {
+            start_ns,
+            deposit: Nat::from(0u64),
+            withdraw: Nat::from(0u64),
+            reward: Nat::from(0u64),
+        });
+        if state.fee_periods.len() > MAX_PERIODS {
+            let excess = state.fee_periods.len() - MAX_PERIODS;
+            state.fee_periods.drain(..excess);
+        }
+    }
+    let period = state
+        .fee_periods
+        .last_mut()
+        .expect("period was just ensured");
+    match kind {
+        FeeKind::Deposit => period.deposit += fee.clone(),
+        FeeKind::Withdraw => period.withdraw += fee.clone(),
+        FeeKind::Reward => period.reward += fee.clone(),
+    }
+}
+
+pub(crate) fn accrue(kind: FeeKind, fee: &Nat) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| accrue_in(&mut state.borrow_mut(), kind, fee, now));
+}
+
+/// Sends `amount`, or everything accrued, to the treasury account. The
+/// ledger fee is paid out of the treasury balance.
+#[ic_cdk::update]
+async fn sweep_treasury(amount: Option<Nat>) -> Result<Nat, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let treasury = STATE
+        .with(|state| state.borrow().fee_config.treasury)
+        .ok_or_else(|| "No treasury account is configured".to_string())?;
+    let fee = refresh_transfer_fee_if_stale(&Nat::from(DEFAULT_TRANSFER_FEE)).await?;
+
+    // Taken off the balance before the transfer so that concurrent sweeps
+    // cannot spend it twice.
+    let amount = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let available = if state.treasury_balance > fee {
+            state.treasury_balance.clone() - fee.clone()
+        } else {
+            Nat::from(0u64)
+        };
+        let amount = amount.unwrap_or_else(|| available.clone());
+        if amount == 0u64 || amount > available {
+            return Err(format!(
+                "Only {} can be swept after the transfer fee",
+                available
+            ));
+        }
+        state.treasury_balance -= amount.clone() + fee.clone();
+        Ok(amount)
+    })?;
+
+    let transfer_arg = TransferArg {
+        from_subaccount: None,
+        to: treasury,
+        amount: amount.clone(),
+        fee: Some(fee.clone()),
+        memo: None,
+        created_at_time: None,
+    };
+    match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+        current_config().ledger_canister,
+        "icrc1_transfer",
+        (transfer_arg,),
+    )
+    .await
+    {
+        Ok((Ok(block_index),)) => {
+            STATE.with(|state| {
+                let mut state = state.borrow_mut();
+                state.treasury_swept += amount.clone();
+                book_outflow_in(&mut state, &(amount.clone() + fee.clone()));
+            });
+            record_operation(NewOperation {
+                kind: BridgeOperationKind::TreasurySweep,
+                principal: treasury.owner,
+                eth_address: None,
+                amount,
+                evm_amount: None,
+                fee,
+                ledger_block_index: Some(block_index.clone()),
+                evm_tx_hash: None,
+                related_operation: None,
+                chain_id: None,
+            });
+            Ok(block_index)
+        }
+        Ok((Err(e),)) => {
+            restore_treasury(amount, fee);
+            Err(format!("Treasury transfer failed: {:?}", e))
+        }
+        Err(e) => {
+            restore_treasury(amount, fee);
+            Err(format!("Treasury transfer call failed: {:?}", e))
+        }
+    }
+}
+
+fn restore_treasury(amount: Nat, fee: Nat) {
+    STATE.with(|state| state.borrow_mut().treasury_balance += amount + fee);
+}
+
+#[ic_cdk::query]
+fn get_fee_config() -> FeeConfig {
+    STATE.with(|state| state.borrow().fee_config.clone())
+}
+
+#[ic_cdk::update]
+fn set_fee_config(config: FeeConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    config.validate()?;
+    STATE.with(|state| state.borrow_mut().fee_config = config);
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn get_treasury() -> TreasuryStatus {
+    STATE.with(|state| {
+        let state = state.borrow();
+        TreasuryStatus {
+            treasury: state.fee_config.treasury,
+            balance: state.treasury_balance.clone(),
+            total_accrued: state.fees_accrued.clone(),
+            total_swept: state.treasury_swept.clone(),
+        }
+    })
+}
+
+// Newest periods first; days without fees are skipped.
+#[ic_cdk::query]
+fn get_fee_periods(offset: u64, limit: u64) -> Vec<FeePeriod> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .fee_periods
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::detected_burn;
+    use crate::{unlocks, StableState};
+
+    #[test]
+    fn protocol_fees_are_kept_from_payouts_and_accrued_per_day() {
+        let mut state = State::new();
+        state.transfer_fee = Nat::from(10u64);
+        state.total_deposited = Nat::from(10_000u64);
+        state.fee_config = FeeConfig {
+            deposit_fee_bps: 10,
+            withdraw_fee_bps: 50,
+            reward_fee_bps: 1_000,
+            treasury: None,
+        };
+        assert_eq!(
+            fee_for_in(&state, FeeKind::Deposit, &Nat::from(1_999u64)),
+            Nat::from(1u64)
+        );
+        assert_eq!(
+            fee_for_in(&state, FeeKind::Reward, &Nat::from(999u64)),
+            Nat::from(99u64)
+        );
+
+        // The withdraw fee comes off the payout and counts towards demand.
+        unlocks::ingest_burn_in(&mut state, detected_burn(0, 1_000), 0);
+        assert_eq!(state.burn_unlocks[&0].amount, Nat::from(995u64));
+        assert_eq!(state.burn_unlocks[&0].protocol_fee, Some(Nat::from(5u64)));
+        assert_eq!(unlocks::unsettled_demand_in(&state), Nat::from(1_000u64));
+
+        // Fees land in the period of the day they accrue.
+        let day_ns = 24 * 60 * 60 * 1_000_000_000u64;
+        accrue_in(&mut state, FeeKind::Deposit, &Nat::from(3u64), 1);
+        accrue_in(&mut state, FeeKind::Withdraw, &Nat::from(5u64), day_ns - 1);
+        accrue_in(&mut state, FeeKind::Reward, &Nat::from(7u64), day_ns);
+        accrue_in(&mut state, FeeKind::Reward, &Nat::from(0u64), 3 * day_ns);
+        assert_eq!(state.fee_periods.len(), 2);
+        assert_eq!(state.fee_periods[0].start_ns, 0);
+        assert_eq!(state.fee_periods[0].deposit, Nat::from(3u64));
+        assert_eq!(state.fee_periods[0].withdraw, Nat::from(5u64));
+        assert_eq!(state.fee_periods[1].start_ns, day_ns);
+        assert_eq!(state.fee_periods[1].reward, Nat::from(7u64));
+        assert_eq!(state.treasury_balance, Nat::from(15u64));
+        assert_eq!(state.fees_accrued, Nat::from(15u64));
+
+        // Accrued fees are not liquid ICP the queue or staking may use.
+        let payable = unlocks::schedule_queue_in(&mut state, &Nat::from(1_014u64), 0);
+        assert!(payable.is_empty());
+        let payable = unlocks::schedule_queue_in(&mut state, &Nat::from(1_015u64), 0);
+        assert_eq!(payable.len(), 1);
+        assert_eq!(untreasured_in(&state, &Nat::from(10u64)), Nat::from(0u64));
+
+        // State saved before fees existed charges none.
+        let mut stable = StableState::from(&state);
+        stable.fee_config = None;
+        stable.treasury_balance = None;
+        stable.fee_periods = None;
+        let restored: State = stable.into();
+        assert_eq!(restored.fee_config.withdraw_fee_bps, 0);
+        assert_eq!(restored.treasury_balance, Nat::from(0u64));
+        assert!(restored.fee_periods.is_empty());
+        assert_eq!(restored.fees_accrued, Nat::from(15u64));
+    }
+}
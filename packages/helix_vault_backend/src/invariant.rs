@@ -0,0 +1,301 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::deposits::{in_flight_mint_shares_in, in_flight_mints_in};
+use crate::exchange_rate::{icp_for_in, shares_for_in};
+use crate::pause::{trip_in, PauseTarget};
+use crate::{current_config, ensure_controller, State, STATE};
+use candid::{CandidType, Int, Nat};
+use ic_cdk::call;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+const INVARIANT_CHECK_INTERVAL: Duration = Duration::from_secs(10 * 60);
+// Oldest snapshots are dropped past this; at one check every ten minutes
+// this is roughly a week of history.
+const MAX_SNAPSHOTS: usize = 1_000;
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct InvariantConfig {
+    /// Unbacked hstICP supply, in ledger units, tolerated before the check
+    /// counts as breached.
+    pub tolerance: Nat,
+    /// Pause deposits and unlocks when the tolerance is exceeded.
+    pub auto_pause: bool,
+}
+
+impl Default for InvariantConfig {
+    fn default() -> Self {
+        Self {
+            tolerance: Nat::from(0u64),
+            auto_pause: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ChainSupply {
+    pub chain_id: u64,
+    pub contract: String,
+    pub total_supply_wei: Nat,
+}
+
+/// Both sides of the bridge as read by one check. Supply is compared in hstICP
+/// wei against the shares the vault minted; ledger amounts use 8 decimals and
+/// value hstICP at the current exchange rate.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SupplySnapshot {
+    pub id: u64,
+    pub timestamp_ns: u64,
+    pub total_deposited: Nat,
+    /// Deposits whose mint was submitted but not yet credited; their hstICP
+    /// may already exist.
+    pub in_flight_mints: Nat,
+    pub supplies: Vec<ChainSupply>,
+    /// Ledger units the on-chain supply redeems for.
+    pub total_supply: Nat,
+    /// `total_supply` less what the vault's own shares redeem for. Negative
+    /// while holders have burned hstICP without unlocking yet, which the
+    /// vault cannot see.
+    pub drift: Int,
+    /// Ledger units of supply not covered by minted or in-flight shares.
+    pub unbacked_supply: Nat,
+    pub tolerance_exceeded: bool,
+    /// hstICP wei the vault has minted and not yet seen burned.
+    pub share_supply: Option<Nat>,
+    /// hstICP wei of the in-flight mints.
+    pub in_flight_mint_shares: Option<Nat>,
+}
+
+/// Evaluates `supplies` against the vault's books. Only unbacked supply is
+/// held against the tolerance: a shortfall is expected from pending unlocks.
+pub(crate) fn snapshot_in(
+    state: &State,
+    supplies: Vec<ChainSupply>,
+    timestamp_ns: u64,
+) -> SupplySnapshot {
+    let total_supply_wei = supplies.iter().fold(Nat::from(0u64), |sum, supply| {
+        sum + supply.total_supply_wei.clone()
+    });
+    let in_flight_mint_shares = in_flight_mint_shares_in(state);
+    let backing = state.share_supply.clone() + in_flight_mint_shares.clone();
+    let unbacked_shares = if total_supply_wei > backing {
+        total_supply_wei.clone() - backing
+    } else {
+        Nat::from(0u64)
+    };
+    let total_supply = icp_for_in(state, &total_supply_wei);
+    let unbacked_supply = icp_for_in(state, &unbacked_shares);
+
+    SupplySnapshot {
+        id: state.next_snapshot_id,
+        timestamp_ns,
+        total_deposited: state.total_deposited.clone(),
+        in_flight_mints: in_flight_mints_in(state),
+        supplies,
+        drift: Int::from(total_supply.clone()) - Int::from(icp_for_in(state, &state.share_supply)),
+        total_supply,
+        tolerance_exceeded: unbacked_shares
+            > shares_for_in(state, &state.invariant_config.tolerance),
+        unbacked_supply,
+        share_supply: Some(state.share_supply.clone()),
+        in_flight_mint_shares: Some(in_flight_mint_shares),
+    }
+}
+
+pub(crate) fn record_snapshot_in(state: &mut State, snapshot: SupplySnapshot) {
+    if snapshot.tolerance_exceeded && state.invariant_config.auto_pause {
+        trip_in(
+            state,
+            PauseTarget::Both,
+            format!(
+                "Supply invariant breached: {} units of hstICP are unbacked",
+                snapshot.unbacked_supply
+            ),
+            snapshot.timestamp_ns,
+        );
+    }
+    state.next_snapshot_id = snapshot.id + 1;
+    state.supply_snapshots.push(snapshot);
+    if state.supply_snapshots.len() > MAX_SNAPSHOTS {
+        let excess = state.supply_snapshots.len() - MAX_SNAPSHOTS;
+        state.supply_snapshots.drain(..excess);
+    }
+}
+
+async fn read_total_supply(chain_id: u64, contract: String) -> Result<ChainSupply, String> {
+    let (result,): (Result<String, String>,) = call(
+        current_config().evm_backend_canister,
+        "hst_icp_total_supply",
+        (chain_id, contract.clone()),
+    )
+    .await
+    .map_err(|e| format!("Call failed: {:?}", e))?;
+    let total_supply_wei = Nat::from_str(&result?)
+        .map_err(|_| format!("Invalid total supply on chain {}", chain_id))?;
+    Ok(ChainSupply {
+        chain_id,
+        contract,
+        total_supply_wei,
+    })
+}
+
+// Every chain must answer: a partial sum would understate supply.
+async fn check_invariant() -> Result<SupplySnapshot, String> {
+    let contracts: Vec<(u64, String)> = STATE.with(|state| {
+        state
+            .borrow()
+            .chain_contracts
+            .iter()
+            .map(|(chain_id, contract)| (*chain_id, contract.clone()))
+            .collect()
+    });
+
+    let mut supplies = Vec::with_capacity(contracts.len());
+    for (chain_id, contract) in contracts {
+        supplies.push(read_total_supply(chain_id, contract).await?);
+    }
+
+    // Read the books after the supplies so mints confirmed meanwhile are
+    // counted on both sides.
+    let now = ic_cdk::api::time();
+    Ok(STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let snapshot = snapshot_in(&state, supplies, now);
+        record_snapshot_in(&mut state, snapshot.clone());
+        snapshot
+    }))
+}
+
+pub(crate) fn start_invariant_monitor() {
+    ic_cdk_timers::set_timer_interval(INVARIANT_CHECK_INTERVAL, || {
+        ic_cdk::spawn(async {
+            if let Err(e) = check_invariant().await {
+                ic_cdk::println!("supply invariant check: {}", e);
+            }
+        })
+    });
+}
+
+#[ic_cdk::update]
+async fn check_supply_invariant() -> Result<SupplySnapshot, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    check_invariant().await
+}
+
+/// The most recent snapshot, if any check has completed.
+#[ic_cdk::query]
+fn get_supply_drift() -> Option<SupplySnapshot> {
+    STATE.with(|state| state.borrow().supply_snapshots.last().cloned())
+}
+
+// Newest entries first.
+#[ic_cdk::query]
+fn get_supply_snapshots(offset: u64, limit: u64) -> Vec<SupplySnapshot> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .supply_snapshots
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_invariant_config() -> InvariantConfig {
+    STATE.with(|state| state.borrow().invariant_config.clone())
+}
+
+#[ic_cdk::update]
+fn set_invariant_config(config: InvariantConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    STATE.with(|state| state.borrow_mut().invariant_config = config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{apply_successful_deposit, reset_state};
+    use crate::{accounting, apply_burn_unlock, exchange_rate, CONTRACT_ADDRESS, DEFAULT_CHAIN_ID};
+    use ic_principal::Principal;
+
+    #[test]
+    fn supply_snapshot_pauses_only_on_unbacked_supply() {
+        let mut state = State::new();
+        let scale = 10u128.pow(10);
+        state.total_deposited = Nat::from(100u64);
+        state.share_supply = Nat::from(100 * scale);
+        let supply = |units: u128| ChainSupply {
+            chain_id: DEFAULT_CHAIN_ID,
+            contract: CONTRACT_ADDRESS.to_lowercase(),
+            total_supply_wei: Nat::from(units * scale),
+        };
+
+        // Burned but not yet unlocked: a shortfall, not a breach.
+        let snapshot = snapshot_in(&state, vec![supply(90)], 1);
+        assert_eq!(snapshot.drift, candid::Int::from(-10));
+        assert!(!snapshot.tolerance_exceeded);
+        record_snapshot_in(&mut state, snapshot);
+        assert!(!state.pause.deposits.paused);
+
+        state.invariant_config.tolerance = Nat::from(5u64);
+        let snapshot = snapshot_in(&state, vec![supply(100), supply(6)], 2);
+        assert_eq!(snapshot.unbacked_supply, Nat::from(6u64));
+        assert!(snapshot.tolerance_exceeded);
+        record_snapshot_in(&mut state, snapshot);
+
+        assert!(state.pause.deposits.paused && state.pause.unlocks.paused);
+        assert_eq!(state.pause.unlocks.changed_at_ns, Some(2));
+        assert_eq!(
+            state
+                .supply_snapshots
+                .iter()
+                .map(|snapshot| snapshot.id)
+                .collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+    }
+
+    #[test]
+    fn supply_snapshot_holds_after_unlocks_below_par() {
+        reset_state();
+        let caller = Principal::anonymous();
+        let scale = 10u128.pow(10);
+        apply_successful_deposit(caller, &Nat::from(100u64), &Nat::from(100 * scale));
+        // A fee holders had to bear leaves each share worth less than a unit.
+        STATE.with(|state| accounting::charge_fee_in(&mut state.borrow_mut(), &Nat::from(10u64)));
+
+        // Burning half the supply unlocks 45 units.
+        let burned = Nat::from(50 * scale);
+        let value = STATE.with(|state| exchange_rate::icp_for_in(&state.borrow(), &burned));
+        assert_eq!(value, Nat::from(45u64));
+        apply_burn_unlock(&caller, &burned, &value).unwrap();
+        STATE.with(|state| exchange_rate::burn_shares_in(&mut state.borrow_mut(), &burned));
+
+        let supply = |wei: u128| ChainSupply {
+            chain_id: DEFAULT_CHAIN_ID,
+            contract: CONTRACT_ADDRESS.to_lowercase(),
+            total_supply_wei: Nat::from(wei),
+        };
+        STATE.with(|state| {
+            let state = state.borrow();
+            let snapshot = snapshot_in(&state, vec![supply(50 * scale)], 1);
+            assert_eq!(snapshot.total_supply, Nat::from(45u64));
+            assert_eq!(snapshot.unbacked_supply, Nat::from(0u64));
+            assert_eq!(snapshot.drift, candid::Int::from(0));
+            assert!(!snapshot.tolerance_exceeded);
+
+            // A single wei the vault never minted is still caught.
+            let snapshot = snapshot_in(&state, vec![supply(50 * scale + 1)], 2);
+            assert!(snapshot.tolerance_exceeded);
+        });
+    }
+}
@@ -1,44 +1,150 @@
 // SPDX-License-Identifier: MIT
 // Copyright (c) 2025 Helix Labs
 
+mod accounting;
+mod deposit_accounts;
+mod deposits;
+mod exchange_rate;
+mod fees;
+mod invariant;
+mod pause;
+mod rate_limits;
+mod staking;
+mod transfer_log;
+mod unlocks;
+
+use accounting::Books;
 use candid::{CandidType, Nat};
+use deposit_accounts::DepositTarget;
+use deposits::{DepositOperation, DepositSource, MintTrackingConfig};
+use exchange_rate::ExchangeRate;
+use fees::{FeeConfig, FeeKind, FeePeriod};
 use ic_cdk::{
     call,
     storage::{stable_restore, stable_save},
 };
 use ic_principal::Principal;
-use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
 use icrc_ledger_types::icrc1::transfer::{TransferArg, TransferError};
-use icrc_ledger_types::icrc2::transfer_from::{TransferFromArgs, TransferFromError};
+use invariant::{InvariantConfig, SupplySnapshot};
+use pause::{CircuitBreakerConfig, PauseEvent, PauseState};
+use rate_limits::{BridgeDirection, RateLimitConfig, TransferUsage};
 use serde::{Deserialize, Serialize};
+use staking::{PendingMaturity, RewardSweep, StakedNeuron, StakingConfig};
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::str::FromStr;
+use transfer_log::{record_operation, BridgeOperation, BridgeOperationKind, NewOperation};
+use unlocks::BurnUnlock;
 
 const ICRC1_LEDGER_CANISTER_ID: &str = "br5f7-7uaaa-aaaaa-qaaca-cai";
 const EVM_BACKEND_CANISTER_ID: &str = "bkyz2-fmaaa-aaaaa-qaaaq-cai";
 const CONTRACT_ADDRESS: &str = "0x272aEe5159a257359e84EAB3a6e3bd7F90b712EC";
+// Holesky, where `CONTRACT_ADDRESS` is deployed.
+const DEFAULT_CHAIN_ID: u64 = 17_000;
 const DEFAULT_TRANSFER_FEE: u64 = 10_000;
+// Prefix the EVM backend gives burns that are not final yet.
+const PENDING_CONFIRMATIONS: &str = "Pending confirmations";
 
 #[derive(Clone, Default)]
 struct State {
     user_balances: HashMap<Principal, Nat>,
+    /// hstICP wei minted to a user short of a whole unit of `user_balances`.
+    share_dust: HashMap<Principal, Nat>,
     total_deposited: Nat,
     transfer_fee: Nat,
     used_tx_hashes: HashSet<String>,
     config: BridgeConfig,
     fee_initialized: bool,
+    operations: Vec<BridgeOperation>,
+    deposits: BTreeMap<u64, DepositOperation>,
+    next_deposit_id: u64,
+    mint_tracking: MintTrackingConfig,
+    chain_contracts: BTreeMap<u64, String>,
+    default_chain_id: u64,
+    pause: PauseState,
+    pause_events: Vec<PauseEvent>,
+    guardians: BTreeSet<Principal>,
+    circuit_breaker: CircuitBreakerConfig,
+    consecutive_mint_failures: u32,
+    invariant_config: InvariantConfig,
+    supply_snapshots: Vec<SupplySnapshot>,
+    next_snapshot_id: u64,
+    rate_limits: RateLimitConfig,
+    transfer_usage: Vec<TransferUsage>,
+    next_usage_id: u64,
+    burn_unlocks: BTreeMap<u64, BurnUnlock>,
+    next_burn_unlock_id: u64,
+    burn_cursor: u64,
+    deposit_targets: BTreeMap<Principal, DepositTarget>,
+    staking_config: StakingConfig,
+    neurons: BTreeMap<u64, StakedNeuron>,
+    next_neuron_id: u64,
+    liquid_balance: Nat,
+    liquid_read_at_ns: Option<u64>,
+    rewards_disbursed: Nat,
+    pending_maturity: Vec<PendingMaturity>,
+    reward_sweep: Option<RewardSweep>,
+    /// hstICP wei outstanding by the vault's books.
+    share_supply: Nat,
+    rate_history: Vec<ExchangeRate>,
+    fee_config: FeeConfig,
+    /// Protocol fees accrued and not yet swept, in ledger units.
+    treasury_balance: Nat,
+    fees_accrued: Nat,
+    treasury_swept: Nat,
+    fee_periods: Vec<FeePeriod>,
+    books: Books,
 }
 
 impl State {
     fn new() -> Self {
+        let config = BridgeConfig::default();
         Self {
             user_balances: HashMap::new(),
+            share_dust: HashMap::new(),
             total_deposited: Nat::from(0u64),
             transfer_fee: Nat::from(DEFAULT_TRANSFER_FEE),
             used_tx_hashes: HashSet::new(),
-            config: BridgeConfig::default(),
+            chain_contracts: BTreeMap::from([(DEFAULT_CHAIN_ID, config.contract_address.clone())]),
+            default_chain_id: DEFAULT_CHAIN_ID,
+            config,
             fee_initialized: false,
+            operations: Vec::new(),
+            deposits: BTreeMap::new(),
+            next_deposit_id: 0,
+            mint_tracking: MintTrackingConfig::default(),
+            pause: PauseState::default(),
+            pause_events: Vec::new(),
+            guardians: BTreeSet::new(),
+            circuit_breaker: CircuitBreakerConfig::default(),
+            consecutive_mint_failures: 0,
+            invariant_config: InvariantConfig::default(),
+            supply_snapshots: Vec::new(),
+            next_snapshot_id: 0,
+            rate_limits: RateLimitConfig::default(),
+            transfer_usage: Vec::new(),
+            next_usage_id: 0,
+            burn_unlocks: BTreeMap::new(),
+            next_burn_unlock_id: 0,
+            burn_cursor: 0,
+            deposit_targets: BTreeMap::new(),
+            staking_config: StakingConfig::default(),
+            neurons: BTreeMap::new(),
+            next_neuron_id: 0,
+            liquid_balance: Nat::from(0u64),
+            liquid_read_at_ns: None,
+            rewards_disbursed: Nat::from(0u64),
+            pending_maturity: Vec::new(),
+            reward_sweep: None,
+            share_supply: Nat::from(0u64),
+            rate_history: Vec::new(),
+            fee_config: FeeConfig::default(),
+            treasury_balance: Nat::from(0u64),
+            fees_accrued: Nat::from(0u64),
+            treasury_swept: Nat::from(0u64),
+            fee_periods: Vec::new(),
+            books: Books::default(),
         }
     }
 }
@@ -72,6 +178,44 @@ struct StableState {
     used_tx_hashes: Vec<String>,
     config: BridgeConfig,
     fee_initialized: bool,
+    operations: Option<Vec<BridgeOperation>>,
+    deposits: Option<Vec<DepositOperation>>,
+    next_deposit_id: Option<u64>,
+    mint_tracking: Option<MintTrackingConfig>,
+    chain_contracts: Option<BTreeMap<u64, String>>,
+    default_chain_id: Option<u64>,
+    pause: Option<PauseState>,
+    pause_events: Option<Vec<PauseEvent>>,
+    guardians: Option<Vec<Principal>>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    consecutive_mint_failures: Option<u32>,
+    invariant_config: Option<InvariantConfig>,
+    supply_snapshots: Option<Vec<SupplySnapshot>>,
+    next_snapshot_id: Option<u64>,
+    rate_limits: Option<RateLimitConfig>,
+    transfer_usage: Option<Vec<TransferUsage>>,
+    next_usage_id: Option<u64>,
+    burn_unlocks: Option<Vec<BurnUnlock>>,
+    next_burn_unlock_id: Option<u64>,
+    burn_cursor: Option<u64>,
+    deposit_targets: Option<Vec<(Principal, DepositTarget)>>,
+    staking_config: Option<StakingConfig>,
+    neurons: Option<Vec<StakedNeuron>>,
+    next_neuron_id: Option<u64>,
+    liquid_balance: Option<Nat>,
+    liquid_read_at_ns: Option<u64>,
+    rewards_disbursed: Option<Nat>,
+    pending_maturity: Option<Vec<PendingMaturity>>,
+    reward_sweep: Option<RewardSweep>,
+    share_supply: Option<Nat>,
+    rate_history: Option<Vec<ExchangeRate>>,
+    fee_config: Option<FeeConfig>,
+    treasury_balance: Option<Nat>,
+    fees_accrued: Option<Nat>,
+    treasury_swept: Option<Nat>,
+    fee_periods: Option<Vec<FeePeriod>>,
+    books: Option<Books>,
+    share_dust: Option<Vec<(Principal, Nat)>>,
 }
 
 impl From<&State> for StableState {
@@ -87,25 +231,209 @@ impl From<&State> for StableState {
             used_tx_hashes: state.used_tx_hashes.iter().cloned().collect(),
             config: state.config.clone(),
             fee_initialized: state.fee_initialized,
+            operations: Some(state.operations.clone()),
+            deposits: Some(state.deposits.values().cloned().collect()),
+            next_deposit_id: Some(state.next_deposit_id),
+            mint_tracking: Some(state.mint_tracking.clone()),
+            chain_contracts: Some(state.chain_contracts.clone()),
+            default_chain_id: Some(state.default_chain_id),
+            pause: Some(state.pause.clone()),
+            pause_events: Some(state.pause_events.clone()),
+            guardians: Some(state.guardians.iter().copied().collect()),
+            circuit_breaker: Some(state.circuit_breaker.clone()),
+            consecutive_mint_failures: Some(state.consecutive_mint_failures),
+            invariant_config: Some(state.invariant_config.clone()),
+            supply_snapshots: Some(state.supply_snapshots.clone()),
+            next_snapshot_id: Some(state.next_snapshot_id),
+            rate_limits: Some(state.rate_limits.clone()),
+            transfer_usage: Some(state.transfer_usage.clone()),
+            next_usage_id: Some(state.next_usage_id),
+            burn_unlocks: Some(state.burn_unlocks.values().cloned().collect()),
+            next_burn_unlock_id: Some(state.next_burn_unlock_id),
+            burn_cursor: Some(state.burn_cursor),
+            deposit_targets: Some(
+                state
+                    .deposit_targets
+                    .iter()
+                    .map(|(user, target)| (*user, target.clone()))
+                    .collect(),
+            ),
+            staking_config: Some(state.staking_config.clone()),
+            neurons: Some(state.neurons.values().cloned().collect()),
+            next_neuron_id: Some(state.next_neuron_id),
+            liquid_balance: Some(state.liquid_balance.clone()),
+            liquid_read_at_ns: state.liquid_read_at_ns,
+            rewards_disbursed: Some(state.rewards_disbursed.clone()),
+            pending_maturity: Some(state.pending_maturity.clone()),
+            reward_sweep: state.reward_sweep.clone(),
+            share_supply: Some(state.share_supply.clone()),
+            rate_history: Some(state.rate_history.clone()),
+            fee_config: Some(state.fee_config.clone()),
+            treasury_balance: Some(state.treasury_balance.clone()),
+            fees_accrued: Some(state.fees_accrued.clone()),
+            treasury_swept: Some(state.treasury_swept.clone()),
+            fee_periods: Some(state.fee_periods.clone()),
+            books: Some(state.books.clone()),
+            share_dust: Some(
+                state
+                    .share_dust
+                    .iter()
+                    .map(|(principal, dust)| (*principal, dust.clone()))
+                    .collect(),
+            ),
         }
     }
 }
 
 impl From<StableState> for State {
     fn from(state: StableState) -> Self {
-        State {
+        let default_chain_id = state.default_chain_id.unwrap_or(DEFAULT_CHAIN_ID);
+        // Before per-chain contracts the configured contract lived on the default chain.
+        let chain_contracts = state.chain_contracts.unwrap_or_else(|| {
+            BTreeMap::from([(default_chain_id, state.config.contract_address.clone())])
+        });
+        let total_deposited = state.total_deposited;
+        let books = state.books;
+        let mut restored = State {
             user_balances: state.user_balances.into_iter().collect(),
-            total_deposited: state.total_deposited,
+            share_dust: state.share_dust.unwrap_or_default().into_iter().collect(),
+            total_deposited: total_deposited.clone(),
             transfer_fee: state.transfer_fee,
             used_tx_hashes: state.used_tx_hashes.into_iter().collect(),
             config: state.config,
             fee_initialized: state.fee_initialized,
-        }
+            operations: state.operations.unwrap_or_default(),
+            deposits: state
+                .deposits
+                .unwrap_or_default()
+                .into_iter()
+                .map(|op| (op.id, op))
+                .collect(),
+            next_deposit_id: state.next_deposit_id.unwrap_or_default(),
+            mint_tracking: state.mint_tracking.unwrap_or_default(),
+            chain_contracts,
+            default_chain_id,
+            pause: state.pause.unwrap_or_default(),
+            pause_events: state.pause_events.unwrap_or_default(),
+            guardians: state.guardians.unwrap_or_default().into_iter().collect(),
+            circuit_breaker: state.circuit_breaker.unwrap_or_default(),
+            consecutive_mint_failures: state.consecutive_mint_failures.unwrap_or_default(),
+            invariant_config: state.invariant_config.unwrap_or_default(),
+            supply_snapshots: state.supply_snapshots.unwrap_or_default(),
+            next_snapshot_id: state.next_snapshot_id.unwrap_or_default(),
+            rate_limits: state.rate_limits.unwrap_or_default(),
+            transfer_usage: state.transfer_usage.unwrap_or_default(),
+            next_usage_id: state.next_usage_id.unwrap_or_default(),
+            burn_unlocks: state
+                .burn_unlocks
+                .unwrap_or_default()
+                .into_iter()
+                .map(|unlock| (unlock.id, unlock))
+                .collect(),
+            next_burn_unlock_id: state.next_burn_unlock_id.unwrap_or_default(),
+            burn_cursor: state.burn_cursor.unwrap_or_default(),
+            deposit_targets: state
+                .deposit_targets
+                .unwrap_or_default()
+                .into_iter()
+                .collect(),
+            staking_config: state.staking_config.unwrap_or_default(),
+            neurons: state
+                .neurons
+                .unwrap_or_default()
+                .into_iter()
+                .map(|neuron| (neuron.id, neuron))
+                .collect(),
+            next_neuron_id: state.next_neuron_id.unwrap_or_default(),
+            liquid_balance: state.liquid_balance.unwrap_or_else(|| Nat::from(0u64)),
+            liquid_read_at_ns: state.liquid_read_at_ns,
+            rewards_disbursed: state.rewards_disbursed.unwrap_or_else(|| Nat::from(0u64)),
+            pending_maturity: state.pending_maturity.unwrap_or_default(),
+            reward_sweep: state.reward_sweep,
+            // Until share accounting every unit was minted 1:1.
+            share_supply: state.share_supply.unwrap_or_else(|| {
+                total_deposited.clone() * Nat::from(exchange_rate::WEI_PER_UNIT)
+            }),
+            rate_history: state.rate_history.unwrap_or_default(),
+            fee_config: state.fee_config.unwrap_or_default(),
+            treasury_balance: state.treasury_balance.unwrap_or_else(|| Nat::from(0u64)),
+            fees_accrued: state.fees_accrued.unwrap_or_else(|| Nat::from(0u64)),
+            treasury_swept: state.treasury_swept.unwrap_or_else(|| Nat::from(0u64)),
+            fee_periods: state.fee_periods.unwrap_or_default(),
+            books: Books::default(),
+        };
+        restored.books = books.unwrap_or_else(|| accounting::opening_books_in(&restored));
+        restored
     }
 }
 
 thread_local! {
     static STATE: RefCell<State> = RefCell::new(State::new());
+    // Transaction hashes of plain unlocks being verified or paid by an
+    // in-flight call; see `TxHashGuard`.
+    static UNLOCKING: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+struct TxHashGuard(String);
+
+impl TxHashGuard {
+    fn acquire(tx_hash: &str) -> Option<Self> {
+        UNLOCKING.with(|set| {
+            set.borrow_mut()
+                .insert(tx_hash.to_string())
+                .then(|| TxHashGuard(tx_hash.to_string()))
+        })
+    }
+}
+
+impl Drop for TxHashGuard {
+    fn drop(&mut self) {
+        UNLOCKING.with(|set| {
+            set.borrow_mut().remove(&self.0);
+        });
+    }
+}
+
+/// hstICP wei taken off a caller's recorded balance while their plain unlock
+/// awaits the ledger, so concurrent unlocks cannot spend it twice. Put back
+/// on drop unless the unlock went through.
+struct BalanceHold {
+    owner: Principal,
+    shares: Nat,
+    spent: bool,
+}
+
+impl BalanceHold {
+    fn take(owner: Principal, shares: &Nat) -> Result<Self, String> {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let held = held_shares_in(&state, &owner);
+            if held < *shares {
+                return Err("Insufficient recorded balance for withdrawal.".to_string());
+            }
+            set_held_shares_in(&mut state, &owner, held - shares.clone());
+            Ok(BalanceHold {
+                owner,
+                shares: shares.clone(),
+                spent: false,
+            })
+        })
+    }
+
+    fn spend(mut self) {
+        self.spent = true;
+    }
+}
+
+impl Drop for BalanceHold {
+    fn drop(&mut self) {
+        if self.spent {
+            return;
+        }
+        STATE.with(|state| {
+            credit_shares_in(&mut state.borrow_mut(), &self.owner, &self.shares);
+        });
+    }
 }
 
 #[derive(CandidType, Deserialize)]
@@ -113,6 +441,18 @@ struct BurnValidation {
     from: String,
     amount_wei: String,
     contract: String,
+    recipient: Option<Principal>,
+    recipient_subaccount: Option<Vec<u8>>,
+    log_index: Option<u64>,
+}
+
+#[ic_cdk::init]
+fn init() {
+    deposits::start_reconciler();
+    invariant::start_invariant_monitor();
+    unlocks::start_unlock_processor();
+    deposit_accounts::start_deposit_account_watcher();
+    staking::start_staking();
 }
 
 #[ic_cdk::pre_upgrade]
@@ -132,23 +472,82 @@ fn post_upgrade() {
         }),
         Err(_) => STATE.with(|state| *state.borrow_mut() = State::new()),
     }
+    deposits::start_reconciler();
+    invariant::start_invariant_monitor();
+    unlocks::start_unlock_processor();
+    deposit_accounts::start_deposit_account_watcher();
+    staking::start_staking();
+}
+
+/// Books a confirmed mint of `shares` hstICP wei for `amount` ledger units.
+/// Recorded balances are kept in whole hstICP units of 8 decimals.
+// Wei short of a whole unit are carried in `share_dust` until later mints
+// make up a unit.
+fn apply_successful_deposit_in(state: &mut State, caller: Principal, amount: &Nat, shares: &Nat) {
+    let unit = Nat::from(exchange_rate::WEI_PER_UNIT);
+    let dust = state.share_dust.remove(&caller).unwrap_or_default();
+    let minted = dust + shares.clone();
+    let remainder = minted.clone() % unit.clone();
+    if remainder > 0u64 {
+        state.share_dust.insert(caller, remainder);
+    }
+    let entry = state
+        .user_balances
+        .entry(caller)
+        .or_insert_with(|| Nat::from(0u64));
+    *entry += minted / unit;
+    state.total_deposited += amount.clone();
+    state.share_supply += shares.clone();
 }
 
-fn apply_successful_deposit(caller: Principal, amount: &Nat) {
+/// hstICP wei recorded for `who`: whole units and carried dust.
+fn held_shares_in(state: &State, who: &Principal) -> Nat {
+    let balance = state
+        .user_balances
+        .get(who)
+        .cloned()
+        .unwrap_or_else(|| Nat::from(0u64));
+    let dust = state.share_dust.get(who).cloned().unwrap_or_default();
+    balance * Nat::from(exchange_rate::WEI_PER_UNIT) + dust
+}
+
+/// Records `held` hstICP wei for `who`, split into whole units and dust.
+fn set_held_shares_in(state: &mut State, who: &Principal, held: Nat) {
+    let unit = Nat::from(exchange_rate::WEI_PER_UNIT);
+    let dust = held.clone() % unit.clone();
+    if dust > 0u64 {
+        state.share_dust.insert(*who, dust);
+    } else {
+        state.share_dust.remove(who);
+    }
+    state.user_balances.insert(*who, held / unit);
+}
+
+/// Puts `shares` hstICP wei back on `who`'s recorded balance.
+fn credit_shares_in(state: &mut State, who: &Principal, shares: &Nat) {
+    let held = held_shares_in(state, who) + shares.clone();
+    set_held_shares_in(state, who, held);
+}
+
+/// Books a plain unlock paid out of the caller's held balance.
+fn apply_successful_withdraw(hold: BalanceHold, total_amount: &Nat) -> Result<(), String> {
+    hold.spend();
     STATE.with(|state| {
         let mut state = state.borrow_mut();
-        let entry = state
-            .user_balances
-            .entry(caller)
-            .or_insert_with(|| Nat::from(0u64));
-        *entry += amount.clone();
-        state.total_deposited += amount.clone();
-    });
+        if state.total_deposited < total_amount.clone() {
+            return Err("Vault accounting underflow detected.".to_string());
+        }
+        state.total_deposited -= total_amount.clone();
+        Ok(())
+    })
 }
 
-fn apply_successful_withdraw(
-    caller: &Principal,
-    withdraw_amount: &Nat,
+/// Books an unlock paid for `burned` hstICP wei. The recipient need not be
+/// the original depositor, so their recorded balance, dust included, only
+/// goes down to zero.
+fn apply_burn_unlock(
+    recipient: &Principal,
+    burned: &Nat,
     total_amount: &Nat,
 ) -> Result<(), String> {
     STATE.with(|state| {
@@ -156,14 +555,15 @@ fn apply_successful_withdraw(
         if state.total_deposited < total_amount.clone() {
             return Err("Vault accounting underflow detected.".to_string());
         }
-        let balance = state
-            .user_balances
-            .get_mut(caller)
-            .ok_or_else(|| "Recorded balance missing for caller.".to_string())?;
-        if *balance < withdraw_amount.clone() {
-            return Err("Recorded balance insufficient for withdrawal.".to_string());
+        if state.user_balances.contains_key(recipient) {
+            let held = held_shares_in(&state, recipient);
+            let left = if held > *burned {
+                held - burned.clone()
+            } else {
+                Nat::from(0u64)
+            };
+            set_held_shares_in(&mut state, recipient, left);
         }
-        *balance -= withdraw_amount.clone();
         state.total_deposited -= total_amount.clone();
         Ok(())
     })
@@ -181,6 +581,20 @@ fn current_config() -> BridgeConfig {
     STATE.with(|state| state.borrow().config.clone())
 }
 
+/// Resolves an optional caller-supplied chain to the chain id and the hstICP
+/// contract the vault trusts there.
+fn resolve_chain(chain_id: Option<u64>) -> Result<(u64, String), String> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        let chain_id = chain_id.unwrap_or(state.default_chain_id);
+        state
+            .chain_contracts
+            .get(&chain_id)
+            .map(|contract| (chain_id, contract.clone()))
+            .ok_or_else(|| format!("Chain {} is not supported", chain_id))
+    })
+}
+
 async fn refresh_transfer_fee_if_stale(default_fee: &Nat) -> Result<Nat, String> {
     let (token_canister, should_refresh, cached_fee) = STATE.with(|state| {
         let state = state.borrow();
@@ -211,37 +625,6 @@ async fn refresh_transfer_fee_if_stale(default_fee: &Nat) -> Result<Nat, String>
     Ok(fee)
 }
 
-async fn attempt_refund(
-    token_canister: Principal,
-    recipient: Principal,
-    amount: Nat,
-    fee: Nat,
-) -> Result<(), String> {
-    let refund_arg = TransferArg {
-        from_subaccount: None,
-        to: Account {
-            owner: recipient,
-            subaccount: None,
-        },
-        amount,
-        fee: Some(fee),
-        memo: None,
-        created_at_time: None,
-    };
-
-    match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
-        token_canister,
-        "icrc1_transfer",
-        (refund_arg,),
-    )
-    .await
-    {
-        Ok((Ok(_),)) => Ok(()),
-        Ok((Err(err),)) => Err(format!("Refund transfer failed: {:?}", err)),
-        Err(err) => Err(format!("Refund transfer call failed: {:?}", err)),
-    }
-}
-
 fn normalize_contract_address(address: &str) -> Result<String, String> {
     let trimmed = address.trim();
     if trimmed.is_empty() {
@@ -258,6 +641,7 @@ fn normalize_contract_address(address: &str) -> Result<String, String> {
     Ok(format!("0x{}", without_prefix))
 }
 
+/// hstICP units, 8 decimals, minted to `user` and not yet unlocked.
 #[ic_cdk::query]
 fn get_user_balance(user: Principal) -> Nat {
     STATE.with(|state| {
@@ -298,6 +682,10 @@ fn set_bridge_configuration(
 
     STATE.with(|state| {
         let mut state = state.borrow_mut();
+        let default_chain_id = state.default_chain_id;
+        state
+            .chain_contracts
+            .insert(default_chain_id, normalized_contract.clone());
         state.config = BridgeConfig {
             ledger_canister,
             evm_backend_canister,
@@ -309,9 +697,88 @@ fn set_bridge_configuration(
     Ok(())
 }
 
+#[ic_cdk::query]
+fn get_chain_contracts() -> Vec<(u64, String)> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .chain_contracts
+            .iter()
+            .map(|(chain_id, contract)| (*chain_id, contract.clone()))
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_default_chain_id() -> u64 {
+    STATE.with(|state| state.borrow().default_chain_id)
+}
+
+#[ic_cdk::update]
+fn set_chain_contract(chain_id: u64, contract_address: String) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    ensure_controller(caller)?;
+
+    let normalized_contract = normalize_contract_address(&contract_address)?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if chain_id == state.default_chain_id {
+            state.config.contract_address = normalized_contract.clone();
+        }
+        state.chain_contracts.insert(chain_id, normalized_contract);
+    });
+
+    Ok(())
+}
+
 #[ic_cdk::update]
-async fn deposit_icrc1(amount: Nat, eth_address: String) -> Result<String, String> {
-    if amount == Nat::from(0u64) {
+fn remove_chain_contract(chain_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    ensure_controller(caller)?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if chain_id == state.default_chain_id {
+            return Err("Cannot remove the default chain".to_string());
+        }
+        state
+            .chain_contracts
+            .remove(&chain_id)
+            .map(|_| ())
+            .ok_or_else(|| format!("Chain {} is not supported", chain_id))
+    })
+}
+
+#[ic_cdk::update]
+fn set_default_chain_id(chain_id: u64) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    ensure_controller(caller)?;
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let contract = state
+            .chain_contracts
+            .get(&chain_id)
+            .cloned()
+            .ok_or_else(|| format!("Chain {} is not supported", chain_id))?;
+        state.default_chain_id = chain_id;
+        state.config.contract_address = contract;
+        Ok(())
+    })
+}
+
+/// Pulls `amount` from the caller's `from_subaccount` under their ICRC-2
+/// approval. Refunds go to `refund_to`, or back to the source account.
+#[ic_cdk::update]
+async fn deposit_icrc1(
+    amount: Nat,
+    eth_address: String,
+    chain_id: Option<u64>,
+    from_subaccount: Option<Subaccount>,
+    refund_to: Option<Account>,
+) -> Result<String, String> {
+    if amount == 0u64 {
         return Err("Deposit amount must be greater than zero".to_string());
     }
 
@@ -319,9 +786,9 @@ async fn deposit_icrc1(amount: Nat, eth_address: String) -> Result<String, Strin
         return Err("Ethereum address must be provided".to_string());
     }
 
+    pause::ensure_deposits_open()?;
     let caller = ic_cdk::api::caller();
-    let config = current_config();
-    let token_canister = config.ledger_canister;
+    let (chain_id, _) = resolve_chain(chain_id)?;
 
     let default_fee = Nat::from(DEFAULT_TRANSFER_FEE);
     let fee = refresh_transfer_fee_if_stale(&default_fee).await?;
@@ -333,75 +800,46 @@ async fn deposit_icrc1(amount: Nat, eth_address: String) -> Result<String, Strin
         ));
     }
 
-    let transfer_arg = TransferFromArgs {
-        spender_subaccount: None,
-        from: Account {
-            owner: caller,
-            subaccount: None,
-        },
-        to: Account {
-            owner: ic_cdk::id(),
-            subaccount: None,
+    let usage_id = rate_limits::reserve(BridgeDirection::Deposit, caller, &amount)?;
+    let deposit_id = deposits::create_deposit(
+        caller,
+        eth_address,
+        chain_id,
+        amount,
+        fee.clone(),
+        DepositSource {
+            from_subaccount,
+            deposit_subaccount: None,
+            refund_to,
         },
-        amount: amount.clone(),
-        fee: Some(fee.clone()),
-        memo: None,
-        created_at_time: None,
-    };
-
-    match call::<(TransferFromArgs,), (Result<Nat, TransferFromError>,)>(
-        token_canister,
-        "icrc2_transfer_from",
-        (transfer_arg,),
-    )
-    .await
-    {
-        Ok((Ok(_block_index),)) => {
-            let evm_backend = config.evm_backend_canister;
-            let contract = config.contract_address.clone();
-
-            let scaled_amount = (amount.0.clone() * 10u128.pow(10)).to_string();
-            let result: (Result<String, String>,) =
-                call(evm_backend, "mint", (contract, eth_address, scaled_amount))
-                    .await
-                    .map_err(|e| format!("Call failed: {:?}", e))?;
-
-            match result.0 {
-                Ok(tx_hash) => {
-                    apply_successful_deposit(caller, &amount);
-
-                    STATE.with(|state| {
-                        let mut state = state.borrow_mut();
-                        state.transfer_fee = fee.clone();
-                        state.fee_initialized = true;
-                    });
-
-                    Ok(format!(
-                        "Deposit successful. Mint transaction sent: {}",
-                        tx_hash
-                    ))
-                }
-                Err(e) => {
-                    let refund_result =
-                        attempt_refund(token_canister, caller, amount.clone(), fee.clone()).await;
-
-                    let refund_message = match refund_result {
-                        Ok(_) => "Refunded deposit amount after mint failure.".to_string(),
-                        Err(err) => format!("Failed to refund deposit after mint failure: {}", err),
-                    };
-
-                    Err(format!(
-                        "Deposit succeeded but mint failed: {}. {}",
-                        e, refund_message
-                    ))
-                }
+    );
+    let tx_hash = match deposits::advance_deposit(deposit_id).await {
+        Ok(tx_hash) => tx_hash,
+        Err(e) => {
+            if deposits::pull_refused(deposit_id) {
+                rate_limits::release(usage_id);
             }
+            return Err(e);
         }
-        Ok((Err(e),)) => Err(format!("Transfer failed: {:?}", e)),
-        Err(e) => Err(format!("Call failed: {:?}", e)),
-    }
+    };
+
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.transfer_fee = fee.clone();
+        state.fee_initialized = true;
+    });
+
+    Ok(format!(
+        "Deposit accepted. Mint transaction sent: {}. Balance is credited once it is confirmed.",
+        tx_hash
+    ))
 }
 
+/// Plain burns are paid to `to`, or the caller's default account. `to` is
+/// ignored for `burnToICP` burns, which pay the account in their event.
+/// `withdraw_amount_8dec` is the hstICP burned; the payout is its value at
+/// the current exchange rate. When the vault's liquid ICP cannot cover it
+/// after earlier tickets, the burn is queued as a withdrawal ticket.
 #[ic_cdk::update]
 async fn unlock_icrc1(
     tx_hash: String,
@@ -409,14 +847,39 @@ async fn unlock_icrc1(
     evm_amount_18dec: String,
     withdraw_amount_8dec: Nat,
     expected_contract: String,
+    chain_id: Option<u64>,
+    to: Option<Account>,
+) -> Result<String, String> {
+    pause::ensure_unlocks_open()?;
+    unlock(
+        tx_hash,
+        expected_eth_from,
+        evm_amount_18dec,
+        withdraw_amount_8dec,
+        expected_contract,
+        chain_id,
+        to,
+    )
+    .await
+}
+
+async fn unlock(
+    tx_hash: String,
+    expected_eth_from: String,
+    evm_amount_18dec: String,
+    withdraw_amount_8dec: Nat,
+    expected_contract: String,
+    chain_id: Option<u64>,
+    to: Option<Account>,
 ) -> Result<String, String> {
     let config = current_config();
+    let (chain_id, contract_address) = resolve_chain(chain_id)?;
 
-    if !expected_contract.eq_ignore_ascii_case(&config.contract_address) {
+    if !expected_contract.eq_ignore_ascii_case(&contract_address) {
         return Err("Unsupported contract address".to_string());
     }
 
-    if withdraw_amount_8dec == Nat::from(0u64) {
+    if withdraw_amount_8dec == 0u64 {
         return Err("Withdrawal amount must be greater than zero".to_string());
     }
 
@@ -427,26 +890,17 @@ async fn unlock_icrc1(
     if already_used {
         return Err("Transaction hash has already been used for unlock.".to_string());
     }
-
-    // Ensure the caller has sufficient recorded balance
-    let caller = ic_cdk::api::caller();
-    let recorded_balance = STATE.with(|state| {
-        state
-            .borrow()
-            .user_balances
-            .get(&caller)
-            .cloned()
-            .unwrap_or_else(|| Nat::from(0u64))
-    });
-    if recorded_balance < withdraw_amount_8dec {
-        return Err("Insufficient recorded balance for withdrawal.".to_string());
-    }
+    // Held until this call returns, so a concurrent call cannot pay the same
+    // burn while this one awaits.
+    let _guard = TxHashGuard::acquire(&normalized_tx_hash)
+        .ok_or_else(|| "An unlock of this transaction is already in progress.".to_string())?;
 
     // 2. Verify the burn on EVM
     let (result,): (Result<BurnValidation, String>,) = call(
         config.evm_backend_canister,
         "verify_tx_receipt_with_validation",
         (
+            chain_id,
             tx_hash.clone(),
             expected_eth_from.clone(),
             evm_amount_18dec.clone(),
@@ -458,10 +912,7 @@ async fn unlock_icrc1(
 
     match result {
         Ok(proof) => {
-            if !proof
-                .contract
-                .eq_ignore_ascii_case(&config.contract_address)
-            {
+            if !proof.contract.eq_ignore_ascii_case(&contract_address) {
                 return Err("Burn proof references unexpected contract.".to_string());
             }
 
@@ -478,83 +929,199 @@ async fn unlock_icrc1(
                 .map_err(|_| "Invalid burn amount format".to_string())?;
             let scale = Nat::from(10u128.pow(10));
 
-            if burn_amount_nat.clone() % scale.clone() != Nat::from(0u64) {
+            if burn_amount_nat.clone() % scale.clone() != 0u64 {
                 return Err("Burn amount is not aligned with expected decimals.".to_string());
             }
 
-            let withdraw_from_burn = burn_amount_nat / scale.clone();
+            let withdraw_from_burn = burn_amount_nat.clone() / scale.clone();
             if withdraw_from_burn != withdraw_amount_8dec {
                 return Err("Requested withdrawal does not match burned amount.".to_string());
             }
 
-            let default_fee = Nat::from(DEFAULT_TRANSFER_FEE);
-            let fee = refresh_transfer_fee_if_stale(&default_fee).await?;
-            let total_amount = withdraw_amount_8dec.clone() + fee.clone();
-
-            let token_canister = config.ledger_canister;
-            let vault_balance = match call::<(Account,), (Nat,)>(
-                token_canister,
-                "icrc1_balance_of",
-                (Account {
-                    owner: ic_cdk::id(),
-                    subaccount: None,
-                },),
-            )
-            .await
-            {
-                Ok((bal,)) => bal,
-                Err(e) => return Err(format!("Failed to get vault balance: {:?}", e)),
-            };
+            // `burnToICP` burns are paid to the account in their event, whoever
+            // submits the proof.
+            if proof.log_index.is_some() {
+                return unlocks::unlock_proven_burn(chain_id, normalized_tx_hash, proof).await;
+            }
 
-            if vault_balance < total_amount {
-                return Err(format!(
-                    "Insufficient vault balance to unlock amount + fee."
-                ));
+            // Plain burns are paid to the caller against their recorded balance,
+            // and only from an address the vault minted to for them.
+            let caller = ic_cdk::api::caller();
+            let bound =
+                STATE.with(|state| deposits::minted_to_in(&state.borrow(), &caller, &proof.from));
+            if !bound {
+                return Err("Burner address has no hstICP minted to it for the caller.".to_string());
+            }
+            // Taken off the balance now and put back unless the unlock is paid.
+            let hold = BalanceHold::take(caller, &burn_amount_nat)?;
+            // `withdraw_amount_8dec` is the hstICP burned; it pays out at the current
+            // rate, less the withdraw fee and the ledger fee.
+            let value =
+                STATE.with(|state| exchange_rate::icp_for_in(&state.borrow(), &burn_amount_nat));
+            if value == 0u64 {
+                return Err("Burn is worth less than one ledger unit.".to_string());
             }
 
-            let transfer_arg = TransferArg {
-                from_subaccount: None,
-                to: Account {
-                    owner: caller,
-                    subaccount: None,
-                },
-                amount: withdraw_amount_8dec.clone(),
-                fee: Some(fee.clone()),
-                memo: None,
-                created_at_time: None,
-            };
+            let to = to.unwrap_or(Account {
+                owner: caller,
+                subaccount: None,
+            });
+            unlock_to_caller(caller, to, chain_id, normalized_tx_hash, proof, hold, value).await
+        }
+        // Passed through untouched so clients can tell it apart and retry.
+        Err(err_msg) if err_msg.starts_with(PENDING_CONFIRMATIONS) => Err(err_msg),
+        Err(err_msg) => Err(format!("Burn verification failed: {}", err_msg)),
+    }
+}
 
-            match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
-                token_canister,
-                "icrc1_transfer",
-                (transfer_arg,),
-            )
-            .await
-            {
-                Ok((Ok(_block_index),)) => {
-                    apply_successful_withdraw(&caller, &withdraw_amount_8dec, &total_amount)?;
-                    STATE.with(|state| {
-                        let mut state = state.borrow_mut();
-                        state.used_tx_hashes.insert(normalized_tx_hash);
-                        state.transfer_fee = fee.clone();
-                        state.fee_initialized = true;
-                    });
-
-                    Ok(format!(
-                        "Unlocked {} nICP to caller. Verified burn on Ethereum.",
-                        withdraw_amount_8dec
-                    ))
-                }
-                Ok((Err(e),)) => Err(format!("Transfer failed: {:?}", e)),
-                Err(e) => Err(format!("Transfer call failed: {:?}", e)),
+async fn unlock_to_caller(
+    caller: Principal,
+    to: Account,
+    chain_id: u64,
+    normalized_tx_hash: String,
+    proof: BurnValidation,
+    hold: BalanceHold,
+    value: Nat,
+) -> Result<String, String> {
+    let config = current_config();
+    let default_fee = Nat::from(DEFAULT_TRANSFER_FEE);
+    let fee = refresh_transfer_fee_if_stale(&default_fee).await?;
+    let protocol_fee =
+        STATE.with(|state| fees::fee_for_in(&state.borrow(), FeeKind::Withdraw, &value));
+    // The ledger fee comes out of the payout, so holders are charged exactly
+    // what the burn is worth.
+    if value <= protocol_fee.clone() + fee.clone() {
+        return Err("Burn does not cover the withdraw and ledger fees.".to_string());
+    }
+    let payout = value.clone() - protocol_fee.clone() - fee.clone();
+    let total_amount = value.clone();
+
+    let token_canister = config.ledger_canister;
+    let vault_balance = match call::<(Account,), (Nat,)>(
+        token_canister,
+        "icrc1_balance_of",
+        (Account {
+            owner: ic_cdk::id(),
+            subaccount: None,
+        },),
+    )
+    .await
+    {
+        Ok((bal,)) => bal,
+        Err(e) => return Err(format!("Failed to get vault balance: {:?}", e)),
+    };
+
+    // Earlier tickets are paid first; without enough left the burn joins the
+    // withdrawal queue.
+    let (available, demand) = STATE.with(|state| {
+        let state = state.borrow();
+        (
+            fees::untreasured_in(&state, &vault_balance),
+            unlocks::unsettled_demand_in(&state),
+        )
+    });
+    if available < demand + total_amount.clone() {
+        return Ok(unlocks::queue_plain_burn(
+            to,
+            chain_id,
+            normalized_tx_hash,
+            &proof,
+            hold,
+            value,
+            &vault_balance,
+        ));
+    }
+
+    let usage_id = rate_limits::reserve(BridgeDirection::Unlock, caller, &payout)?;
+    let burn_operation_id = record_operation(NewOperation {
+        kind: BridgeOperationKind::BurnVerified,
+        principal: caller,
+        eth_address: Some(proof.from.clone()),
+        amount: payout.clone(),
+        evm_amount: Some(proof.amount_wei.clone()),
+        fee: protocol_fee.clone(),
+        ledger_block_index: None,
+        evm_tx_hash: Some(normalized_tx_hash.clone()),
+        related_operation: None,
+        chain_id: Some(chain_id),
+    });
+
+    let transfer_arg = TransferArg {
+        from_subaccount: None,
+        to,
+        amount: payout.clone(),
+        fee: Some(fee.clone()),
+        memo: None,
+        created_at_time: Some(ic_cdk::api::time()),
+    };
+
+    match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+        token_canister,
+        "icrc1_transfer",
+        (transfer_arg,),
+    )
+    .await
+    {
+        Ok((Ok(block_index),)) => {
+            // Paid out: the burn is spent and every entry below is posted; a
+            // bookkeeping error is only logged for review.
+            STATE.with(|state| {
+                state
+                    .borrow_mut()
+                    .used_tx_hashes
+                    .insert(normalized_tx_hash.clone())
+            });
+            let burned = hold.shares.clone();
+            if let Err(e) = apply_successful_withdraw(hold, &total_amount) {
+                ic_cdk::println!("unlock {}: {}", normalized_tx_hash, e);
             }
+            STATE.with(|state| {
+                accounting::book_outflow_in(
+                    &mut state.borrow_mut(),
+                    &(payout.clone() + fee.clone()),
+                )
+            });
+            STATE.with(|state| exchange_rate::burn_shares_in(&mut state.borrow_mut(), &burned));
+            fees::accrue(FeeKind::Withdraw, &protocol_fee);
+            record_operation(NewOperation {
+                kind: BridgeOperationKind::Unlock,
+                principal: caller,
+                eth_address: Some(proof.from),
+                amount: payout.clone(),
+                evm_amount: Some(proof.amount_wei),
+                fee: fee.clone(),
+                ledger_block_index: Some(block_index),
+                evm_tx_hash: Some(normalized_tx_hash.clone()),
+                related_operation: Some(burn_operation_id),
+                chain_id: Some(chain_id),
+            });
+            STATE.with(|state| {
+                let mut state = state.borrow_mut();
+                state.transfer_fee = fee.clone();
+                state.fee_initialized = true;
+            });
+
+            Ok(format!(
+                "Unlocked {} nICP to {}. Verified burn on Ethereum.",
+                payout, to
+            ))
+        }
+        // A failed unlock paid nothing out, so it does not count against the limits.
+        Ok((Err(e),)) => {
+            rate_limits::release(usage_id);
+            Err(format!("Transfer failed: {:?}", e))
+        }
+        Err(e) => {
+            rate_limits::release(usage_id);
+            Err(format!("Transfer call failed: {:?}", e))
         }
-        Err(err_msg) => Err(format!("Burn verification failed: {}", err_msg)),
     }
 }
 
+/// Re-reads the main account balance and the ledger fee. Controllers only.
 #[ic_cdk::update]
 async fn sync_state() -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
     let config = current_config();
     let token_canister = config.ledger_canister;
     let balance = match call::<(Account,), (Nat,)>(
@@ -575,30 +1142,109 @@ async fn sync_state() -> Result<(), String> {
         Err(e) => return Err(format!("Failed to sync fee: {:?}", e)),
     };
 
+    // `total_deposited` is kept by the books; whatever the balance read does
+    // not explain shows up in `get_reconciliation` instead.
+    let now = ic_cdk::api::time();
     STATE.with(|state| {
         let mut state = state.borrow_mut();
-        state.total_deposited = balance;
+        state.liquid_balance = balance;
+        state.liquid_read_at_ns = Some(now);
         state.transfer_fee = fee;
         state.fee_initialized = true;
+        exchange_rate::record_rate_in(&mut state, now);
     });
     Ok(())
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
-    fn reset_state() {
+    pub(crate) fn reset_state() {
         STATE.with(|state| *state.borrow_mut() = State::new());
     }
 
+    pub(crate) fn apply_successful_deposit(caller: Principal, amount: &Nat, shares: &Nat) {
+        STATE.with(|state| {
+            apply_successful_deposit_in(&mut state.borrow_mut(), caller, amount, shares)
+        });
+    }
+
+    pub(crate) fn deposit_op(
+        id: u64,
+        status: deposits::DepositStatus,
+        amount: u64,
+    ) -> DepositOperation {
+        DepositOperation {
+            id,
+            caller: Principal::anonymous(),
+            eth_address: "0x0000000000000000000000000000000000000001".to_string(),
+            amount: Nat::from(amount),
+            fee: Nat::from(10u64),
+            status,
+            created_at_ns: 1,
+            updated_at_ns: 1,
+            pull_block_index: None,
+            mint_tx_hash: None,
+            refund_created_at_ns: None,
+            refund_block_index: None,
+            log_operation: None,
+            last_error: None,
+            mint_submitted_at_ns: None,
+            mint_attempts: None,
+            confirmations: None,
+            chain_id: Some(DEFAULT_CHAIN_ID),
+            from_subaccount: None,
+            deposit_subaccount: None,
+            refund_to: None,
+            evm_amount: None,
+            protocol_fee: None,
+            refund_amount: None,
+        }
+    }
+
+    /// A `burnToICP` of `units` whole hstICP units paying the anonymous principal.
+    pub(crate) fn detected_burn(id: u64, units: u128) -> unlocks::DetectedBurn {
+        unlocks::DetectedBurn {
+            id,
+            chain_id: DEFAULT_CHAIN_ID,
+            contract: CONTRACT_ADDRESS.to_string(),
+            tx_hash: format!("0x{}", id),
+            log_index: 0,
+            from: "0x1".to_string(),
+            recipient: Some(Principal::anonymous()),
+            recipient_subaccount: None,
+            amount_wei: (units * exchange_rate::WEI_PER_UNIT).to_string(),
+        }
+    }
+
+    pub(crate) fn staked_neuron(
+        id: u64,
+        stake: u64,
+        status: staking::NeuronStatus,
+    ) -> staking::StakedNeuron {
+        staking::StakedNeuron {
+            id,
+            neuron_id: Some(id + 100),
+            stake: Nat::from(stake),
+            maturity: Nat::from(0u64),
+            dissolve_delay_secs: 100,
+            dissolves_at_secs: (status == staking::NeuronStatus::Dissolving).then_some(50),
+            status,
+            created_at_ns: 0,
+            updated_at_ns: 0,
+            last_error: None,
+            funding: None,
+        }
+    }
+
     #[test]
     fn apply_successful_deposit_updates_balances() {
         reset_state();
         let caller = Principal::anonymous();
         let amount = Nat::from(50u64);
 
-        apply_successful_deposit(caller, &amount);
+        apply_successful_deposit(caller, &amount, &Nat::from(500_000_000_000u64));
 
         let stored_balance = STATE.with(|state| {
             state
@@ -611,10 +1257,14 @@ mod tests {
 
         assert_eq!(stored_balance.unwrap(), amount);
         assert_eq!(total, Nat::from(50u64));
+        assert_eq!(
+            STATE.with(|state| state.borrow().share_supply.clone()),
+            Nat::from(500_000_000_000u64)
+        );
     }
 
     #[test]
-    fn apply_successful_withdraw_subtracts_amount_and_fee() {
+    fn apply_successful_withdraw_subtracts_burn_value() {
         reset_state();
         let caller = Principal::anonymous();
         let initial_balance = Nat::from(200u64);
@@ -626,23 +1276,160 @@ mod tests {
             state.total_deposited = initial_total.clone();
         });
 
+        // 70 hstICP worth 80 ICP, paid out as 70 after a ledger fee of 10:
+        // holders are charged what the burn is worth, fee included.
         let withdraw_amount = Nat::from(70u64);
-        let fee = Nat::from(10u64);
-        let total_amount = withdraw_amount.clone() + fee.clone();
+        let burned = withdraw_amount.clone() * Nat::from(exchange_rate::WEI_PER_UNIT);
+        let total_amount = Nat::from(80u64);
 
-        apply_successful_withdraw(&Principal::anonymous(), &withdraw_amount, &total_amount)
-            .expect("withdrawal should succeed");
+        let hold = BalanceHold::take(caller, &burned).expect("balance covers the burn");
+        apply_successful_withdraw(hold, &total_amount).expect("withdrawal should succeed");
 
-        let stored_balance = STATE.with(|state| {
-            state
-                .borrow()
-                .user_balances
-                .get(&Principal::anonymous())
-                .cloned()
-        });
+        let stored_balance = STATE.with(|state| state.borrow().user_balances.get(&caller).cloned());
         let total = STATE.with(|state| state.borrow().total_deposited.clone());
 
         assert_eq!(stored_balance.unwrap(), initial_balance - withdraw_amount);
-        assert_eq!(total, initial_total - total_amount);
+        assert_eq!(total, Nat::from(220u64));
+
+        // Carried dust counts towards the balance, as it does for burn unlocks.
+        let unit = exchange_rate::WEI_PER_UNIT;
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.user_balances.insert(caller, Nat::from(1u64));
+            state.share_dust.insert(caller, Nat::from(unit / 2));
+        });
+        let hold = BalanceHold::take(caller, &Nat::from(unit + unit / 4)).unwrap();
+        // A second unlock cannot spend what the first one holds.
+        assert!(BalanceHold::take(caller, &Nat::from(unit / 2)).is_err());
+        drop(hold);
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.user_balances[&caller], Nat::from(1u64));
+            assert_eq!(state.share_dust[&caller], Nat::from(unit / 2));
+        });
+
+        let hold = BalanceHold::take(caller, &Nat::from(unit + unit / 4)).unwrap();
+        apply_successful_withdraw(hold, &Nat::from(1u64)).expect("dust covers the fraction");
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.user_balances[&caller], Nat::from(0u64));
+            assert_eq!(state.share_dust[&caller], Nat::from(unit / 4));
+        });
+    }
+
+    #[test]
+    fn stable_state_round_trip_keeps_deposits() {
+        let mut state = State::new();
+        state.next_deposit_id = 2;
+        let mut op = deposit_op(1, deposits::DepositStatus::Pulled, 100);
+        op.pull_block_index = Some(Nat::from(7u64));
+        state.deposits.insert(1, op);
+
+        let restored: State = StableState::from(&state).into();
+
+        assert_eq!(restored.next_deposit_id, 2);
+        assert_eq!(
+            restored.deposits.get(&1).map(|op| op.status.clone()),
+            Some(deposits::DepositStatus::Pulled)
+        );
+    }
+
+    #[test]
+    fn plain_burns_are_bound_to_the_caller_and_guarded() {
+        let mut state = State::new();
+        let other = Principal::from_slice(&[9]);
+        state
+            .deposits
+            .insert(1, deposit_op(1, deposits::DepositStatus::MintConfirmed, 10));
+        state
+            .deposits
+            .insert(2, deposit_op(2, deposits::DepositStatus::Pulled, 10));
+        state.deposits.get_mut(&2).unwrap().eth_address =
+            "0x0000000000000000000000000000000000000002".to_string();
+
+        let caller = Principal::anonymous();
+        let minted = "0x0000000000000000000000000000000000000001";
+        assert!(deposits::minted_to_in(&state, &caller, minted));
+        assert!(!deposits::minted_to_in(&state, &other, minted));
+        // Nothing was minted to an address whose deposit never got that far.
+        assert!(!deposits::minted_to_in(
+            &state,
+            &caller,
+            "0x0000000000000000000000000000000000000002"
+        ));
+
+        let guard = TxHashGuard::acquire("0xabc").unwrap();
+        assert!(TxHashGuard::acquire("0xabc").is_none());
+        assert!(TxHashGuard::acquire("0xdef").is_some());
+        drop(guard);
+        assert!(TxHashGuard::acquire("0xabc").is_some());
+    }
+
+    #[test]
+    fn resolve_chain_defaults_and_rejects_unknown_chains() {
+        reset_state();
+        STATE.with(|state| {
+            state.borrow_mut().chain_contracts.insert(
+                8_453,
+                "0x0000000000000000000000000000000000000002".to_string(),
+            );
+        });
+
+        let (chain_id, contract) = resolve_chain(None).unwrap();
+        assert_eq!(chain_id, DEFAULT_CHAIN_ID);
+        assert_eq!(contract, CONTRACT_ADDRESS.to_lowercase());
+        assert_eq!(resolve_chain(Some(8_453)).unwrap().0, 8_453);
+        assert!(resolve_chain(Some(56)).is_err());
+    }
+
+    #[test]
+    fn share_dust_is_carried_and_residue_goes_to_the_treasury() {
+        reset_state();
+        let user = Principal::anonymous();
+        let scale = 10u128.pow(10);
+
+        // 1.5 units of hstICP, then another half: the fraction is carried.
+        apply_successful_deposit(user, &Nat::from(1u64), &Nat::from(15 * scale / 10));
+        apply_successful_deposit(user, &Nat::from(1u64), &Nat::from(scale / 2));
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.user_balances[&user], Nat::from(2u64));
+            assert!(state.share_dust.is_empty());
+        });
+        apply_successful_deposit(user, &Nat::from(1u64), &Nat::from(scale / 4));
+        let restored: State = STATE
+            .with(|state| StableState::from(&*state.borrow()))
+            .into();
+        assert_eq!(restored.share_dust[&user], Nat::from(scale / 4));
+
+        // Burning a fraction takes it out of the carried dust.
+        STATE.with(|state| state.borrow_mut().total_deposited += Nat::from(1u64));
+        apply_burn_unlock(&user, &Nat::from(scale / 2), &Nat::from(1u64)).unwrap();
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.user_balances[&user], Nat::from(1u64));
+            assert_eq!(state.share_dust[&user], Nat::from(3 * scale / 4));
+        });
+
+        // Once every share is burned, what is left is nobody's.
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            exchange_rate::burn_shares_in(&mut state, &Nat::from(2 * scale));
+            assert_eq!(state.total_deposited, Nat::from(3u64));
+            exchange_rate::burn_shares_in(&mut state, &Nat::from(scale / 4));
+            assert_eq!(state.share_supply, Nat::from(0u64));
+            assert_eq!(state.total_deposited, Nat::from(0u64));
+            assert_eq!(state.treasury_balance, Nat::from(3u64));
+            assert_eq!(accounting::liabilities_in(&state), Nat::from(3u64));
+
+            // Rewards that land with nothing outstanding are swept too.
+            staking::book_rewards_in(&mut state, &Nat::from(7u64), 0);
+            assert_eq!(state.total_deposited, Nat::from(0u64));
+            assert_eq!(state.treasury_balance, Nat::from(10u64));
+            assert_eq!(
+                exchange_rate::shares_for_in(&state, &Nat::from(5u64)),
+                Nat::from(5 * scale)
+            );
+        });
     }
 }
@@ -0,0 +1,324 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::{ensure_controller, State, STATE};
+use candid::CandidType;
+use ic_principal::Principal;
+use serde::{Deserialize, Serialize};
+
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct PauseFlag {
+    pub paused: bool,
+    pub reason: Option<String>,
+    pub changed_at_ns: Option<u64>,
+    /// Who last flipped the flag; `None` when the circuit breaker tripped it.
+    pub changed_by: Option<Principal>,
+}
+
+/// New deposits and mints stop while `deposits` is paused; refunds and
+/// confirmations of mints already sent carry on. Unlocks stop while
+/// `unlocks` is paused.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct PauseState {
+    pub deposits: PauseFlag,
+    pub unlocks: PauseFlag,
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum PauseTarget {
+    Deposits,
+    Unlocks,
+    Both,
+}
+
+impl PauseTarget {
+    fn covers_deposits(self) -> bool {
+        matches!(self, PauseTarget::Deposits | PauseTarget::Both)
+    }
+
+    fn covers_unlocks(self) -> bool {
+        matches!(self, PauseTarget::Unlocks | PauseTarget::Both)
+    }
+}
+
+/// A single entry of the append-only pause log.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PauseEvent {
+    pub id: u64,
+    pub target: PauseTarget,
+    pub paused: bool,
+    pub reason: String,
+    /// `None` for automatic trips.
+    pub caller: Option<Principal>,
+    pub timestamp_ns: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// Failed mints in a row (reverted, cancelled, dropped or rejected by the
+    /// EVM backend) that pause deposits; zero disables the check.
+    pub max_consecutive_mint_failures: u32,
+    /// Pause deposits when a refund transfer is refused by the ledger.
+    pub pause_on_refund_failure: bool,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            max_consecutive_mint_failures: 3,
+            pause_on_refund_failure: true,
+        }
+    }
+}
+
+pub(crate) fn set_paused_in(
+    state: &mut State,
+    target: PauseTarget,
+    paused: bool,
+    reason: String,
+    caller: Option<Principal>,
+    now_ns: u64,
+) {
+    let flag = PauseFlag {
+        paused,
+        reason: Some(reason.clone()),
+        changed_at_ns: Some(now_ns),
+        changed_by: caller,
+    };
+    if target.covers_deposits() {
+        state.pause.deposits = flag.clone();
+    }
+    if target.covers_unlocks() {
+        state.pause.unlocks = flag;
+    }
+    let id = state.pause_events.len() as u64;
+    state.pause_events.push(PauseEvent {
+        id,
+        target,
+        paused,
+        reason,
+        caller,
+        timestamp_ns: now_ns,
+    });
+}
+
+/// Pauses whatever part of `target` is still open on behalf of the circuit
+/// breaker. Flags that are already paused are left alone, so a persisting
+/// anomaly neither floods the log nor overwrites the reason an operator gave.
+pub(crate) fn trip_in(state: &mut State, target: PauseTarget, reason: String, now_ns: u64) {
+    let deposits = target.covers_deposits() && !state.pause.deposits.paused;
+    let unlocks = target.covers_unlocks() && !state.pause.unlocks.paused;
+    let target = match (deposits, unlocks) {
+        (true, true) => PauseTarget::Both,
+        (true, false) => PauseTarget::Deposits,
+        (false, true) => PauseTarget::Unlocks,
+        (false, false) => return,
+    };
+    set_paused_in(state, target, true, reason, None, now_ns);
+}
+
+pub(crate) fn trip(target: PauseTarget, reason: String) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| trip_in(&mut state.borrow_mut(), target, reason, now));
+}
+
+/// Counts a failed mint and trips deposits once the configured run length is
+/// reached.
+pub(crate) fn record_mint_failure_in(state: &mut State, now_ns: u64) {
+    state.consecutive_mint_failures = state.consecutive_mint_failures.saturating_add(1);
+    let limit = state.circuit_breaker.max_consecutive_mint_failures;
+    if limit > 0 && state.consecutive_mint_failures >= limit {
+        let reason = format!("{} mints failed in a row", state.consecutive_mint_failures);
+        trip_in(state, PauseTarget::Deposits, reason, now_ns);
+    }
+}
+
+pub(crate) fn record_mint_failure() {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| record_mint_failure_in(&mut state.borrow_mut(), now));
+}
+
+pub(crate) fn record_mint_success_in(state: &mut State) {
+    state.consecutive_mint_failures = 0;
+}
+
+pub(crate) fn record_refund_failure(id: u64, err: &str) {
+    let enabled = STATE.with(|state| state.borrow().circuit_breaker.pause_on_refund_failure);
+    if enabled {
+        trip(
+            PauseTarget::Deposits,
+            format!("Refund of deposit {} failed: {}", id, err),
+        );
+    }
+}
+
+fn ensure_open(flag: &PauseFlag, direction: &str) -> Result<(), String> {
+    if flag.paused {
+        Err(format!(
+            "{} are paused: {}",
+            direction,
+            flag.reason.as_deref().unwrap_or("no reason given")
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn ensure_deposits_open() -> Result<(), String> {
+    STATE.with(|state| ensure_open(&state.borrow().pause.deposits, "Deposits"))
+}
+
+pub(crate) fn ensure_unlocks_open() -> Result<(), String> {
+    STATE.with(|state| ensure_open(&state.borrow().pause.unlocks, "Unlocks"))
+}
+
+fn ensure_controller_or_guardian(caller: Principal) -> Result<(), String> {
+    let is_guardian = STATE.with(|state| state.borrow().guardians.contains(&caller));
+    if is_guardian {
+        Ok(())
+    } else {
+        ensure_controller(caller)
+    }
+}
+
+fn validate_reason(reason: &str) -> Result<String, String> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err("A reason must be provided".to_string());
+    }
+    Ok(reason.to_string())
+}
+
+#[ic_cdk::query]
+fn get_pause_state() -> PauseState {
+    STATE.with(|state| state.borrow().pause.clone())
+}
+
+// Newest entries first.
+#[ic_cdk::query]
+fn get_pause_events(offset: u64, limit: u64) -> Vec<PauseEvent> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .pause_events
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+/// Controllers and guardians may pause.
+#[ic_cdk::update]
+fn pause_bridge(target: PauseTarget, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    ensure_controller_or_guardian(caller)?;
+    let reason = validate_reason(&reason)?;
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        set_paused_in(
+            &mut state.borrow_mut(),
+            target,
+            true,
+            reason,
+            Some(caller),
+            now,
+        )
+    });
+    Ok(())
+}
+
+/// Only controllers may resume, so a single guardian key cannot undo a
+/// pause. Nothing is resumed automatically.
+#[ic_cdk::update]
+fn unpause_bridge(target: PauseTarget, reason: String) -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    ensure_controller(caller)?;
+    let reason = validate_reason(&reason)?;
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        if target.covers_deposits() {
+            state.consecutive_mint_failures = 0;
+        }
+        set_paused_in(&mut state, target, false, reason, Some(caller), now)
+    });
+    Ok(())
+}
+
+#[ic_cdk::query]
+fn list_guardians() -> Vec<Principal> {
+    STATE.with(|state| state.borrow().guardians.iter().copied().collect())
+}
+
+#[ic_cdk::update]
+fn add_guardian(guardian: Principal) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    STATE.with(|state| {
+        if state.borrow_mut().guardians.insert(guardian) {
+            Ok(())
+        } else {
+            Err(format!("{} is already a guardian", guardian))
+        }
+    })
+}
+
+#[ic_cdk::update]
+fn remove_guardian(guardian: Principal) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    STATE.with(|state| {
+        if state.borrow_mut().guardians.remove(&guardian) {
+            Ok(())
+        } else {
+            Err(format!("{} is not a guardian", guardian))
+        }
+    })
+}
+
+#[ic_cdk::query]
+fn get_circuit_breaker_config() -> CircuitBreakerConfig {
+    STATE.with(|state| state.borrow().circuit_breaker.clone())
+}
+
+#[ic_cdk::update]
+fn set_circuit_breaker_config(config: CircuitBreakerConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    STATE.with(|state| state.borrow_mut().circuit_breaker = config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_mint_failures_trip_deposits_once() {
+        let mut state = State::new();
+        state.circuit_breaker.max_consecutive_mint_failures = 2;
+
+        record_mint_failure_in(&mut state, 1);
+        assert!(!state.pause.deposits.paused);
+        record_mint_failure_in(&mut state, 2);
+        record_mint_failure_in(&mut state, 3);
+
+        assert!(state.pause.deposits.paused);
+        assert!(!state.pause.unlocks.paused);
+        assert_eq!(state.pause.deposits.changed_at_ns, Some(2));
+        assert_eq!(state.pause.deposits.changed_by, None);
+        assert_eq!(state.pause_events.len(), 1);
+
+        // A later invariant breach still pauses the direction left open.
+        trip_in(&mut state, PauseTarget::Both, "drift".to_string(), 4);
+        assert!(state.pause.unlocks.paused);
+        assert_eq!(state.pause_events[1].target, PauseTarget::Unlocks);
+        assert_eq!(
+            state.pause.deposits.reason.as_deref(),
+            Some("2 mints failed in a row")
+        );
+    }
+}
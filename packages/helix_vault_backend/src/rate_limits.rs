@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::{ensure_controller, State, STATE};
+use candid::{CandidType, Nat};
+use ic_principal::Principal;
+use serde::{Deserialize, Serialize};
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BridgeDirection {
+    Deposit,
+    Unlock,
+}
+
+/// Volume allowed within any `window_secs` long stretch ending now. Unset caps
+/// are unlimited.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WindowLimit {
+    pub window_secs: u64,
+    pub per_principal: Option<Nat>,
+    pub global: Option<Nat>,
+}
+
+/// Amounts are in ledger units: the pulled amount for deposits and the
+/// withdrawn amount for unlocks.
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct DirectionLimits {
+    pub max_transfer: Option<Nat>,
+    pub windows: Vec<WindowLimit>,
+}
+
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct RateLimitConfig {
+    pub deposits: DirectionLimits,
+    pub unlocks: DirectionLimits,
+}
+
+impl RateLimitConfig {
+    fn limits(&self, direction: BridgeDirection) -> &DirectionLimits {
+        match direction {
+            BridgeDirection::Deposit => &self.deposits,
+            BridgeDirection::Unlock => &self.unlocks,
+        }
+    }
+
+    fn longest_window_ns(&self) -> u64 {
+        self.deposits
+            .windows
+            .iter()
+            .chain(&self.unlocks.windows)
+            .map(|window| window.window_secs.saturating_mul(NANOS_PER_SEC))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let windows = self.deposits.windows.iter().chain(&self.unlocks.windows);
+        for window in windows {
+            if window.window_secs == 0 {
+                return Err("Rate limit windows must be at least one second".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Volume counted against the limits. Recorded when a transfer is accepted
+/// and only released if it fails before any funds move.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TransferUsage {
+    pub id: u64,
+    pub direction: BridgeDirection,
+    pub principal: Principal,
+    pub amount: Nat,
+    pub timestamp_ns: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WindowQuota {
+    pub window_secs: u64,
+    pub principal_remaining: Option<Nat>,
+    pub global_remaining: Option<Nat>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BridgeQuota {
+    pub direction: BridgeDirection,
+    pub max_transfer: Option<Nat>,
+    pub windows: Vec<WindowQuota>,
+    /// Largest transfer that would be accepted right now; `None` when
+    /// unlimited.
+    pub available: Option<Nat>,
+}
+
+fn used_in(
+    state: &State,
+    direction: BridgeDirection,
+    principal: Option<Principal>,
+    since_ns: u64,
+) -> Nat {
+    state
+        .transfer_usage
+        .iter()
+        .filter(|usage| {
+            usage.direction == direction
+                && usage.timestamp_ns > since_ns
+                && principal.is_none_or(|principal| usage.principal == principal)
+        })
+        .fold(Nat::from(0u64), |sum, usage| sum + usage.amount.clone())
+}
+
+fn remaining(cap: &Option<Nat>, used: Nat) -> Option<Nat> {
+    cap.as_ref().map(|cap| {
+        if *cap > used {
+            cap.clone() - used
+        } else {
+            Nat::from(0u64)
+        }
+    })
+}
+
+fn min_limit(current: Option<Nat>, limit: Option<Nat>) -> Option<Nat> {
+    match (current, limit) {
+        (Some(current), Some(limit)) => Some(current.min(limit)),
+        (current, limit) => current.or(limit),
+    }
+}
+
+pub(crate) fn quota_in(
+    state: &State,
+    direction: BridgeDirection,
+    principal: Principal,
+    now_ns: u64,
+) -> BridgeQuota {
+    let limits = state.rate_limits.limits(direction);
+    let windows: Vec<WindowQuota> = limits
+        .windows
+        .iter()
+        .map(|window| {
+            let since = now_ns.saturating_sub(window.window_secs.saturating_mul(NANOS_PER_SEC));
+            WindowQuota {
+                window_secs: window.window_secs,
+                principal_remaining: remaining(
+                    &window.per_principal,
+                    used_in(state, direction, Some(principal), since),
+                ),
+                global_remaining: remaining(&window.global, used_in(state, direction, None, since)),
+            }
+        })
+        .collect();
+    let available = windows
+        .iter()
+        .flat_map(|window| [&window.principal_remaining, &window.global_remaining])
+        .fold(limits.max_transfer.clone(), |available, limit| {
+            min_limit(available, limit.clone())
+        });
+
+    BridgeQuota {
+        direction,
+        max_transfer: limits.max_transfer.clone(),
+        windows,
+        available,
+    }
+}
+
+/// Checks `amount` against every limit and records it as used. Returns the
+/// usage id for `release_in`.
+pub(crate) fn reserve_in(
+    state: &mut State,
+    direction: BridgeDirection,
+    principal: Principal,
+    amount: &Nat,
+    now_ns: u64,
+) -> Result<u64, String> {
+    let cutoff = now_ns.saturating_sub(state.rate_limits.longest_window_ns());
+    state
+        .transfer_usage
+        .retain(|usage| usage.timestamp_ns > cutoff);
+
+    let quota = quota_in(state, direction, principal, now_ns);
+    if let Some(max_transfer) = &quota.max_transfer {
+        if amount > max_transfer {
+            return Err(format!(
+                "Amount {} exceeds the maximum of {} per transfer",
+                amount, max_transfer
+            ));
+        }
+    }
+    for window in &quota.windows {
+        if let Some(left) = window
+            .principal_remaining
+            .as_ref()
+            .filter(|left| amount > *left)
+        {
+            return Err(format!(
+                "Amount {} exceeds your remaining {} for the current {}s window",
+                amount, left, window.window_secs
+            ));
+        }
+        if let Some(left) = window
+            .global_remaining
+            .as_ref()
+            .filter(|left| amount > *left)
+        {
+            return Err(format!(
+                "Amount {} exceeds the bridge's remaining {} for the current {}s window",
+                amount, left, window.window_secs
+            ));
+        }
+    }
+
+    let id = state.next_usage_id;
+    state.next_usage_id += 1;
+    // Nothing to remember when no window could ever count it.
+    if !state.rate_limits.limits(direction).windows.is_empty() {
+        state.transfer_usage.push(TransferUsage {
+            id,
+            direction,
+            principal,
+            amount: amount.clone(),
+            timestamp_ns: now_ns,
+        });
+    }
+    Ok(id)
+}
+
+pub(crate) fn release_in(state: &mut State, id: u64) {
+    state.transfer_usage.retain(|usage| usage.id != id);
+}
+
+pub(crate) fn reserve(
+    direction: BridgeDirection,
+    principal: Principal,
+    amount: &Nat,
+) -> Result<u64, String> {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| reserve_in(&mut state.borrow_mut(), direction, principal, amount, now))
+}
+
+pub(crate) fn release(id: u64) {
+    STATE.with(|state| release_in(&mut state.borrow_mut(), id));
+}
+
+#[ic_cdk::query]
+fn get_bridge_quota(user: Principal, direction: BridgeDirection) -> BridgeQuota {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| quota_in(&state.borrow(), direction, user, now))
+}
+
+#[ic_cdk::query]
+fn get_rate_limit_config() -> RateLimitConfig {
+    STATE.with(|state| state.borrow().rate_limits.clone())
+}
+
+#[ic_cdk::update]
+fn set_rate_limit_config(config: RateLimitConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    config.validate()?;
+    STATE.with(|state| state.borrow_mut().rate_limits = config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limits_roll_over_windows_and_release_failed_transfers() {
+        let mut state = State::new();
+        let hour = 3_600;
+        let hour_ns = hour * 1_000_000_000;
+        state.rate_limits.deposits = DirectionLimits {
+            max_transfer: Some(Nat::from(60u64)),
+            windows: vec![WindowLimit {
+                window_secs: hour,
+                per_principal: Some(Nat::from(100u64)),
+                global: Some(Nat::from(150u64)),
+            }],
+        };
+        let user = Principal::anonymous();
+        let other = Principal::management_canister();
+        let deposit = BridgeDirection::Deposit;
+        let reserve = |state: &mut State, principal, amount: u64, now| {
+            reserve_in(state, deposit, principal, &Nat::from(amount), now)
+        };
+
+        assert!(reserve(&mut state, user, 61, 1).is_err());
+        reserve(&mut state, user, 60, 1).unwrap();
+        assert!(reserve(&mut state, user, 41, 2).is_err());
+        let failed = reserve(&mut state, other, 60, 2).unwrap();
+        assert!(reserve(&mut state, other, 31, 3).is_err());
+
+        release_in(&mut state, failed);
+        let quota = quota_in(&state, deposit, user, 3);
+        assert_eq!(quota.available, Some(Nat::from(40u64)));
+        // Unlocks are unlimited.
+        let quota = quota_in(&state, BridgeDirection::Unlock, user, 3);
+        assert_eq!(quota.available, None);
+
+        // The first deposit leaves the window an hour later.
+        reserve(&mut state, user, 60, hour_ns + 1).unwrap();
+        assert_eq!(state.transfer_usage.len(), 1);
+    }
+}
@@ -0,0 +1,1402 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::accounting::{book_inflow_in, book_stake_in, book_unstake_in};
+use crate::exchange_rate::{record_rate_in, sweep_residue_in};
+use crate::fees::{accrue_in, fee_for_in, FeeKind};
+use crate::unlocks::{schedule_queue_in, unsettled_demand_in};
+use crate::{current_config, ensure_controller, State, STATE};
+use candid::{CandidType, Nat};
+use ic_cdk::call;
+use ic_principal::Principal;
+use icrc_ledger_types::icrc1::account::{Account, Subaccount};
+use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+use std::time::Duration;
+
+const STAKING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const NNS_GOVERNANCE_CANISTER_ID: &str = "rrkah-fqaaa-aaaaa-aaaaq-cai";
+// Smallest stake the NNS accepts for a neuron.
+const MIN_NEURON_STAKE_E8S: u64 = 100_000_000;
+// Longest dissolve delay the NNS accepts.
+const MAX_DISSOLVE_DELAY_SECS: u64 = 8 * 365 * 24 * 60 * 60;
+const BPS_DENOMINATOR: u64 = 10_000;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+// Governance pays out disbursed maturity seven days later; a day on top of
+// that, a disbursement is taken to have landed.
+const MATURITY_SETTLED_AFTER_NS: u64 = 8 * 24 * 60 * 60 * NANOS_PER_SEC;
+
+thread_local! {
+    // Set while a staking round or a manual stake runs. Both read the liquid
+    // balance before awaiting and book the stake against that read.
+    static STAKING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current call as the one staking; released on drop, which also
+/// runs when the call traps after an await.
+struct StakingGuard;
+
+impl StakingGuard {
+    fn acquire() -> Result<Self, String> {
+        if STAKING.with(|staking| staking.replace(true)) {
+            return Err("Staking is already in progress".to_string());
+        }
+        Ok(StakingGuard)
+    }
+}
+
+impl Drop for StakingGuard {
+    fn drop(&mut self) {
+        STAKING.with(|staking| staking.set(false));
+    }
+}
+
+/// Vault subaccount maturity is disbursed to, apart from the main account so
+/// rewards are recognised only once they land. Deposit subaccounts start with
+/// a principal length, never 0xff.
+pub(crate) const REWARDS_SUBACCOUNT: Subaccount = {
+    let mut subaccount = [0u8; 32];
+    subaccount[0] = 0xff;
+    subaccount
+};
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StakingConfig {
+    pub governance_canister: Principal,
+    /// Dissolve delay new neurons are locked for, and kept at while locked.
+    pub dissolve_delay_secs: u64,
+    /// Share of `total_deposited`, in basis points, that stays liquid for
+    /// unlocks.
+    pub liquid_buffer_bps: u64,
+    /// Maturity, in e8s, a neuron accrues before it is disbursed to the vault.
+    pub min_maturity_to_disburse: Nat,
+    /// Stake everything above the buffer on every staking round.
+    pub auto_stake: bool,
+}
+
+impl Default for StakingConfig {
+    fn default() -> Self {
+        Self {
+            governance_canister: Principal::from_text(NNS_GOVERNANCE_CANISTER_ID)
+                .expect("invalid governance principal"),
+            // Six months, the shortest delay that earns voting rewards.
+            dissolve_delay_secs: 182 * 24 * 60 * 60,
+            liquid_buffer_bps: 2_000,
+            min_maturity_to_disburse: Nat::from(MIN_NEURON_STAKE_E8S),
+            auto_stake: false,
+        }
+    }
+}
+
+impl StakingConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.liquid_buffer_bps > BPS_DENOMINATOR {
+            return Err("liquid_buffer_bps cannot exceed 10000".to_string());
+        }
+        if self.dissolve_delay_secs > MAX_DISSOLVE_DELAY_SECS {
+            return Err("dissolve_delay_secs cannot exceed eight years".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum NeuronStatus {
+    /// Funds sent to the neuron's account; not yet claimed.
+    Funding,
+    Locked,
+    Dissolving,
+    /// Dissolved; the stake waits to be disbursed to the vault.
+    Dissolved,
+    Disbursed,
+}
+
+/// The transfer funding a neuron, kept until the ledger confirms it so that
+/// every retry carries the same fee and creation time and is deduplicated.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct NeuronFunding {
+    pub fee: Nat,
+    pub created_at_ns: u64,
+}
+
+/// A neuron controlled by the vault. `id` is local and doubles as the memo
+/// of the neuron's staking account. Amounts are in e8s.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StakedNeuron {
+    pub id: u64,
+    pub neuron_id: Option<u64>,
+    pub stake: Nat,
+    pub maturity: Nat,
+    pub dissolve_delay_secs: u64,
+    pub dissolves_at_secs: Option<u64>,
+    pub status: NeuronStatus,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+    pub last_error: Option<String>,
+    /// Set while the staking transfer is unconfirmed; the stake is only
+    /// booked once it is. `None` in state saved before.
+    pub funding: Option<NeuronFunding>,
+}
+
+/// Maturity a neuron started disbursing. Governance pays it into
+/// `REWARDS_SUBACCOUNT` about seven days later, modulated by a few percent.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PendingMaturity {
+    /// Local id of the neuron.
+    pub neuron: u64,
+    pub maturity: Nat,
+    pub started_at_ns: u64,
+}
+
+/// A transfer of landed rewards into the main account, kept until the ledger
+/// confirms it so that a retry is deduplicated.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RewardSweep {
+    pub amount: Nat,
+    pub fee: Nat,
+    pub created_at_ns: u64,
+}
+
+/// Where the vault's ICP is. `liquid` is the ledger balance as last read.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StakingBuckets {
+    pub liquid: Nat,
+    pub liquid_read_at_ns: Option<u64>,
+    /// Funding and locked neurons, once their staking transfer is confirmed.
+    pub staked: Nat,
+    /// Dissolving neurons and dissolved ones not yet disbursed.
+    pub dissolving: Nat,
+    /// Maturity accrued but not yet disbursed.
+    pub maturity: Nat,
+    /// Maturity disbursing to the vault that has not landed yet.
+    pub maturity_disbursing: Nat,
+    /// Rewards landed in the vault so far.
+    pub rewards_disbursed: Nat,
+}
+
+/// A neuron as reported by governance.
+#[derive(Clone, Debug)]
+pub(crate) struct NeuronSnapshot {
+    pub stake: Nat,
+    pub maturity: Nat,
+    pub dissolve_delay_secs: u64,
+    pub dissolves_at_secs: Option<u64>,
+}
+
+impl NeuronSnapshot {
+    fn status(&self, now_secs: u64) -> NeuronStatus {
+        match self.dissolves_at_secs {
+            Some(at) if at > now_secs => NeuronStatus::Dissolving,
+            Some(_) => NeuronStatus::Dissolved,
+            None if self.dissolve_delay_secs > 0 => NeuronStatus::Locked,
+            None => NeuronStatus::Dissolved,
+        }
+    }
+}
+
+/// The governance operations staking relies on, so the flows can run against
+/// a mock outside a canister.
+pub(crate) trait Governance {
+    /// Sends `amount` from the vault to the staking account of neuron `memo`.
+    /// A duplicate of an earlier transfer counts as sent. `Ok(Err)` when the
+    /// ledger refused the transfer, `Err` when the call failed and the
+    /// transfer may have landed.
+    async fn fund_neuron(
+        &self,
+        memo: u64,
+        amount: &Nat,
+        funding: &NeuronFunding,
+    ) -> Result<Result<(), String>, String>;
+    /// Claims, or refreshes, the neuron funded under `memo` and returns its id.
+    async fn claim_neuron(&self, memo: u64) -> Result<u64, String>;
+    async fn increase_dissolve_delay(
+        &self,
+        neuron_id: u64,
+        additional_secs: u32,
+    ) -> Result<(), String>;
+    async fn start_dissolving(&self, neuron_id: u64) -> Result<(), String>;
+    async fn neuron(&self, neuron_id: u64) -> Result<NeuronSnapshot, String>;
+    /// Disburses the whole stake of a dissolved neuron to the vault.
+    async fn disburse(&self, neuron_id: u64) -> Result<(), String>;
+    /// Starts disbursing all maturity to `REWARDS_SUBACCOUNT` and returns the
+    /// maturity disbursed.
+    async fn disburse_maturity(&self, neuron_id: u64) -> Result<Nat, String>;
+    /// Balance of `REWARDS_SUBACCOUNT`.
+    async fn rewards_balance(&self) -> Result<Nat, String>;
+    /// Moves `sweep.amount` from `REWARDS_SUBACCOUNT` to the main account,
+    /// with the same outcomes as `fund_neuron`.
+    async fn sweep_rewards(&self, sweep: &RewardSweep) -> Result<Result<(), String>, String>;
+}
+
+// Subset of the NNS governance interface.
+#[derive(CandidType, Deserialize)]
+struct NeuronId {
+    id: u64,
+}
+
+#[derive(CandidType)]
+struct ManageNeuron {
+    id: Option<NeuronId>,
+    command: Option<Command>,
+}
+
+#[derive(CandidType)]
+enum Command {
+    ClaimOrRefresh(ClaimOrRefresh),
+    Configure(Configure),
+    Disburse(Disburse),
+    DisburseMaturity(DisburseMaturity),
+}
+
+#[derive(CandidType)]
+struct ClaimOrRefresh {
+    by: Option<By>,
+}
+
+#[derive(CandidType)]
+enum By {
+    MemoAndController(ClaimOrRefreshNeuronFromAccount),
+}
+
+#[derive(CandidType)]
+struct ClaimOrRefreshNeuronFromAccount {
+    controller: Option<Principal>,
+    memo: u64,
+}
+
+#[derive(CandidType)]
+struct Configure {
+    operation: Option<Operation>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct EmptyRecord {}
+
+#[derive(CandidType)]
+enum Operation {
+    StartDissolving(EmptyRecord),
+    IncreaseDissolveDelay(IncreaseDissolveDelay),
+}
+
+#[derive(CandidType)]
+struct IncreaseDissolveDelay {
+    additional_dissolve_delay_seconds: u32,
+}
+
+#[derive(CandidType)]
+struct AccountIdentifier {
+    hash: Vec<u8>,
+}
+
+#[derive(CandidType)]
+struct Amount {
+    e8s: u64,
+}
+
+// Both left empty: the whole stake goes to the controller's account.
+#[derive(CandidType)]
+struct Disburse {
+    to_account: Option<AccountIdentifier>,
+    amount: Option<Amount>,
+}
+
+#[derive(CandidType)]
+struct GovernanceAccount {
+    owner: Option<Principal>,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType)]
+struct DisburseMaturity {
+    to_account: Option<GovernanceAccount>,
+    percentage_to_disburse: u32,
+}
+
+#[derive(CandidType, Deserialize)]
+struct GovernanceError {
+    error_message: String,
+    error_type: i32,
+}
+
+#[derive(CandidType, Deserialize)]
+struct ClaimOrRefreshResponse {
+    refreshed_neuron_id: Option<NeuronId>,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DisburseResponse {
+    transfer_block_height: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DisburseMaturityResponse {
+    amount_disbursed_e8s: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+enum CommandResponse {
+    Error(GovernanceError),
+    ClaimOrRefresh(ClaimOrRefreshResponse),
+    Configure(EmptyRecord),
+    Disburse(DisburseResponse),
+    DisburseMaturity(DisburseMaturityResponse),
+}
+
+#[derive(CandidType, Deserialize)]
+struct ManageNeuronResponse {
+    command: Option<CommandResponse>,
+}
+
+#[derive(CandidType, Deserialize)]
+enum DissolveState {
+    DissolveDelaySeconds(u64),
+    WhenDissolvedTimestampSeconds(u64),
+}
+
+#[derive(CandidType, Deserialize)]
+struct FullNeuron {
+    cached_neuron_stake_e8s: u64,
+    maturity_e8s_equivalent: u64,
+    dissolve_state: Option<DissolveState>,
+}
+
+/// Staking account of the neuron `controller` funds under `memo`, as the NNS
+/// derives it.
+pub(crate) fn neuron_subaccount(controller: Principal, memo: u64) -> Subaccount {
+    let mut hasher = Sha256::new();
+    hasher.update([0x0c]);
+    hasher.update(b"neuron-stake");
+    hasher.update(controller.as_slice());
+    hasher.update(memo.to_be_bytes());
+    hasher.finalize().into()
+}
+
+/// The NNS governance canister, funded from the vault's ledger.
+pub(crate) struct NnsGovernance {
+    governance: Principal,
+    ledger: Principal,
+}
+
+impl NnsGovernance {
+    fn from_config() -> Self {
+        NnsGovernance {
+            governance: STATE.with(|state| state.borrow().staking_config.governance_canister),
+            ledger: current_config().ledger_canister,
+        }
+    }
+
+    async fn manage_neuron(&self, arg: ManageNeuron) -> Result<CommandResponse, String> {
+        let (response,): (ManageNeuronResponse,) = call(self.governance, "manage_neuron", (arg,))
+            .await
+            .map_err(|e| format!("Call to governance failed: {:?}", e))?;
+        match response.command {
+            Some(CommandResponse::Error(err)) => Err(format!(
+                "Governance error {}: {}",
+                err.error_type, err.error_message
+            )),
+            Some(command) => Ok(command),
+            None => Err("Governance returned no response".to_string()),
+        }
+    }
+
+    async fn configure(&self, neuron_id: u64, operation: Operation) -> Result<(), String> {
+        self.manage_neuron(ManageNeuron {
+            id: Some(NeuronId { id: neuron_id }),
+            command: Some(Command::Configure(Configure {
+                operation: Some(operation),
+            })),
+        })
+        .await
+        .map(|_| ())
+    }
+}
+
+impl Governance for NnsGovernance {
+    async fn fund_neuron(
+        &self,
+        memo: u64,
+        amount: &Nat,
+        funding: &NeuronFunding,
+    ) -> Result<Result<(), String>, String> {
+        let transfer_arg = TransferArg {
+            from_subaccount: None,
+            to: Account {
+                owner: self.governance,
+                subaccount: Some(neuron_subaccount(ic_cdk::id(), memo)),
+            },
+            amount: amount.clone(),
+            fee: Some(funding.fee.clone()),
+            memo: Some(Memo::from(memo)),
+            created_at_time: Some(funding.created_at_ns),
+        };
+        match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+            self.ledger,
+            "icrc1_transfer",
+            (transfer_arg,),
+        )
+        .await
+        {
+            Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => Ok(Ok(())),
+            Ok((Err(e),)) => Ok(Err(format!("Staking transfer failed: {:?}", e))),
+            Err(e) => Err(format!("Staking transfer call failed: {:?}", e)),
+        }
+    }
+
+    async fn claim_neuron(&self, memo: u64) -> Result<u64, String> {
+        let response = self
+            .manage_neuron(ManageNeuron {
+                id: None,
+                command: Some(Command::ClaimOrRefresh(ClaimOrRefresh {
+                    by: Some(By::MemoAndController(ClaimOrRefreshNeuronFromAccount {
+                        controller: Some(ic_cdk::id()),
+                        memo,
+                    })),
+                })),
+            })
+            .await?;
+        match response {
+            CommandResponse::ClaimOrRefresh(ClaimOrRefreshResponse {
+                refreshed_neuron_id: Some(NeuronId { id }),
+            }) => Ok(id),
+            _ => Err("Governance did not return a neuron id".to_string()),
+        }
+    }
+
+    async fn increase_dissolve_delay(
+        &self,
+        neuron_id: u64,
+        additional_secs: u32,
+    ) -> Result<(), String> {
+        self.configure(
+            neuron_id,
+            Operation::IncreaseDissolveDelay(IncreaseDissolveDelay {
+                additional_dissolve_delay_seconds: additional_secs,
+            }),
+        )
+        .await
+    }
+
+    async fn start_dissolving(&self, neuron_id: u64) -> Result<(), String> {
+        self.configure(neuron_id, Operation::StartDissolving(EmptyRecord {}))
+            .await
+    }
+
+    async fn neuron(&self, neuron_id: u64) -> Result<NeuronSnapshot, String> {
+        let (result,): (Result<FullNeuron, GovernanceError>,) =
+            call(self.governance, "get_full_neuron", (neuron_id,))
+                .await
+                .map_err(|e| format!("Call to governance failed: {:?}", e))?;
+        let neuron = result
+            .map_err(|err| format!("Governance error {}: {}", err.error_type, err.error_message))?;
+        let (dissolve_delay_secs, dissolves_at_secs) = match neuron.dissolve_state {
+            Some(DissolveState::DissolveDelaySeconds(delay)) => (delay, None),
+            Some(DissolveState::WhenDissolvedTimestampSeconds(at)) => (0, Some(at)),
+            None => (0, None),
+        };
+        Ok(NeuronSnapshot {
+            stake: Nat::from(neuron.cached_neuron_stake_e8s),
+            maturity: Nat::from(neuron.maturity_e8s_equivalent),
+            dissolve_delay_secs,
+            dissolves_at_secs,
+        })
+    }
+
+    async fn disburse(&self, neuron_id: u64) -> Result<(), String> {
+        self.manage_neuron(ManageNeuron {
+            id: Some(NeuronId { id: neuron_id }),
+            command: Some(Command::Disburse(Disburse {
+                to_account: None,
+                amount: None,
+            })),
+        })
+        .await
+        .map(|_| ())
+    }
+
+    async fn disburse_maturity(&self, neuron_id: u64) -> Result<Nat, String> {
+        let response = self
+            .manage_neuron(ManageNeuron {
+                id: Some(NeuronId { id: neuron_id }),
+                command: Some(Command::DisburseMaturity(DisburseMaturity {
+                    to_account: Some(GovernanceAccount {
+                        owner: Some(ic_cdk::id()),
+                        subaccount: Some(REWARDS_SUBACCOUNT.to_vec()),
+                    }),
+                    percentage_to_disburse: 100,
+                })),
+            })
+            .await?;
+        match response {
+            CommandResponse::DisburseMaturity(DisburseMaturityResponse {
+                amount_disbursed_e8s,
+            }) => Ok(Nat::from(amount_disbursed_e8s.unwrap_or(0))),
+            _ => Err("Unexpected response to DisburseMaturity".to_string()),
+        }
+    }
+
+    async fn rewards_balance(&self) -> Result<Nat, String> {
+        let (balance,): (Nat,) = call(
+            self.ledger,
+            "icrc1_balance_of",
+            (Account {
+                owner: ic_cdk::id(),
+                subaccount: Some(REWARDS_SUBACCOUNT),
+            },),
+        )
+        .await
+        .map_err(|e| format!("Failed to get rewards balance: {:?}", e))?;
+        Ok(balance)
+    }
+
+    async fn sweep_rewards(&self, sweep: &RewardSweep) -> Result<Result<(), String>, String> {
+        let transfer_arg = TransferArg {
+            from_subaccount: Some(REWARDS_SUBACCOUNT),
+            to: Account {
+                owner: ic_cdk::id(),
+                subaccount: None,
+            },
+            amount: sweep.amount.clone(),
+            fee: Some(sweep.fee.clone()),
+            memo: None,
+            created_at_time: Some(sweep.created_at_ns),
+        };
+        match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+            self.ledger,
+            "icrc1_transfer",
+            (transfer_arg,),
+        )
+        .await
+        {
+            Ok((Ok(_),)) | Ok((Err(TransferError::Duplicate { .. }),)) => Ok(Ok(())),
+            Ok((Err(e),)) => Ok(Err(format!("Reward sweep failed: {:?}", e))),
+            Err(e) => Err(format!("Reward sweep call failed: {:?}", e)),
+        }
+    }
+}
+
+pub(crate) fn buckets_in(state: &State) -> StakingBuckets {
+    let zero = || Nat::from(0u64);
+    let (staked, dissolving, maturity) = state.neurons.values().fold(
+        (zero(), zero(), zero()),
+        |(staked, dissolving, maturity), neuron| match neuron.status {
+            _ if neuron.funding.is_some() => (staked, dissolving, maturity),
+            NeuronStatus::Funding | NeuronStatus::Locked => (
+                staked + neuron.stake.clone(),
+                dissolving,
+                maturity + neuron.maturity.clone(),
+            ),
+            NeuronStatus::Dissolving | NeuronStatus::Dissolved => (
+                staked,
+                dissolving + neuron.stake.clone(),
+                maturity + neuron.maturity.clone(),
+            ),
+            NeuronStatus::Disbursed => (staked, dissolving, maturity),
+        },
+    );
+    StakingBuckets {
+        liquid: state.liquid_balance.clone(),
+        liquid_read_at_ns: state.liquid_read_at_ns,
+        staked,
+        dissolving,
+        maturity,
+        maturity_disbursing: state
+            .pending_maturity
+            .iter()
+            .fold(Nat::from(0u64), |total, pending| {
+                total + pending.maturity.clone()
+            }),
+        rewards_disbursed: state.rewards_disbursed.clone(),
+    }
+}
+
+/// ICP held in neurons that have not been disbursed.
+pub(crate) fn neuron_stake_in(state: &State) -> Nat {
+    let buckets = buckets_in(state);
+    buckets.staked + buckets.dissolving
+}
+
+/// Stakes and fees of staking transfers not confirmed yet, which may still
+/// leave the main account.
+fn unconfirmed_funding_in(state: &State) -> Nat {
+    state
+        .neurons
+        .values()
+        .filter_map(|neuron| {
+            let funding = neuron.funding.as_ref()?;
+            Some(neuron.stake.clone() + funding.fee.clone())
+        })
+        .fold(Nat::from(0u64), |total, amount| total + amount)
+}
+
+/// Largest amount that can be staked from `liquid` without going below the
+/// buffer, after the fee of the staking transfer, unconfirmed staking
+/// transfers, what queued withdrawals are owed and the accrued fees waiting
+/// to be swept.
+pub(crate) fn stakeable_in(state: &State, liquid: &Nat) -> Nat {
+    let buffer = state.total_deposited.clone() * Nat::from(state.staking_config.liquid_buffer_bps)
+        / Nat::from(BPS_DENOMINATOR);
+    let reserved = buffer
+        + state.transfer_fee.clone()
+        + unconfirmed_funding_in(state)
+        + unsettled_demand_in(state)
+        + state.treasury_balance.clone();
+    if *liquid > reserved {
+        liquid.clone() - reserved
+    } else {
+        Nat::from(0u64)
+    }
+}
+
+/// When `shortfall` more ledger units are likely to be liquid: as neurons
+/// already dissolving come due, then as locked ones would if they started
+/// dissolving now. `None` when staked ICP cannot cover it.
+pub(crate) fn estimate_available_in(state: &State, shortfall: &Nat, now_ns: u64) -> Option<u64> {
+    let now_secs = now_ns / NANOS_PER_SEC;
+    let mut dissolving: Vec<(u64, Nat)> = state
+        .neurons
+        .values()
+        .filter(|neuron| {
+            matches!(
+                neuron.status,
+                NeuronStatus::Dissolving | NeuronStatus::Dissolved
+            )
+        })
+        .map(|neuron| {
+            let at = neuron.dissolves_at_secs.unwrap_or(now_secs).max(now_secs);
+            (at, neuron.stake.clone())
+        })
+        .collect();
+    dissolving.sort_by_key(|(at, _)| *at);
+    let locked = state
+        .neurons
+        .values()
+        .filter(|neuron| neuron.status == NeuronStatus::Locked)
+        .map(|neuron| (now_secs + neuron.dissolve_delay_secs, neuron.stake.clone()));
+
+    let mut covered = Nat::from(0u64);
+    let mut latest = now_secs;
+    for (at, stake) in dissolving.into_iter().chain(locked) {
+        covered += stake;
+        latest = latest.max(at);
+        if covered >= *shortfall {
+            return Some(latest * NANOS_PER_SEC);
+        }
+    }
+    None
+}
+
+/// Locked neurons, oldest first, to start dissolving so that queued
+/// withdrawals not covered by `liquid` or dissolving neurons are.
+pub(crate) fn neurons_to_dissolve_in(state: &State, liquid: &Nat) -> Vec<u64> {
+    let dissolving = buckets_in(state).dissolving;
+    let mut covered = liquid.clone() + dissolving;
+    let demand = unsettled_demand_in(state);
+    let mut ids = Vec::new();
+    for neuron in state.neurons.values() {
+        if covered >= demand {
+            break;
+        }
+        if neuron.status == NeuronStatus::Locked {
+            covered += neuron.stake.clone();
+            ids.push(neuron.id);
+        }
+    }
+    ids
+}
+
+fn update_neuron(id: u64, now_ns: u64, f: impl FnOnce(&mut StakedNeuron)) {
+    STATE.with(|state| {
+        if let Some(neuron) = state.borrow_mut().neurons.get_mut(&id) {
+            f(neuron);
+            neuron.updated_at_ns = now_ns;
+        }
+    });
+}
+
+fn neuron(id: u64) -> Result<StakedNeuron, String> {
+    STATE
+        .with(|state| state.borrow().neurons.get(&id).cloned())
+        .ok_or_else(|| format!("Neuron {} not found", id))
+}
+
+/// The staking transfer of neuron `id` landed: its stake moves to the books.
+fn confirm_funding_in(state: &mut State, id: u64, now_ns: u64) {
+    let Some(neuron) = state.neurons.get_mut(&id) else {
+        return;
+    };
+    let Some(funding) = neuron.funding.take() else {
+        return;
+    };
+    neuron.updated_at_ns = now_ns;
+    let stake = neuron.stake.clone();
+    book_stake_in(state, &stake, &funding.fee);
+}
+
+pub(crate) fn record_liquid_in(state: &mut State, liquid: &Nat, now_ns: u64) {
+    state.liquid_balance = liquid.clone();
+    state.liquid_read_at_ns = Some(now_ns);
+}
+
+/// Stakes `amount` out of `liquid` into a new neuron and locks it for the
+/// configured dissolve delay. A neuron whose staking transfer could not be
+/// confirmed, or that could not be claimed or locked yet, is finished by the
+/// next refresh.
+pub(crate) async fn stake<G: Governance>(
+    gov: &G,
+    amount: Nat,
+    liquid: &Nat,
+    now_ns: u64,
+) -> Result<u64, String> {
+    if amount < MIN_NEURON_STAKE_E8S {
+        return Err(format!(
+            "A neuron needs a stake of at least {} e8s",
+            MIN_NEURON_STAKE_E8S
+        ));
+    }
+    let (available, fee) = STATE.with(|state| {
+        let state = state.borrow();
+        (stakeable_in(&state, liquid), state.transfer_fee.clone())
+    });
+    if amount > available {
+        return Err(format!(
+            "Only {} e8s can be staked without dipping into the liquid buffer",
+            available
+        ));
+    }
+
+    let funding = NeuronFunding {
+        fee: fee.clone(),
+        created_at_ns: now_ns,
+    };
+    let id = STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let id = state.next_neuron_id;
+        state.next_neuron_id += 1;
+        state.neurons.insert(
+            id,
+            StakedNeuron {
+                id,
+                neuron_id: None,
+                stake: amount.clone(),
+                maturity: Nat::from(0u64),
+                dissolve_delay_secs: 0,
+                dissolves_at_secs: None,
+                status: NeuronStatus::Funding,
+                created_at_ns: now_ns,
+                updated_at_ns: now_ns,
+                last_error: None,
+                funding: Some(funding.clone()),
+            },
+        );
+        id
+    });
+    match gov.fund_neuron(id, &amount, &funding).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            STATE.with(|state| state.borrow_mut().neurons.remove(&id));
+            return Err(e);
+        }
+        Err(e) => {
+            update_neuron(id, now_ns, |neuron| neuron.last_error = Some(e));
+            return Ok(id);
+        }
+    }
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        // `stakeable_in` checked that `liquid` covers the stake and its fee.
+        state.liquid_balance = liquid.clone() - amount.clone() - fee.clone();
+        state.liquid_read_at_ns = Some(now_ns);
+        confirm_funding_in(&mut state, id, now_ns);
+    });
+
+    if let Err(e) = refresh_neuron(gov, id, now_ns).await {
+        update_neuron(id, now_ns, |neuron| neuron.last_error = Some(e));
+    }
+    Ok(id)
+}
+
+/// Re-sends the unconfirmed staking transfer of `current` with its original
+/// fee and creation time, so the ledger deduplicates it, and books the stake
+/// once it is confirmed. Returns the neuron id when confirming it took a
+/// claim.
+async fn resend_funding<G: Governance>(
+    gov: &G,
+    current: &StakedNeuron,
+    now_ns: u64,
+) -> Result<Option<u64>, String> {
+    let Some(funding) = current.funding.as_ref() else {
+        return Ok(None);
+    };
+    let claimed = match gov.fund_neuron(current.id, &current.stake, funding).await? {
+        Ok(()) => None,
+        // Past the ledger's deduplication window a landed transfer is
+        // refused; a neuron that can be claimed proves it landed.
+        Err(refused) => Some(gov.claim_neuron(current.id).await.map_err(|_| refused)?),
+    };
+    STATE.with(|state| confirm_funding_in(&mut state.borrow_mut(), current.id, now_ns));
+    Ok(claimed)
+}
+
+/// Brings neuron `id` in line with governance: confirms its staking transfer
+/// and claims it if needed, keeps a locked neuron at the configured delay,
+/// disburses maturity above the threshold and disburses the stake once
+/// dissolved.
+pub(crate) async fn refresh_neuron<G: Governance>(
+    gov: &G,
+    id: u64,
+    now_ns: u64,
+) -> Result<(), String> {
+    let current = neuron(id)?;
+    if current.status == NeuronStatus::Disbursed {
+        return Ok(());
+    }
+    let config = STATE.with(|state| state.borrow().staking_config.clone());
+
+    let claimed = resend_funding(gov, &current, now_ns).await?;
+    let neuron_id = match current.neuron_id {
+        Some(neuron_id) => neuron_id,
+        None => {
+            let neuron_id = match claimed {
+                Some(neuron_id) => neuron_id,
+                None => gov.claim_neuron(id).await?,
+            };
+            update_neuron(id, now_ns, |neuron| {
+                neuron.neuron_id = Some(neuron_id);
+                neuron.status = NeuronStatus::Locked;
+            });
+            neuron_id
+        }
+    };
+
+    let mut snapshot = gov.neuron(neuron_id).await?;
+    // A new neuron has no delay yet and would read as dissolved, so go by our
+    // own record: only neurons we never started dissolving are raised.
+    if matches!(current.status, NeuronStatus::Funding | NeuronStatus::Locked)
+        && snapshot.dissolves_at_secs.is_none()
+        && snapshot.dissolve_delay_secs < config.dissolve_delay_secs
+    {
+        let additional =
+            (config.dissolve_delay_secs - snapshot.dissolve_delay_secs).min(u32::MAX as u64) as u32;
+        gov.increase_dissolve_delay(neuron_id, additional).await?;
+        snapshot.dissolve_delay_secs += additional as u64;
+    }
+    let status = snapshot.status(now_ns / NANOS_PER_SEC);
+    update_neuron(id, now_ns, |neuron| {
+        neuron.stake = snapshot.stake.clone();
+        neuron.maturity = snapshot.maturity.clone();
+        neuron.dissolve_delay_secs = snapshot.dissolve_delay_secs;
+        neuron.dissolves_at_secs = snapshot.dissolves_at_secs;
+        neuron.status = status;
+        neuron.last_error = None;
+    });
+
+    if snapshot.maturity > 0u64 && snapshot.maturity >= config.min_maturity_to_disburse {
+        let disbursed = gov.disburse_maturity(neuron_id).await?;
+        // Booked by `collect_rewards` once it lands.
+        STATE.with(|state| {
+            state.borrow_mut().pending_maturity.push(PendingMaturity {
+                neuron: id,
+                maturity: disbursed.clone(),
+                started_at_ns: now_ns,
+            })
+        });
+        update_neuron(id, now_ns, |neuron| {
+            neuron.maturity = if neuron.maturity > disbursed {
+                neuron.maturity.clone() - disbursed
+            } else {
+                Nat::from(0u64)
+            };
+        });
+    }
+
+    if status == NeuronStatus::Dissolved && snapshot.stake > 0u64 {
+        gov.disburse(neuron_id).await?;
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let fee = state.transfer_fee.clone();
+            book_unstake_in(&mut state, &snapshot.stake, &fee);
+        });
+        update_neuron(id, now_ns, |neuron| {
+            neuron.stake = Nat::from(0u64);
+            neuron.status = NeuronStatus::Disbursed;
+        });
+    }
+    Ok(())
+}
+
+/// Rewards that landed in the main account: less the reward fee, they raise
+/// the ICP behind every hstICP, and so the exchange rate.
+pub(crate) fn book_rewards_in(state: &mut State, landed: &Nat, now_ns: u64) {
+    let reward_fee = fee_for_in(state, FeeKind::Reward, landed);
+    state.rewards_disbursed += landed.clone();
+    state.total_deposited += landed.clone() - reward_fee.clone();
+    book_inflow_in(state, landed);
+    accrue_in(state, FeeKind::Reward, &reward_fee, now_ns);
+    sweep_residue_in(state);
+    record_rate_in(state, now_ns);
+}
+
+/// Sweeps maturity that landed in `REWARDS_SUBACCOUNT` into the main account
+/// and books it as rewards. An unconfirmed sweep is resent as is.
+pub(crate) async fn collect_rewards<G: Governance>(gov: &G, now_ns: u64) -> Result<(), String> {
+    STATE.with(|state| {
+        state.borrow_mut().pending_maturity.retain(|pending| {
+            now_ns.saturating_sub(pending.started_at_ns) < MATURITY_SETTLED_AFTER_NS
+        })
+    });
+    let sweep = match STATE.with(|state| state.borrow().reward_sweep.clone()) {
+        Some(sweep) => sweep,
+        None => {
+            let balance = gov.rewards_balance().await?;
+            let fee = STATE.with(|state| state.borrow().transfer_fee.clone());
+            if balance <= fee {
+                return Ok(());
+            }
+            let sweep = RewardSweep {
+                amount: balance - fee.clone(),
+                fee,
+                created_at_ns: now_ns,
+            };
+            STATE.with(|state| state.borrow_mut().reward_sweep = Some(sweep.clone()));
+            sweep
+        }
+    };
+    let result = gov.sweep_rewards(&sweep).await?;
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.reward_sweep = None;
+        if result.is_ok() {
+            book_rewards_in(&mut state, &sweep.amount, now_ns);
+        }
+    });
+    // A refused sweep moved nothing; the next round reads the balance again.
+    result
+}
+
+/// One staking round: refreshes every neuron, collects landed rewards,
+/// starts dissolving what queued withdrawals need, then stakes the excess
+/// above the buffer when `auto_stake` is set. Errors are recorded per neuron.
+pub(crate) async fn run_staking<G: Governance>(gov: &G, liquid: Nat, now_ns: u64) {
+    STATE.with(|state| record_liquid_in(&mut state.borrow_mut(), &liquid, now_ns));
+    let ids: Vec<u64> = STATE.with(|state| {
+        state
+            .borrow()
+            .neurons
+            .values()
+            .filter(|neuron| neuron.status != NeuronStatus::Disbursed)
+            .map(|neuron| neuron.id)
+            .collect()
+    });
+    for id in ids {
+        if let Err(e) = refresh_neuron(gov, id, now_ns).await {
+            update_neuron(id, now_ns, |neuron| neuron.last_error = Some(e));
+        }
+    }
+    // Rewards that could not be collected are picked up by the next round.
+    let _ = collect_rewards(gov, now_ns).await;
+
+    // Withdrawals the buffer cannot cover are paid from dissolving neurons.
+    let to_dissolve = STATE.with(|state| neurons_to_dissolve_in(&state.borrow(), &liquid));
+    for id in to_dissolve {
+        let Some(neuron_id) = neuron(id).ok().and_then(|neuron| neuron.neuron_id) else {
+            continue;
+        };
+        let result = match gov.start_dissolving(neuron_id).await {
+            Ok(()) => refresh_neuron(gov, id, now_ns).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            update_neuron(id, now_ns, |neuron| neuron.last_error = Some(e));
+        }
+    }
+    STATE.with(|state| schedule_queue_in(&mut state.borrow_mut(), &liquid, now_ns));
+
+    let (auto_stake, available) = STATE.with(|state| {
+        let state = state.borrow();
+        (
+            state.staking_config.auto_stake,
+            stakeable_in(&state, &state.liquid_balance),
+        )
+    });
+    if auto_stake && available >= MIN_NEURON_STAKE_E8S {
+        let liquid = STATE.with(|state| state.borrow().liquid_balance.clone());
+        // Failures leave the funds liquid; the next round tries again.
+        let _ = stake(gov, available, &liquid, now_ns).await;
+    }
+}
+
+pub(crate) async fn read_liquid_balance() -> Result<Nat, String> {
+    let (balance,): (Nat,) = call(
+        current_config().ledger_canister,
+        "icrc1_balance_of",
+        (Account {
+            owner: ic_cdk::id(),
+            subaccount: None,
+        },),
+    )
+    .await
+    .map_err(|e| format!("Failed to get vault balance: {:?}", e))?;
+    Ok(balance)
+}
+
+async fn staking_round() -> Result<StakingBuckets, String> {
+    let _guard = StakingGuard::acquire()?;
+    let liquid = read_liquid_balance().await?;
+    run_staking(&NnsGovernance::from_config(), liquid, ic_cdk::api::time()).await;
+    Ok(STATE.with(|state| buckets_in(&state.borrow())))
+}
+
+pub(crate) fn start_staking() {
+    ic_cdk_timers::set_timer_interval(STAKING_INTERVAL, || {
+        ic_cdk::spawn(async {
+            if let Err(e) = staking_round().await {
+                ic_cdk::println!("staking round: {}", e);
+            }
+        })
+    });
+}
+
+/// Stakes `amount`, or everything above the liquid buffer, into a new neuron.
+#[ic_cdk::update]
+async fn stake_vault_funds(amount: Option<Nat>) -> Result<StakedNeuron, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let _guard = StakingGuard::acquire()?;
+    let liquid = read_liquid_balance().await?;
+    let now = ic_cdk::api::time();
+    STATE.with(|state| record_liquid_in(&mut state.borrow_mut(), &liquid, now));
+    let amount =
+        amount.unwrap_or_else(|| STATE.with(|state| stakeable_in(&state.borrow(), &liquid)));
+    let id = stake(&NnsGovernance::from_config(), amount, &liquid, now).await?;
+    neuron(id)
+}
+
+#[ic_cdk::update]
+async fn start_dissolving_neuron(id: u64) -> Result<StakedNeuron, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let neuron_id = neuron(id)?
+        .neuron_id
+        .ok_or_else(|| format!("Neuron {} has not been claimed yet", id))?;
+    let gov = NnsGovernance::from_config();
+    gov.start_dissolving(neuron_id).await?;
+    refresh_neuron(&gov, id, ic_cdk::api::time()).await?;
+    neuron(id)
+}
+
+#[ic_cdk::update]
+async fn increase_neuron_dissolve_delay(
+    id: u64,
+    additional_secs: u32,
+) -> Result<StakedNeuron, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let neuron_id = neuron(id)?
+        .neuron_id
+        .ok_or_else(|| format!("Neuron {} has not been claimed yet", id))?;
+    let gov = NnsGovernance::from_config();
+    gov.increase_dissolve_delay(neuron_id, additional_secs)
+        .await?;
+    refresh_neuron(&gov, id, ic_cdk::api::time()).await?;
+    neuron(id)
+}
+
+/// Runs a staking round now instead of waiting for the hourly timer.
+#[ic_cdk::update]
+async fn refresh_staking() -> Result<StakingBuckets, String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    staking_round().await
+}
+
+#[ic_cdk::query]
+fn get_staking_buckets() -> StakingBuckets {
+    STATE.with(|state| buckets_in(&state.borrow()))
+}
+
+#[ic_cdk::query]
+fn list_neurons() -> Vec<StakedNeuron> {
+    STATE.with(|state| state.borrow().neurons.values().cloned().collect())
+}
+
+#[ic_cdk::query]
+fn get_staking_config() -> StakingConfig {
+    STATE.with(|state| state.borrow().staking_config.clone())
+}
+
+#[ic_cdk::update]
+fn set_staking_config(config: StakingConfig) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    config.validate()?;
+    STATE.with(|state| state.borrow_mut().staking_config = config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::reset_state;
+    use crate::{accounting, StableState};
+    use candid::Int;
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    #[derive(Default)]
+    struct MockGovernance {
+        neurons: RefCell<BTreeMap<u64, NeuronSnapshot>>,
+        fail_claims: Cell<bool>,
+        /// Staking transfers land but the call reports a failure.
+        drop_funding_replies: Cell<bool>,
+        refuse_funding: Cell<bool>,
+        rewards: RefCell<Nat>,
+        reward_sweeps: RefCell<BTreeSet<u64>>,
+    }
+
+    impl MockGovernance {
+        fn with_neuron(&self, memo: u64, f: impl FnOnce(&mut NeuronSnapshot)) {
+            f(self.neurons.borrow_mut().get_mut(&(memo + 100)).unwrap());
+        }
+    }
+
+    impl Governance for MockGovernance {
+        async fn fund_neuron(
+            &self,
+            memo: u64,
+            amount: &Nat,
+            _funding: &NeuronFunding,
+        ) -> Result<Result<(), String>, String> {
+            if self.refuse_funding.get() {
+                return Ok(Err("insufficient funds".to_string()));
+            }
+            // A repeated transfer is deduplicated by the ledger.
+            self.neurons
+                .borrow_mut()
+                .entry(memo + 100)
+                .or_insert_with(|| NeuronSnapshot {
+                    stake: amount.clone(),
+                    maturity: Nat::from(0u64),
+                    dissolve_delay_secs: 0,
+                    dissolves_at_secs: None,
+                });
+            if self.drop_funding_replies.get() {
+                return Err("call timed out".to_string());
+            }
+            Ok(Ok(()))
+        }
+
+        async fn claim_neuron(&self, memo: u64) -> Result<u64, String> {
+            if self.fail_claims.get() {
+                return Err("governance unavailable".to_string());
+            }
+            Ok(memo + 100)
+        }
+
+        async fn increase_dissolve_delay(
+            &self,
+            neuron_id: u64,
+            additional_secs: u32,
+        ) -> Result<(), String> {
+            self.with_neuron(neuron_id - 100, |neuron| {
+                neuron.dissolve_delay_secs += additional_secs as u64
+            });
+            Ok(())
+        }
+
+        async fn start_dissolving(&self, neuron_id: u64) -> Result<(), String> {
+            self.with_neuron(neuron_id - 100, |neuron| {
+                neuron.dissolves_at_secs = Some(neuron.dissolve_delay_secs);
+                neuron.dissolve_delay_secs = 0;
+            });
+            Ok(())
+        }
+
+        async fn neuron(&self, neuron_id: u64) -> Result<NeuronSnapshot, String> {
+            Ok(self.neurons.borrow()[&neuron_id].clone())
+        }
+
+        async fn disburse(&self, neuron_id: u64) -> Result<(), String> {
+            self.with_neuron(neuron_id - 100, |neuron| neuron.stake = Nat::from(0u64));
+            Ok(())
+        }
+
+        async fn disburse_maturity(&self, neuron_id: u64) -> Result<Nat, String> {
+            let mut maturity = Nat::from(0u64);
+            self.with_neuron(neuron_id - 100, |neuron| {
+                maturity = std::mem::replace(&mut neuron.maturity, Nat::from(0u64))
+            });
+            Ok(maturity)
+        }
+
+        async fn rewards_balance(&self) -> Result<Nat, String> {
+            Ok(self.rewards.borrow().clone())
+        }
+
+        async fn sweep_rewards(&self, sweep: &RewardSweep) -> Result<Result<(), String>, String> {
+            if self.reward_sweeps.borrow_mut().insert(sweep.created_at_ns) {
+                *self.rewards.borrow_mut() -= sweep.amount.clone() + sweep.fee.clone();
+            }
+            Ok(Ok(()))
+        }
+    }
+
+    #[test]
+    fn staking_locks_neurons_and_disburses_rewards_and_stake() {
+        use futures::executor::block_on;
+        use NeuronStatus;
+
+        reset_state();
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.total_deposited = Nat::from(1_000_000_000u64);
+            state.share_supply = Nat::from(1_000_000_000u128 * 10u128.pow(10));
+            state.treasury_balance = Nat::from(30_000u64);
+            state.books.liquid = Nat::from(1_000_030_000u64);
+        });
+        let gov = MockGovernance::default();
+        let liquid = Nat::from(1_000_030_000u64);
+        let delay = STATE.with(|state| state.borrow().staking_config.dissolve_delay_secs);
+
+        // 20% of the 10 ICP under management stays liquid, as does the treasury.
+        let stakeable = STATE.with(|state| stakeable_in(&state.borrow(), &liquid));
+        assert_eq!(stakeable, Nat::from(799_990_000u64));
+        assert!(block_on(stake(&gov, Nat::from(99_999_999u64), &liquid, 0)).is_err());
+        assert!(block_on(stake(&gov, Nat::from(800_000_000u64), &liquid, 0)).is_err());
+
+        let id = block_on(stake(&gov, Nat::from(500_000_000u64), &liquid, 0)).unwrap();
+        let neuron = STATE.with(|state| state.borrow().neurons[&id].clone());
+        assert_eq!(neuron.neuron_id, Some(100));
+        assert_eq!(neuron.status, NeuronStatus::Locked);
+        assert_eq!(neuron.dissolve_delay_secs, delay);
+        assert_eq!(
+            STATE.with(|state| state.borrow().liquid_balance.clone()),
+            Nat::from(500_020_000u64)
+        );
+
+        // A neuron that cannot be claimed yet is picked up by the next round.
+        gov.fail_claims.set(true);
+        let liquid = Nat::from(500_020_000u64);
+        let second = block_on(stake(&gov, Nat::from(100_000_000u64), &liquid, 0)).unwrap();
+        let neuron = STATE.with(|state| state.borrow().neurons[&second].clone());
+        assert_eq!(neuron.status, NeuronStatus::Funding);
+        assert!(neuron.last_error.is_some());
+        gov.fail_claims.set(false);
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let neuron = STATE.with(|state| state.borrow().neurons[&second].clone());
+        assert_eq!(neuron.status, NeuronStatus::Locked);
+        assert_eq!(neuron.last_error, None);
+
+        // Maturity below the threshold accrues; above it, it is disbursed.
+        gov.with_neuron(id, |neuron| neuron.maturity = Nat::from(50_000_000u64));
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.staked, Nat::from(600_000_000u64));
+        assert_eq!(buckets.maturity, Nat::from(50_000_000u64));
+        gov.with_neuron(id, |neuron| neuron.maturity = Nat::from(150_000_000u64));
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.maturity, Nat::from(0u64));
+        assert_eq!(buckets.maturity_disbursing, Nat::from(150_000_000u64));
+        assert_eq!(buckets.rewards_disbursed, Nat::from(0u64));
+
+        // Rewards are booked once they land, modulated, and are swept in.
+        *gov.rewards.borrow_mut() = Nat::from(142_510_000u64);
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.rewards_disbursed, Nat::from(142_500_000u64));
+        assert_eq!(*gov.rewards.borrow(), Nat::from(0u64));
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.rewards_disbursed, Nat::from(142_500_000u64));
+
+        // Dissolving neurons move buckets and are disbursed once dissolved.
+        block_on(Governance::start_dissolving(&gov, 100)).unwrap();
+        block_on(run_staking(&gov, Nat::from(400_010_000u64), 0));
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.staked, Nat::from(100_000_000u64));
+        assert_eq!(buckets.dissolving, Nat::from(500_000_000u64));
+        let dissolved_at_ns = (delay + 1) * 1_000_000_000;
+        block_on(run_staking(
+            &gov,
+            Nat::from(400_010_000u64),
+            dissolved_at_ns,
+        ));
+        let neuron = STATE.with(|state| state.borrow().neurons[&id].clone());
+        assert_eq!(neuron.status, NeuronStatus::Disbursed);
+        assert_eq!(neuron.stake, Nat::from(0u64));
+        // Disbursements long past the payout delay are no longer pending.
+        let buckets = STATE.with(|state| buckets_in(&state.borrow()));
+        assert_eq!(buckets.maturity_disbursing, Nat::from(0u64));
+        assert_eq!(
+            STATE.with(|state| neuron_stake_in(&state.borrow())),
+            Nat::from(100_000_000u64)
+        );
+
+        // The treasury covers the fees of both staking transfers and the
+        // disbursal, so holders are not charged for them.
+        let report = STATE.with(|state| accounting::reconciliation_in(&state.borrow()));
+        assert_eq!(report.holders, Nat::from(1_142_500_000u64));
+        assert_eq!(report.treasury, Nat::from(0u64));
+        assert_eq!(report.booked_liquid, Nat::from(1_042_500_000u64));
+        assert_eq!(report.booked_staked, Nat::from(100_000_000u64));
+        assert_eq!(report.fee_expense, Nat::from(30_000u64));
+        assert_eq!(report.imbalance, Int::from(0));
+
+        let restored: State = STATE
+            .with(|state| StableState::from(&*state.borrow()))
+            .into();
+        assert_eq!(restored.neurons.len(), 2);
+        assert_eq!(restored.rewards_disbursed, Nat::from(142_500_000u64));
+    }
+
+    #[test]
+    fn unconfirmed_staking_transfers_are_resent_and_booked_once() {
+        use futures::executor::block_on;
+        use NeuronStatus;
+
+        reset_state();
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.total_deposited = Nat::from(1_000_000_000u64);
+            state.books.liquid = Nat::from(1_000_000_000u64);
+        });
+        let gov = MockGovernance::default();
+        let liquid = Nat::from(1_000_000_000u64);
+
+        // A refused transfer sent nothing and leaves no neuron behind.
+        gov.refuse_funding.set(true);
+        assert!(block_on(stake(&gov, Nat::from(500_000_000u64), &liquid, 0)).is_err());
+        assert!(STATE.with(|state| state.borrow().neurons.is_empty()));
+        gov.refuse_funding.set(false);
+
+        // A transfer whose reply was lost stays unbooked and reserved.
+        gov.drop_funding_replies.set(true);
+        let id = block_on(stake(&gov, Nat::from(500_000_000u64), &liquid, 7)).unwrap();
+        let neuron = STATE.with(|state| state.borrow().neurons[&id].clone());
+        assert_eq!(neuron.status, NeuronStatus::Funding);
+        assert!(neuron.last_error.is_some());
+        assert_eq!(neuron.funding.map(|funding| funding.created_at_ns), Some(7));
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.books.staked, Nat::from(0u64));
+            assert_eq!(buckets_in(&state).staked, Nat::from(0u64));
+            assert_eq!(stakeable_in(&state, &liquid), Nat::from(299_980_000u64));
+        });
+
+        // The next round resends it, is deduplicated and books the stake once.
+        gov.drop_funding_replies.set(false);
+        block_on(run_staking(&gov, Nat::from(499_990_000u64), 0));
+        let neuron = STATE.with(|state| state.borrow().neurons[&id].clone());
+        assert_eq!(neuron.status, NeuronStatus::Locked);
+        assert!(neuron.funding.is_none());
+        assert_eq!(neuron.last_error, None);
+        let report = STATE.with(|state| accounting::reconciliation_in(&state.borrow()));
+        assert_eq!(report.booked_staked, Nat::from(500_000_000u64));
+        assert_eq!(report.imbalance, Int::from(0));
+    }
+
+    #[test]
+    fn staking_runs_one_at_a_time() {
+        let guard = StakingGuard::acquire().unwrap();
+        assert!(StakingGuard::acquire().is_err());
+        drop(guard);
+        assert!(StakingGuard::acquire().is_ok());
+    }
+}
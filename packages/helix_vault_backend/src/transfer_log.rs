@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::{State, STATE};
+use candid::{CandidType, Nat};
+use ic_principal::Principal;
+use serde::{Deserialize, Serialize};
+
+const MAX_PAGE_SIZE: u64 = 100;
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BridgeOperationKind {
+    Deposit,
+    Mint,
+    Refund,
+    BurnVerified,
+    Unlock,
+    /// Accrued protocol fees sent to the treasury account.
+    TreasurySweep,
+}
+
+/// A single entry of the append-only bridge log. Amounts are in ledger units
+/// (8 decimals); `evm_amount` carries the 18 decimal hstICP amount when the
+/// operation touched the EVM side.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BridgeOperation {
+    pub id: u64,
+    pub kind: BridgeOperationKind,
+    pub principal: Principal,
+    pub eth_address: Option<String>,
+    pub amount: Nat,
+    pub evm_amount: Option<String>,
+    pub fee: Nat,
+    pub ledger_block_index: Option<Nat>,
+    pub evm_tx_hash: Option<String>,
+    pub related_operation: Option<u64>,
+    pub timestamp_ns: u64,
+    /// EVM chain the operation touched; `None` for entries written before
+    /// multi-chain support, which all used the default chain.
+    pub chain_id: Option<u64>,
+}
+
+/// Fields supplied by the caller when appending to the log; `id` and
+/// `timestamp_ns` are assigned by `record_operation`.
+pub struct NewOperation {
+    pub kind: BridgeOperationKind,
+    pub principal: Principal,
+    pub eth_address: Option<String>,
+    pub amount: Nat,
+    pub evm_amount: Option<String>,
+    pub fee: Nat,
+    pub ledger_block_index: Option<Nat>,
+    pub evm_tx_hash: Option<String>,
+    pub related_operation: Option<u64>,
+    pub chain_id: Option<u64>,
+}
+
+pub fn record_operation(op: NewOperation) -> u64 {
+    let timestamp_ns = ic_cdk::api::time();
+    STATE.with(|state| record_operation_in(&mut state.borrow_mut(), op, timestamp_ns))
+}
+
+pub(crate) fn record_operation_in(state: &mut State, op: NewOperation, timestamp_ns: u64) -> u64 {
+    let id = state.operations.len() as u64;
+    state.operations.push(BridgeOperation {
+        id,
+        kind: op.kind,
+        principal: op.principal,
+        eth_address: op.eth_address.map(|address| address.to_lowercase()),
+        amount: op.amount,
+        evm_amount: op.evm_amount,
+        fee: op.fee,
+        ledger_block_index: op.ledger_block_index,
+        evm_tx_hash: op.evm_tx_hash.map(|hash| hash.to_lowercase()),
+        related_operation: op.related_operation,
+        timestamp_ns,
+        chain_id: op.chain_id,
+    });
+    id
+}
+
+// Newest entries first, matching how the frontend renders history.
+fn page<F>(offset: u64, limit: u64, filter: F) -> Vec<BridgeOperation>
+where
+    F: Fn(&BridgeOperation) -> bool,
+{
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    STATE.with(|state| {
+        state
+            .borrow()
+            .operations
+            .iter()
+            .rev()
+            .filter(|op| filter(op))
+            .skip(offset as usize)
+            .take(limit)
+            .cloned()
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_operation(id: u64) -> Option<BridgeOperation> {
+    STATE.with(|state| state.borrow().operations.get(id as usize).cloned())
+}
+
+#[ic_cdk::query]
+pub(crate) fn get_operations_by_principal(
+    principal: Principal,
+    offset: u64,
+    limit: u64,
+) -> Vec<BridgeOperation> {
+    page(offset, limit, |op| op.principal == principal)
+}
+
+#[ic_cdk::query]
+fn get_operations_by_eth_address(
+    eth_address: String,
+    offset: u64,
+    limit: u64,
+) -> Vec<BridgeOperation> {
+    let eth_address = eth_address.trim().to_lowercase();
+    page(offset, limit, |op| {
+        op.eth_address.as_deref() == Some(eth_address.as_str())
+    })
+}
+
+#[ic_cdk::query]
+pub(crate) fn get_operations_by_tx_hash(tx_hash: String) -> Vec<BridgeOperation> {
+    let tx_hash = tx_hash.trim().to_lowercase();
+    page(0, MAX_PAGE_SIZE, |op| {
+        op.evm_tx_hash.as_deref() == Some(tx_hash.as_str())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::reset_state;
+
+    #[test]
+    fn transfer_log_pages_newest_first_and_filters() {
+        reset_state();
+        let user = Principal::anonymous();
+        let other = Principal::management_canister();
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            for (principal, kind, tx_hash) in [
+                (user, BridgeOperationKind::Deposit, None),
+                (user, BridgeOperationKind::Mint, Some("0xABC")),
+                (other, BridgeOperationKind::Deposit, None),
+            ] {
+                record_operation_in(
+                    &mut state,
+                    NewOperation {
+                        kind,
+                        principal,
+                        eth_address: None,
+                        amount: Nat::from(10u64),
+                        evm_amount: None,
+                        fee: Nat::from(0u64),
+                        ledger_block_index: None,
+                        evm_tx_hash: tx_hash.map(str::to_string),
+                        related_operation: None,
+                        chain_id: None,
+                    },
+                    0,
+                );
+            }
+        });
+
+        let page = get_operations_by_principal(user, 0, 10);
+        assert_eq!(page.iter().map(|op| op.id).collect::<Vec<_>>(), vec![1, 0]);
+
+        let page = get_operations_by_principal(user, 1, 10);
+        assert_eq!(page.iter().map(|op| op.id).collect::<Vec<_>>(), vec![0]);
+
+        let by_hash = get_operations_by_tx_hash("0xabc".to_string());
+        assert_eq!(by_hash.len(), 1);
+        assert_eq!(by_hash[0].kind, BridgeOperationKind::Mint);
+    }
+}
@@ -0,0 +1,949 @@
+// SPDX-License-Identifier: MIT
+// Copyright (c) 2025 Helix Labs
+
+use crate::accounting::book_outflow_in;
+use crate::exchange_rate::{burn_shares_in, icp_for_in};
+use crate::fees::{accrue, fee_for_in, untreasured_in, FeeKind};
+use crate::pause::ensure_unlocks_open;
+use crate::rate_limits::{self, BridgeDirection};
+use crate::staking::{estimate_available_in, read_liquid_balance, record_liquid_in};
+use crate::transfer_log::{record_operation, BridgeOperationKind, NewOperation};
+use crate::{
+    apply_burn_unlock, credit_shares_in, current_config, ensure_controller,
+    refresh_transfer_fee_if_stale, resolve_chain, BalanceHold, BurnValidation, State,
+    DEFAULT_TRANSFER_FEE, STATE,
+};
+use candid::{CandidType, Nat};
+use ic_cdk::call;
+use ic_principal::Principal;
+use icrc_ledger_types::icrc1::account::Account;
+use icrc_ledger_types::icrc1::transfer::{Memo, TransferArg, TransferError};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::str::FromStr;
+use std::time::Duration;
+
+const UNLOCK_INTERVAL: Duration = Duration::from_secs(60);
+const BURN_PAGE_SIZE: u64 = 100;
+// Log index of tickets for plain burns, which have no `BurnToICP` event.
+pub(crate) const PLAIN_BURN_LOG_INDEX: u64 = u64::MAX;
+
+thread_local! {
+    // Unlocks currently being paid by an in-flight call; see `DepositGuard`.
+    static IN_FLIGHT: RefCell<BTreeSet<u64>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Mirror of the EVM backend's `DetectedBurn`.
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub(crate) struct DetectedBurn {
+    pub id: u64,
+    pub chain_id: u64,
+    pub contract: String,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub from: String,
+    pub recipient: Option<Principal>,
+    pub recipient_subaccount: Option<Vec<u8>>,
+    pub amount_wei: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq)]
+pub enum BurnUnlockStatus {
+    /// Waiting to be paid; retried while the bridge is paused, rate limited
+    /// or the ledger is unavailable.
+    Pending,
+    /// Waiting for liquid ICP. Tickets are served in id order as neurons
+    /// dissolve or deposits refill the buffer.
+    Queued,
+    Paid,
+    /// Cannot be paid as-is, e.g. the event named no valid account.
+    NeedsReview,
+}
+
+/// Payout of one burn and its ticket in the withdrawal queue. Amounts are in
+/// ledger units.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BurnUnlock {
+    pub id: u64,
+    pub chain_id: u64,
+    pub tx_hash: String,
+    pub log_index: u64,
+    pub burner: String,
+    /// Account named by the event, paid no matter who reported the burn.
+    pub recipient: Option<Account>,
+    /// Whose recorded balance a plain burn is charged to; the recipient's
+    /// owner otherwise.
+    pub claimant: Option<Principal>,
+    /// Owed to the recipient after the withdraw fee. The ledger fee of the
+    /// payout comes out of it.
+    pub amount: Nat,
+    pub evm_amount: String,
+    /// Withdraw fee kept for the treasury, fixed with `amount`.
+    pub protocol_fee: Option<Nat>,
+    pub status: BurnUnlockStatus,
+    pub created_at_ns: u64,
+    pub updated_at_ns: u64,
+    pub transfer_created_at_ns: Option<u64>,
+    /// Ledger fee of the payout transfer, fixed with `transfer_created_at_ns`
+    /// so that every retry repeats the same transfer.
+    pub ledger_fee: Option<Nat>,
+    pub ledger_block_index: Option<Nat>,
+    pub last_error: Option<String>,
+    /// Estimated time a `Queued` ticket can be paid; `None` while only new
+    /// deposits can cover it.
+    pub available_at_ns: Option<u64>,
+    /// hstICP wei of a plain burn already taken off the claimant's recorded
+    /// balance, so paying it charges nothing more.
+    pub held_shares: Option<Nat>,
+}
+
+struct UnlockGuard(u64);
+
+impl UnlockGuard {
+    fn acquire(id: u64) -> Option<Self> {
+        IN_FLIGHT.with(|set| set.borrow_mut().insert(id).then_some(UnlockGuard(id)))
+    }
+}
+
+impl Drop for UnlockGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT.with(|set| {
+            set.borrow_mut().remove(&self.0);
+        });
+    }
+}
+
+fn burn_unlock(id: u64) -> Result<BurnUnlock, String> {
+    STATE
+        .with(|state| state.borrow().burn_unlocks.get(&id).cloned())
+        .ok_or_else(|| format!("Burn unlock {} not found", id))
+}
+
+fn update_burn_unlock(id: u64, f: impl FnOnce(&mut BurnUnlock)) {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        if let Some(unlock) = state.borrow_mut().burn_unlocks.get_mut(&id) {
+            f(unlock);
+            unlock.updated_at_ns = now;
+        }
+    });
+}
+
+/// Turns a detected burn into a payout. Burns of contracts the vault does not
+/// trust on that chain are ignored; burns already known return their
+/// existing id.
+pub(crate) fn ingest_burn_in(state: &mut State, burn: DetectedBurn, now_ns: u64) -> Option<u64> {
+    let trusted = state
+        .chain_contracts
+        .get(&burn.chain_id)
+        .is_some_and(|contract| contract.eq_ignore_ascii_case(&burn.contract));
+    if !trusted {
+        return None;
+    }
+    let tx_hash = burn.tx_hash.to_lowercase();
+    if let Some(existing) = state.burn_unlocks.values().find(|unlock| {
+        unlock.chain_id == burn.chain_id
+            && unlock.tx_hash == tx_hash
+            && unlock.log_index == burn.log_index
+    }) {
+        return Some(existing.id);
+    }
+
+    let subaccount = match burn.recipient_subaccount.as_deref() {
+        None => Some(None),
+        Some(bytes) => <[u8; 32]>::try_from(bytes).ok().map(Some),
+    };
+    let recipient = burn
+        .recipient
+        .zip(subaccount)
+        .map(|(owner, subaccount)| Account { owner, subaccount });
+    // The payout is fixed at the exchange rate when the burn is first seen.
+    let amount_wei = Nat::from_str(&burn.amount_wei).unwrap_or_default();
+    let (amount, protocol_fee) = payout_in(state, &icp_for_in(state, &amount_wei));
+    let problem = if recipient.is_none() {
+        Some("Burn event does not name a valid account".to_string())
+    } else if amount <= state.transfer_fee {
+        Some(format!(
+            "Burn amount {} does not cover the ledger fee",
+            burn.amount_wei
+        ))
+    } else {
+        None
+    };
+
+    let id = state.next_burn_unlock_id;
+    state.next_burn_unlock_id += 1;
+    state.burn_unlocks.insert(
+        id,
+        BurnUnlock {
+            id,
+            chain_id: burn.chain_id,
+            tx_hash,
+            log_index: burn.log_index,
+            burner: burn.from.to_lowercase(),
+            recipient,
+            claimant: None,
+            amount,
+            evm_amount: burn.amount_wei,
+            protocol_fee: Some(protocol_fee),
+            status: if problem.is_some() {
+                BurnUnlockStatus::NeedsReview
+            } else {
+                BurnUnlockStatus::Pending
+            },
+            created_at_ns: now_ns,
+            updated_at_ns: now_ns,
+            transfer_created_at_ns: None,
+            ledger_fee: None,
+            ledger_block_index: None,
+            last_error: problem,
+            available_at_ns: None,
+            held_shares: None,
+        },
+    );
+    Some(id)
+}
+
+/// Splits `value` ledger units into the payout and the withdraw fee.
+fn payout_in(state: &State, value: &Nat) -> (Nat, Nat) {
+    let protocol_fee = fee_for_in(state, FeeKind::Withdraw, value);
+    (value.clone() - protocol_fee.clone(), protocol_fee)
+}
+
+fn protocol_fee(unlock: &BurnUnlock) -> Nat {
+    unlock
+        .protocol_fee
+        .clone()
+        .unwrap_or_else(|| Nat::from(0u64))
+}
+
+fn unsettled(unlock: &BurnUnlock) -> bool {
+    matches!(
+        unlock.status,
+        BurnUnlockStatus::Pending | BurnUnlockStatus::Queued
+    )
+}
+
+/// Ledger units leaving the ICP under management for `unlock`: the payout,
+/// its ledger fee included, and the withdraw fee moved to the treasury.
+fn demand_in(unlock: &BurnUnlock) -> Nat {
+    unlock.amount.clone() + protocol_fee(unlock)
+}
+
+/// Ledger units, fees included, owed to tickets not paid yet.
+pub(crate) fn unsettled_demand_in(state: &State) -> Nat {
+    state
+        .burn_unlocks
+        .values()
+        .filter(|unlock| unsettled(unlock))
+        .fold(Nat::from(0u64), |sum, unlock| sum + demand_in(unlock))
+}
+
+/// Walks the unsettled tickets in id order against `liquid`, less the
+/// treasury. Those covered are `Pending` and returned; the rest are `Queued`
+/// with an estimate of when staked ICP covers them, so no ticket is paid
+/// ahead of an earlier one.
+pub(crate) fn schedule_queue_in(state: &mut State, liquid: &Nat, now_ns: u64) -> BTreeSet<u64> {
+    let liquid = &untreasured_in(state, liquid);
+    let mut demand = Nat::from(0u64);
+    let mut payable = BTreeSet::new();
+    let mut queued = Vec::new();
+    for unlock in state
+        .burn_unlocks
+        .values()
+        .filter(|unlock| unsettled(unlock))
+    {
+        demand += demand_in(unlock);
+        if demand <= *liquid {
+            payable.insert(unlock.id);
+        } else {
+            let shortfall = demand.clone() - liquid.clone();
+            queued.push((unlock.id, estimate_available_in(state, &shortfall, now_ns)));
+        }
+    }
+
+    for id in &payable {
+        if let Some(unlock) = state.burn_unlocks.get_mut(id) {
+            if unlock.status == BurnUnlockStatus::Queued {
+                unlock.status = BurnUnlockStatus::Pending;
+                unlock.available_at_ns = None;
+                unlock.updated_at_ns = now_ns;
+            }
+        }
+    }
+    for (id, available_at_ns) in queued {
+        if let Some(unlock) = state.burn_unlocks.get_mut(&id) {
+            if unlock.status != BurnUnlockStatus::Queued
+                || unlock.available_at_ns != available_at_ns
+            {
+                unlock.status = BurnUnlockStatus::Queued;
+                unlock.available_at_ns = available_at_ns;
+                unlock.updated_at_ns = now_ns;
+            }
+        }
+    }
+    payable
+}
+
+fn queued_message(unlock: &BurnUnlock) -> String {
+    match unlock.available_at_ns {
+        Some(at) => format!(
+            "Burn queued as withdrawal ticket {}, estimated to be payable at {} ns.",
+            unlock.id, at
+        ),
+        None => format!(
+            "Burn queued as withdrawal ticket {} until deposits refill the vault.",
+            unlock.id
+        ),
+    }
+}
+
+/// Books a verified plain burn worth `value` ledger units that the vault
+/// cannot pay yet as a withdrawal ticket, paid to `to`. The ticket keeps what
+/// `hold` took off the caller's recorded balance.
+pub(crate) fn queue_plain_burn(
+    to: Account,
+    chain_id: u64,
+    tx_hash: String,
+    proof: &BurnValidation,
+    hold: BalanceHold,
+    value: Nat,
+    liquid: &Nat,
+) -> String {
+    let now = ic_cdk::api::time();
+    let (caller, held_shares) = (hold.owner, hold.shares.clone());
+    hold.spend();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        let (amount, protocol_fee) = payout_in(&state, &value);
+        let id = state.next_burn_unlock_id;
+        state.next_burn_unlock_id += 1;
+        state.burn_unlocks.insert(
+            id,
+            BurnUnlock {
+                id,
+                chain_id,
+                tx_hash: tx_hash.clone(),
+                log_index: PLAIN_BURN_LOG_INDEX,
+                burner: proof.from.to_lowercase(),
+                recipient: Some(to),
+                claimant: Some(caller),
+                amount,
+                evm_amount: proof.amount_wei.clone(),
+                protocol_fee: Some(protocol_fee),
+                status: BurnUnlockStatus::Queued,
+                created_at_ns: now,
+                updated_at_ns: now,
+                transfer_created_at_ns: None,
+                ledger_fee: None,
+                ledger_block_index: None,
+                last_error: None,
+                available_at_ns: None,
+                held_shares: Some(held_shares),
+            },
+        );
+        state.used_tx_hashes.insert(tx_hash);
+        schedule_queue_in(&mut state, liquid, now);
+        queued_message(&state.burn_unlocks[&id])
+    })
+}
+
+fn ingest_burns(burns: Vec<DetectedBurn>) -> Vec<u64> {
+    let now = ic_cdk::api::time();
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        burns
+            .into_iter()
+            .filter_map(|burn| ingest_burn_in(&mut state, burn, now))
+            .collect()
+    })
+}
+
+/// Pulls burns the EVM backend detected since the last sync.
+async fn sync_burns() -> Result<(), String> {
+    let evm_backend = current_config().evm_backend_canister;
+    loop {
+        let start_id = STATE.with(|state| state.borrow().burn_cursor);
+        let (burns,): (Vec<DetectedBurn>,) = call(
+            evm_backend,
+            "list_detected_burns",
+            (start_id, BURN_PAGE_SIZE),
+        )
+        .await
+        .map_err(|e| format!("Call failed: {:?}", e))?;
+        let Some(last_id) = burns.last().map(|burn| burn.id) else {
+            return Ok(());
+        };
+        let page_len = burns.len() as u64;
+        ingest_burns(burns);
+        STATE.with(|state| state.borrow_mut().burn_cursor = last_id + 1);
+        if page_len < BURN_PAGE_SIZE {
+            return Ok(());
+        }
+    }
+}
+
+/// Gives a dropped ticket's held shares back to its claimant.
+fn release_held_shares_in(state: &mut State, id: u64) {
+    let Some(unlock) = state.burn_unlocks.get_mut(&id) else {
+        return;
+    };
+    let (Some(claimant), Some(shares)) = (unlock.claimant, unlock.held_shares.take()) else {
+        return;
+    };
+    credit_shares_in(state, &claimant, &shares);
+}
+
+fn record_unlock_error(id: u64, err: String) -> String {
+    update_burn_unlock(id, |unlock| unlock.last_error = Some(err.clone()));
+    err
+}
+
+// `created_at_time`, `memo` and the fee are fixed per unlock so a retried
+// payout is deduplicated by the ledger, as with refunds.
+// Returns `None` when the ticket has to wait in the queue.
+async fn pay_burn_unlock(id: u64) -> Result<Option<Nat>, String> {
+    let _guard =
+        UnlockGuard::acquire(id).ok_or_else(|| format!("Burn unlock {} is in progress", id))?;
+    let unlock = burn_unlock(id)?;
+    if !unsettled(&unlock) {
+        return Err(format!(
+            "Burn unlock {} is in state {:?}",
+            id, unlock.status
+        ));
+    }
+    let recipient = unlock
+        .recipient
+        .ok_or_else(|| format!("Burn unlock {} has no recipient", id))?;
+    ensure_unlocks_open().map_err(|e| record_unlock_error(id, e))?;
+
+    let fee = match unlock.ledger_fee.clone() {
+        Some(fee) => fee,
+        None => refresh_transfer_fee_if_stale(&Nat::from(DEFAULT_TRANSFER_FEE))
+            .await
+            .map_err(|e| record_unlock_error(id, e))?,
+    };
+    let protocol_fee = protocol_fee(&unlock);
+    let total_amount = unlock.amount.clone() + protocol_fee.clone();
+    let total_deposited = STATE.with(|state| state.borrow().total_deposited.clone());
+    let problem = if unlock.amount <= fee {
+        Some("Unlock does not cover the ledger fee")
+    } else if total_deposited < total_amount {
+        Some("Unlock exceeds the vault's recorded deposits")
+    } else {
+        None
+    };
+    // Dropped before any transfer, so a plain burn's held shares go back.
+    if let Some(problem) = problem {
+        let err = problem.to_string();
+        update_burn_unlock(id, |unlock| {
+            unlock.status = BurnUnlockStatus::NeedsReview;
+            unlock.last_error = Some(err.clone());
+        });
+        STATE.with(|state| release_held_shares_in(&mut state.borrow_mut(), id));
+        return Err(err);
+    }
+
+    // A transfer already attempted is retried as is: it may have landed, and
+    // the ledger deduplicates it.
+    if unlock.transfer_created_at_ns.is_none() {
+        let liquid = read_liquid_balance()
+            .await
+            .map_err(|e| record_unlock_error(id, e))?;
+        let now = ic_cdk::api::time();
+        let payable = STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            record_liquid_in(&mut state, &liquid, now);
+            schedule_queue_in(&mut state, &liquid, now)
+        });
+        if !payable.contains(&id) {
+            return Ok(None);
+        }
+    }
+
+    let charged = unlock.claimant.unwrap_or(recipient.owner);
+    let usage_id = rate_limits::reserve(BridgeDirection::Unlock, charged, &unlock.amount)
+        .map_err(|e| record_unlock_error(id, e))?;
+    let created_at = unlock
+        .transfer_created_at_ns
+        .unwrap_or_else(ic_cdk::api::time);
+    update_burn_unlock(id, |unlock| {
+        unlock.transfer_created_at_ns = Some(created_at);
+        unlock.ledger_fee = Some(fee.clone());
+    });
+
+    let payout = unlock.amount.clone() - fee.clone();
+    let transfer_arg = TransferArg {
+        from_subaccount: None,
+        to: recipient,
+        amount: payout.clone(),
+        fee: Some(fee.clone()),
+        memo: Some(Memo::from(id)),
+        created_at_time: Some(created_at),
+    };
+    let block_index = match call::<(TransferArg,), (Result<Nat, TransferError>,)>(
+        current_config().ledger_canister,
+        "icrc1_transfer",
+        (transfer_arg,),
+    )
+    .await
+    {
+        Ok((Ok(block_index),)) => block_index,
+        Ok((Err(TransferError::Duplicate { duplicate_of }),)) => duplicate_of,
+        // An earlier attempt may have landed outside the deduplication window.
+        Ok((Err(TransferError::TooOld),)) => {
+            rate_limits::release(usage_id);
+            let err = "Unlock transfer failed: deduplication window expired".to_string();
+            update_burn_unlock(id, |unlock| {
+                unlock.status = BurnUnlockStatus::NeedsReview;
+                unlock.last_error = Some(err.clone());
+            });
+            return Err(err);
+        }
+        Ok((Err(e),)) => {
+            rate_limits::release(usage_id);
+            return Err(record_unlock_error(
+                id,
+                format!("Unlock transfer failed: {:?}", e),
+            ));
+        }
+        Err(e) => {
+            rate_limits::release(usage_id);
+            return Err(record_unlock_error(
+                id,
+                format!("Unlock transfer call failed: {:?}", e),
+            ));
+        }
+    };
+
+    let burned = Nat::from_str(&unlock.evm_amount).unwrap_or_default();
+    let charge = match unlock.held_shares {
+        Some(_) => Nat::from(0u64),
+        None => burned.clone(),
+    };
+    apply_burn_unlock(&charged, &charge, &total_amount).map_err(|e| record_unlock_error(id, e))?;
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        burn_shares_in(&mut state, &burned);
+        book_outflow_in(&mut state, &unlock.amount);
+    });
+    accrue(FeeKind::Withdraw, &protocol_fee);
+    let burn_operation_id = record_operation(NewOperation {
+        kind: BridgeOperationKind::BurnVerified,
+        principal: charged,
+        eth_address: Some(unlock.burner.clone()),
+        amount: unlock.amount.clone(),
+        evm_amount: Some(unlock.evm_amount.clone()),
+        fee: protocol_fee,
+        ledger_block_index: None,
+        evm_tx_hash: Some(unlock.tx_hash.clone()),
+        related_operation: None,
+        chain_id: Some(unlock.chain_id),
+    });
+    record_operation(NewOperation {
+        kind: BridgeOperationKind::Unlock,
+        principal: charged,
+        eth_address: Some(unlock.burner),
+        amount: payout,
+        evm_amount: Some(unlock.evm_amount),
+        fee,
+        ledger_block_index: Some(block_index.clone()),
+        evm_tx_hash: Some(unlock.tx_hash),
+        related_operation: Some(burn_operation_id),
+        chain_id: Some(unlock.chain_id),
+    });
+    update_burn_unlock(id, |unlock| {
+        unlock.status = BurnUnlockStatus::Paid;
+        unlock.ledger_block_index = Some(block_index.clone());
+        unlock.last_error = None;
+        unlock.available_at_ns = None;
+    });
+    Ok(Some(block_index))
+}
+
+async fn pay_pending_unlocks() {
+    let ids: Vec<u64> = STATE.with(|state| {
+        state
+            .borrow()
+            .burn_unlocks
+            .values()
+            .filter(|unlock| unsettled(unlock))
+            .map(|unlock| unlock.id)
+            .collect()
+    });
+    for id in ids {
+        if IN_FLIGHT.with(|set| set.borrow().contains(&id)) {
+            continue;
+        }
+        match pay_burn_unlock(id).await {
+            Ok(Some(_)) => {}
+            // Later tickets wait behind this one, paid or not.
+            Ok(None) => return,
+            Err(e) => {
+                ic_cdk::println!("burn unlock {}: {}", id, e);
+                return;
+            }
+        }
+    }
+}
+
+async fn process_unlocks() {
+    if let Err(e) = sync_burns().await {
+        ic_cdk::println!("sync burns: {}", e);
+    }
+    pay_pending_unlocks().await;
+}
+
+pub(crate) fn start_unlock_processor() {
+    ic_cdk_timers::set_timer_interval(UNLOCK_INTERVAL, || ic_cdk::spawn(process_unlocks()));
+}
+
+/// Unlocks a `burnToICP` burn proven through `unlock_icrc1`. It is booked
+/// like one found by the scanner, so either route pays it only once.
+pub(crate) async fn unlock_proven_burn(
+    chain_id: u64,
+    tx_hash: String,
+    proof: BurnValidation,
+) -> Result<String, String> {
+    let burn = DetectedBurn {
+        id: 0,
+        chain_id,
+        contract: proof.contract,
+        tx_hash,
+        log_index: proof
+            .log_index
+            .ok_or("Burn proof does not reference a BurnToICP event")?,
+        from: proof.from,
+        recipient: proof.recipient,
+        recipient_subaccount: proof.recipient_subaccount,
+        amount_wei: proof.amount_wei,
+    };
+    let id = ingest_burns(vec![burn])
+        .pop()
+        .ok_or("Burn proof references unexpected contract.")?;
+    let unlock = burn_unlock(id)?;
+    match unlock.status {
+        BurnUnlockStatus::Pending | BurnUnlockStatus::Queued => {}
+        BurnUnlockStatus::Paid => {
+            return Err("Transaction hash has already been used for unlock.".to_string())
+        }
+        BurnUnlockStatus::NeedsReview => {
+            return Err(unlock
+                .last_error
+                .unwrap_or_else(|| format!("Burn unlock {} needs review", id)))
+        }
+    }
+    if pay_burn_unlock(id).await?.is_none() {
+        return Ok(queued_message(&burn_unlock(id)?));
+    }
+    let recipient = unlock
+        .recipient
+        .map(|account| account.to_string())
+        .unwrap_or_default();
+    Ok(format!(
+        "Unlocked {} nICP to {}. Verified burn on Ethereum.",
+        unlock.amount, recipient
+    ))
+}
+
+/// Unlocks the `BurnToICP` events of `tx_hash` right away instead of waiting
+/// for the scanner. Anyone may submit it; payouts always go to the account
+/// named in the event.
+#[ic_cdk::update]
+async fn claim_burn(tx_hash: String, chain_id: Option<u64>) -> Result<Vec<BurnUnlock>, String> {
+    let (chain_id, _) = resolve_chain(chain_id)?;
+    let (result,): (Result<Vec<DetectedBurn>, String>,) = call(
+        current_config().evm_backend_canister,
+        "scan_burn_transaction",
+        (chain_id, tx_hash.clone()),
+    )
+    .await
+    .map_err(|e| format!("Call to EVM RPC failed: {:?}", e))?;
+
+    let ids = ingest_burns(result?);
+    if ids.is_empty() {
+        return Err(format!(
+            "Transaction {} burns no hstICP of the vault's contract",
+            tx_hash
+        ));
+    }
+    for id in &ids {
+        if unsettled(&burn_unlock(*id)?) {
+            if let Err(e) = pay_burn_unlock(*id).await {
+                ic_cdk::println!("burn unlock {}: {}", id, e);
+            }
+        }
+    }
+    ids.into_iter().map(burn_unlock).collect()
+}
+
+#[ic_cdk::update]
+async fn process_burn_unlocks() -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    process_unlocks().await;
+    Ok(())
+}
+
+/// Names the recipient of a burn whose event carried no valid account and
+/// queues it for payment.
+#[ic_cdk::update]
+fn set_burn_unlock_recipient(id: u64, recipient: Account) -> Result<(), String> {
+    ensure_controller(ic_cdk::api::caller())?;
+    let unlock = burn_unlock(id)?;
+    if unlock.status != BurnUnlockStatus::NeedsReview || unlock.recipient.is_some() {
+        return Err(format!("Burn unlock {} already has a recipient", id));
+    }
+    update_burn_unlock(id, |unlock| {
+        unlock.recipient = Some(recipient);
+        unlock.status = BurnUnlockStatus::Pending;
+        unlock.last_error = None;
+    });
+    Ok(())
+}
+
+/// Pays withdrawal ticket `id` now if the queue has reached it. Anyone may
+/// call it; the payout goes to the ticket's recipient.
+#[ic_cdk::update]
+async fn claim_withdrawal(id: u64) -> Result<BurnUnlock, String> {
+    let unlock = burn_unlock(id)?;
+    if !unsettled(&unlock) {
+        return Err(format!(
+            "Withdrawal ticket {} is in state {:?}",
+            id, unlock.status
+        ));
+    }
+    pay_burn_unlock(id).await?;
+    burn_unlock(id)
+}
+
+/// Tickets paid to, or charged to, `user`.
+#[ic_cdk::query]
+fn get_withdrawal_tickets(user: Principal) -> Vec<BurnUnlock> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .burn_unlocks
+            .values()
+            .filter(|unlock| {
+                unlock.claimant == Some(user)
+                    || unlock
+                        .recipient
+                        .is_some_and(|account| account.owner == user)
+            })
+            .cloned()
+            .collect()
+    })
+}
+
+/// Tickets waiting for liquid ICP, in the order they will be paid.
+#[ic_cdk::query]
+fn get_withdrawal_queue() -> Vec<BurnUnlock> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .burn_unlocks
+            .values()
+            .filter(|unlock| unlock.status == BurnUnlockStatus::Queued)
+            .cloned()
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_burn_unlock(id: u64) -> Option<BurnUnlock> {
+    STATE.with(|state| state.borrow().burn_unlocks.get(&id).cloned())
+}
+
+#[ic_cdk::query]
+fn get_unsettled_burn_unlocks() -> Vec<BurnUnlock> {
+    STATE.with(|state| {
+        state
+            .borrow()
+            .burn_unlocks
+            .values()
+            .filter(|unlock| unlock.status != BurnUnlockStatus::Paid)
+            .cloned()
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_burn_unlocks_by_tx_hash(tx_hash: String) -> Vec<BurnUnlock> {
+    let tx_hash = tx_hash.trim().to_lowercase();
+    STATE.with(|state| {
+        state
+            .borrow()
+            .burn_unlocks
+            .values()
+            .filter(|unlock| unlock.tx_hash == tx_hash)
+            .cloned()
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{detected_burn, reset_state, staked_neuron};
+    use crate::{staking, StableState, CONTRACT_ADDRESS};
+
+    #[test]
+    fn burn_events_are_ingested_once_and_unlocked_from_the_vault() {
+        reset_state();
+        let user = Principal::management_canister();
+        let burn_event = |id: u64, contract: &str, log_index: u64, amount_wei: &str| {
+            let mut event = detected_burn(id, 0);
+            event.contract = contract.to_string();
+            event.tx_hash = "0xABC".to_string();
+            event.log_index = log_index;
+            event.recipient = Some(user);
+            event.recipient_subaccount = Some(vec![7; 32]);
+            event.amount_wei = amount_wei.to_string();
+            event
+        };
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            let first = burn_event(0, CONTRACT_ADDRESS, 0, "1000000000000");
+            assert_eq!(ingest_burn_in(&mut state, first.clone(), 0), Some(0));
+            assert_eq!(ingest_burn_in(&mut state, first, 0), Some(0));
+            assert_eq!(
+                ingest_burn_in(&mut state, burn_event(1, "0xdead", 1, "1000000000000"), 0),
+                None
+            );
+            assert_eq!(
+                ingest_burn_in(&mut state, burn_event(2, CONTRACT_ADDRESS, 2, "5"), 0),
+                Some(1)
+            );
+            let mut truncated = burn_event(3, CONTRACT_ADDRESS, 3, "1000000000000");
+            truncated.recipient_subaccount = Some(vec![7; 5]);
+            assert_eq!(ingest_burn_in(&mut state, truncated, 0), Some(2));
+            assert_eq!(state.burn_unlocks.len(), 3);
+            assert_eq!(
+                state.burn_unlocks[&0].recipient,
+                Some(Account {
+                    owner: user,
+                    subaccount: Some([7; 32]),
+                })
+            );
+            assert_eq!(state.burn_unlocks[&0].amount, Nat::from(100u64));
+            assert_eq!(state.burn_unlocks[&0].tx_hash, "0xabc");
+            for id in [1, 2] {
+                assert_eq!(
+                    state.burn_unlocks[&id].status,
+                    BurnUnlockStatus::NeedsReview
+                );
+            }
+            state.total_deposited = Nat::from(1_000u64);
+            state.user_balances.insert(user, Nat::from(40u64));
+        });
+
+        let scale = 10u128.pow(10);
+        apply_burn_unlock(&user, &Nat::from(100 * scale), &Nat::from(110u64)).unwrap();
+        STATE.with(|state| {
+            let state = state.borrow();
+            assert_eq!(state.total_deposited, Nat::from(890u64));
+            assert_eq!(state.user_balances[&user], Nat::from(0u64));
+        });
+        assert!(apply_burn_unlock(&user, &Nat::from(900 * scale), &Nat::from(900u64)).is_err());
+    }
+
+    #[test]
+    fn withdrawal_queue_is_served_in_order_from_liquid_and_staked_icp() {
+        use staking::NeuronStatus;
+        use BurnUnlockStatus;
+
+        let mut state = State::new();
+        state.transfer_fee = Nat::from(10u64);
+        state.total_deposited = Nat::from(10_000u64);
+        for (id, units) in [(0u64, 1_000u128), (1, 2_000), (2, 500)] {
+            ingest_burn_in(&mut state, detected_burn(id, units), 0);
+        }
+        state
+            .neurons
+            .insert(0, staked_neuron(0, 3_000, NeuronStatus::Locked));
+        state
+            .neurons
+            .insert(1, staked_neuron(1, 1_000, NeuronStatus::Dissolving));
+        assert_eq!(unsettled_demand_in(&state), Nat::from(3_500u64));
+
+        // Only the first ticket fits; the next two wait for the locked neuron,
+        // the dissolving one alone being too small.
+        let liquid = Nat::from(1_500u64);
+        let now_ns = 10_000_000_000;
+        let payable = schedule_queue_in(&mut state, &liquid, now_ns);
+        assert_eq!(payable.into_iter().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(state.burn_unlocks[&0].status, BurnUnlockStatus::Pending);
+        for id in [1, 2] {
+            assert_eq!(state.burn_unlocks[&id].status, BurnUnlockStatus::Queued);
+            assert_eq!(
+                state.burn_unlocks[&id].available_at_ns,
+                Some(110_000_000_000)
+            );
+        }
+        assert_eq!(staking::neurons_to_dissolve_in(&state, &liquid), vec![0]);
+        assert_eq!(staking::stakeable_in(&state, &liquid), Nat::from(0u64));
+        assert_eq!(
+            staking::estimate_available_in(&state, &Nat::from(900u64), now_ns),
+            Some(50_000_000_000)
+        );
+        assert_eq!(
+            staking::estimate_available_in(&state, &Nat::from(4_001u64), now_ns),
+            None
+        );
+
+        let payable = schedule_queue_in(&mut state, &Nat::from(3_500u64), now_ns);
+        assert_eq!(payable.len(), 3);
+        assert!(state.burn_unlocks.values().all(|unlock| {
+            unlock.status == BurnUnlockStatus::Pending && unlock.available_at_ns.is_none()
+        }));
+        assert!(staking::neurons_to_dissolve_in(&state, &Nat::from(3_500u64)).is_empty());
+    }
+
+    #[test]
+    fn attempted_unlocks_keep_their_ledger_fee() {
+        let mut state = State::new();
+        state.transfer_fee = Nat::from(10u64);
+        ingest_burn_in(&mut state, detected_burn(0, 1_000), 0);
+        assert_eq!(unsettled_demand_in(&state), Nat::from(1_000u64));
+
+        // The ledger fee comes out of the payout, so fixing it at the first
+        // attempt leaves what the ticket is owed unchanged.
+        if let Some(unlock) = state.burn_unlocks.get_mut(&0) {
+            unlock.transfer_created_at_ns = Some(1);
+            unlock.ledger_fee = Some(Nat::from(10u64));
+        }
+        state.transfer_fee = Nat::from(20u64);
+        assert_eq!(unsettled_demand_in(&state), Nat::from(1_000u64));
+
+        let restored: State = StableState::from(&state).into();
+        assert_eq!(restored.burn_unlocks[&0].ledger_fee, Some(Nat::from(10u64)));
+    }
+
+    #[test]
+    fn dropped_plain_burn_tickets_give_held_shares_back() {
+        let user = Principal::from_slice(&[5]);
+        let unit = crate::exchange_rate::WEI_PER_UNIT;
+        let mut state = State::new();
+        state.user_balances.insert(user, Nat::from(1u64));
+        ingest_burn_in(&mut state, detected_burn(0, 5), 0);
+        if let Some(unlock) = state.burn_unlocks.get_mut(&0) {
+            unlock.log_index = PLAIN_BURN_LOG_INDEX;
+            unlock.claimant = Some(user);
+            unlock.held_shares = Some(Nat::from(5 * unit));
+        }
+        let restored: State = StableState::from(&state).into();
+        assert_eq!(
+            restored.burn_unlocks[&0].held_shares,
+            Some(Nat::from(5 * unit))
+        );
+
+        release_held_shares_in(&mut state, 0);
+        assert_eq!(state.user_balances[&user], Nat::from(6u64));
+        assert_eq!(state.burn_unlocks[&0].held_shares, None);
+        // Released once only.
+        release_held_shares_in(&mut state, 0);
+        assert_eq!(state.user_balances[&user], Nat::from(6u64));
+    }
+}
@@ -10,6 +10,10 @@ import {ERC20Permit} from "@openzeppelin/contracts/token/ERC20/extensions/ERC20P
 contract HelixStakedICP is ERC20, ERC20Burnable, AccessControl, ERC20Permit {
     bytes32 public constant MINTER_ROLE = keccak256("MINTER_ROLE");
 
+    /// Emitted by `burnToICP`; `icpPrincipal` and `subaccount` name the ICRC-1
+    /// account the vault unlocks to. A zero subaccount is the default one.
+    event BurnToICP(address indexed from, bytes icpPrincipal, bytes32 subaccount, uint256 amount);
+
     constructor(address defaultAdmin, address minter)
         ERC20("Helix Staked ICP", "hstICP")
         ERC20Permit("Helix Staked ICP")
@@ -21,4 +25,12 @@ contract HelixStakedICP is ERC20, ERC20Burnable, AccessControl, ERC20Permit {
     function mint(address to, uint256 amount) public onlyRole(MINTER_ROLE) {
         _mint(to, amount);
     }
-}
\ No newline at end of file
+
+    /// Burns `amount` from the caller to be unlocked as nICP to the account
+    /// (`icpPrincipal`, `subaccount`) on the Internet Computer.
+    function burnToICP(bytes calldata icpPrincipal, bytes32 subaccount, uint256 amount) public {
+        require(icpPrincipal.length > 0 && icpPrincipal.length <= 29, "invalid principal");
+        _burn(_msgSender(), amount);
+        emit BurnToICP(_msgSender(), icpPrincipal, subaccount, amount);
+    }
+}